@@ -1,11 +1,17 @@
+use crate::tools::align_pair::{AlignmentType, PairwiseScoring};
 use crate::tools::filter_by_length::{LengthRange, LengthThreshold, Tolerance};
 use crate::tools::get_consensus::AmbiguityMode;
 use crate::tools::get_mindist_seq::ComputeMode;
-use crate::utils::translate::TranslationOptions;
+use crate::tools::replace_ambiguities::ResolutionStrategy;
+use crate::tools::reverse_translate::GapOutput;
+use crate::utils::codon_tables::GeneticCode;
+use crate::utils::fasta_utils::DuplicateIdPolicy;
+use crate::utils::translate::{StopRecoding, TranslationOptions};
 use clap::builder::styling;
 use clap::{Args, Parser, Subcommand};
+use std::fmt;
 use std::path::PathBuf;
-use crate::tools::get_mindist_seq::ComputeMode;
+use std::str::FromStr;
 
 const STYLES: styling::Styles = styling::Styles::styled()
     .header(styling::AnsiColor::Green.on_default().bold())
@@ -16,11 +22,37 @@ const STYLES: styling::Styles = styling::Styles::styled()
 #[derive(Parser)]
 #[command(name = "pipeline-utils-rs")]
 #[command(about = "A collection of CLI utilities for manipulating sequencing files.")]
+#[command(
+    after_help = "FASTA/FASTQ inputs and output files can be given as \"-\" to read from stdin or write to stdout. Paths ending in .gz are read and written gzip-compressed."
+)]
 #[command(styles = STYLES)]
 #[command(version)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// Write a JSON summary of the run (tool, version, input/output files, record counts and
+    /// duration) to this file
+    #[arg(long, global = true)]
+    pub summary_json: Option<PathBuf>,
+    /// Fail instead of overwriting output files that already exist
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_clobber: bool,
+    /// What to do when the input repeats a sequence ID, within one file or across input files
+    #[arg(long, global = true, value_enum, default_value = "error")]
+    pub on_duplicate: DuplicateIdPolicy,
+    /// Log and skip input records that can't be parsed (such as a header that isn't valid UTF-8,
+    /// or a FASTQ quality string of the wrong length) instead of failing
+    #[arg(long, global = true, default_value_t = false)]
+    pub skip_invalid: bool,
+    /// Log "Processed N/total records" every N records in the long-running tools (translate,
+    /// replace-ambiguities, orient-reads, map-coordinates and guess-frame), for logs without a
+    /// terminal
+    #[arg(long, global = true, value_name = "N")]
+    pub progress_interval: Option<usize>,
+    /// Wrap the sequences in FASTA output at N characters per line. 0 (the default) writes each
+    /// sequence on a single line
+    #[arg(long, global = true, value_name = "N", default_value_t = 0)]
+    pub line_width: usize,
 }
 
 #[derive(clap::ValueEnum, Clone)]
@@ -29,6 +61,35 @@ pub enum SequenceOutputType {
     NT,
 }
 
+/// The --reading-frame of translate: a fixed 0-based frame, or "auto" to pick the forward frame
+/// with the fewest internal stops for each sequence (the same as --auto-frame).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadingFrameArg {
+    Fixed(usize),
+    Auto,
+}
+
+impl FromStr for ReadingFrameArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ReadingFrameArg::Auto),
+            "0" | "1" | "2" => Ok(ReadingFrameArg::Fixed(s.parse().unwrap())),
+            _ => Err(format!("expected 0, 1, 2 or auto, not {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ReadingFrameArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadingFrameArg::Fixed(reading_frame) => write!(f, "{}", reading_frame),
+            ReadingFrameArg::Auto => write!(f, "auto"),
+        }
+    }
+}
+
 #[derive(Args)]
 #[group(required = false, multiple = true)]
 pub struct TranslateCliOptions {
@@ -40,8 +101,13 @@ pub struct TranslateCliOptions {
     pub incomplete_aa: char,
     #[arg(long, default_value_t = TranslationOptions::default().frameshift_aa as char)]
     pub frameshift_aa: char,
-    #[arg(long, default_value_t = TranslationOptions::default().reading_frame)]
-    pub reading_frame: usize,
+    /// The 0-based reading frame (0, 1 or 2), or "auto" to pick the forward frame with the fewest
+    /// internal stops for each sequence, as --auto-frame does
+    #[arg(
+        long,
+        default_value_t = ReadingFrameArg::Fixed(TranslationOptions::default().reading_frame)
+    )]
+    pub reading_frame: ReadingFrameArg,
     #[arg(long, default_value_t = TranslationOptions::default().allow_ambiguities)]
     pub allow_ambiguities: bool,
     #[arg(long, default_value_t = TranslationOptions::default().strip_gaps)]
@@ -50,6 +116,56 @@ pub struct TranslateCliOptions {
     pub ignore_gap_codons: bool,
     #[arg(long, default_value_t = TranslationOptions::default().drop_incomplete_codons)]
     pub drop_incomplete_codons: bool,
+    /// Write a gap for a trailing incomplete codon, keeping the output in step with a
+    /// same-length alignment. Takes precedence over --drop-incomplete-codons and --incomplete-aa.
+    #[arg(long, default_value_t = TranslationOptions::default().incomplete_as_gap)]
+    pub incomplete_as_gap: bool,
+    /// The first codon to translate (1-based, inclusive, counted from the reading frame)
+    #[arg(long)]
+    pub codon_start: Option<usize>,
+    /// The last codon to translate (1-based, inclusive, counted from the reading frame)
+    #[arg(long)]
+    pub codon_end: Option<usize>,
+    /// Heuristic: where stop/unknown codons suggest a single- or two-base insertion has broken
+    /// the reading frame, skip those bases (marked with --frameshift-aa) and carry on in the
+    /// recovered frame
+    #[arg(long, default_value_t = TranslationOptions::default().recover_frame)]
+    pub recover_frame: bool,
+    /// Fail, naming the record and codon, instead of writing --unknown-aa for a codon that can't
+    /// be translated
+    #[arg(long, default_value_t = TranslationOptions::default().strict_translation)]
+    pub strict_translation: bool,
+    /// Fail, instead of warning, when --strip-gaps removes gaps that were not codon-aligned and
+    /// leaves a length that is not a multiple of 3
+    #[arg(long, default_value_t = TranslationOptions::default().strict_frame)]
+    pub strict_frame: bool,
+    /// The NCBI genetic code (translation table) to translate with: 1 (standard), 2 (vertebrate
+    /// mitochondrial), 4 (mold mitochondrial), 5 (invertebrate mitochondrial) or 11 (bacterial)
+    #[arg(long, default_value_t = TranslationOptions::default().genetic_code)]
+    pub genetic_code: GeneticCode,
+    /// Translate the reverse complement of each sequence (e.g. a minus-strand CDS). The reading
+    /// frame and codon range are counted from the start of the reverse complement
+    #[arg(
+        long,
+        default_value_t = TranslationOptions::default().reverse_complement,
+        conflicts_with_all = ["frame_reference", "concatenate_frames", "six_frame"]
+    )]
+    pub reverse_complement: bool,
+    /// Translate a codon with ambiguity codes that isn't in the built-in ambiguity tables if every
+    /// codon it could stand for codes for the same amino acid (e.g. ATY as I)
+    #[arg(long, default_value_t = TranslationOptions::default().exhaustive_ambiguities)]
+    pub exhaustive_ambiguities: bool,
+    /// Translate TGA as selenocysteine (sec), TAG as pyrrolysine (pyl) or both, wherever the
+    /// genetic code makes them stops. Every such codon is recoded, as the recoding signals
+    /// aren't looked for
+    #[arg(long)]
+    pub recode_stops: Option<StopRecoding>,
+    /// The character for selenocysteine with --recode-stops
+    #[arg(long, default_value_t = TranslationOptions::default().sec_aa as char)]
+    pub sec_aa: char,
+    /// The character for pyrrolysine with --recode-stops
+    #[arg(long, default_value_t = TranslationOptions::default().pyl_aa as char)]
+    pub pyl_aa: char,
 }
 
 impl From<&TranslateCliOptions> for TranslationOptions {
@@ -59,11 +175,28 @@ impl From<&TranslateCliOptions> for TranslationOptions {
             stop_aa: opts.stop_aa as u8,
             incomplete_aa: opts.incomplete_aa as u8,
             frameshift_aa: opts.frameshift_aa as u8,
-            reading_frame: opts.reading_frame,
+            // With "auto" the frame is picked per sequence, overriding this.
+            reading_frame: match opts.reading_frame {
+                ReadingFrameArg::Fixed(reading_frame) => reading_frame,
+                ReadingFrameArg::Auto => TranslationOptions::default().reading_frame,
+            },
             allow_ambiguities: opts.allow_ambiguities,
             strip_gaps: opts.strip_gaps,
             ignore_gap_codons: opts.ignore_gap_codons,
             drop_incomplete_codons: opts.drop_incomplete_codons,
+            incomplete_as_gap: opts.incomplete_as_gap,
+            codon_start: opts.codon_start,
+            codon_end: opts.codon_end,
+            recover_frame: opts.recover_frame,
+            strict_translation: opts.strict_translation,
+            strict_frame: opts.strict_frame,
+            genetic_code: opts.genetic_code,
+            custom_codon_table: None,
+            reverse_complement: opts.reverse_complement,
+            exhaustive_ambiguities: opts.exhaustive_ambiguities,
+            recode_stops: opts.recode_stops,
+            sec_aa: opts.sec_aa as u8,
+            pyl_aa: opts.pyl_aa as u8,
         }
     }
 }
@@ -158,13 +291,92 @@ impl KmerFilterArgs {
     }
 }
 
+#[derive(Args)]
+#[group(required = true, multiple = true)]
+pub struct OrientOutputArgs {
+    /// FASTA file to write reads matching the reference on the forward strand to
+    #[arg(long)]
+    pub forward_output: Option<PathBuf>,
+    /// FASTA file to write reads matching the reverse strand to, reverse complemented so they
+    /// are in the forward orientation
+    #[arg(long)]
+    pub reverse_output: Option<PathBuf>,
+    /// FASTA file to write every matched read to, normalised to the forward orientation
+    #[arg(long)]
+    pub reverse_complement_output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct PairwiseScoringArgs {
+    /// Score for a matching pair of nucleotides (ignored for translated alignments)
+    #[arg(long, default_value_t = PairwiseScoring::default().match_score, allow_hyphen_values = true)]
+    pub match_score: i32,
+    /// Score for a mismatching pair of nucleotides (ignored for translated alignments)
+    #[arg(long, default_value_t = PairwiseScoring::default().mismatch_score, allow_hyphen_values = true)]
+    pub mismatch_score: i32,
+    /// Penalty for opening a gap (should be negative)
+    #[arg(long, default_value_t = PairwiseScoring::default().gap_open, allow_hyphen_values = true)]
+    pub gap_open: i32,
+    /// Penalty for extending a gap (should be negative)
+    #[arg(long, default_value_t = PairwiseScoring::default().gap_extend, allow_hyphen_values = true)]
+    pub gap_extend: i32,
+}
+
+impl From<&PairwiseScoringArgs> for PairwiseScoring {
+    fn from(opts: &PairwiseScoringArgs) -> Self {
+        PairwiseScoring {
+            match_score: opts.match_score,
+            mismatch_score: opts.mismatch_score,
+            gap_open: opts.gap_open,
+            gap_extend: opts.gap_extend,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Remove non-unique sequences. Output contains only unique sequences.
-    Collapse {
-        /// The input FASTA file containing uncollapsed sequences
+    /// Align two sequences against each other and print the alignment and its score.
+    /// Useful for debugging; the sequences can come from the same file or two different files.
+    AlignPair {
+        /// The FASTA file containing the first sequence (and the second, unless --second-input-file is given)
         #[arg(short = 'i', long)]
         input_file: PathBuf,
+        /// Optional FASTA file containing the second sequence
+        #[arg(long)]
+        second_input_file: Option<PathBuf>,
+        /// The ID of the first sequence
+        #[arg(short = 'a', long)]
+        first_id: String,
+        /// The ID of the second sequence
+        #[arg(short = 'b', long)]
+        second_id: String,
+        /// The type of alignment to perform
+        #[arg(short = 'm', long, default_value = "global")]
+        alignment_type: AlignmentType,
+        /// Translate both sequences and align the amino acids (scored with BLOSUM62)
+        #[arg(long, default_value_t = false)]
+        translated: bool,
+        /// The number of columns to print per line of the alignment
+        #[arg(short = 'w', long, default_value_t = 100)]
+        width: usize,
+        #[command(flatten)]
+        scoring: PairwiseScoringArgs,
+    },
+
+    /// Check that a FASTA file is a valid alignment: all sequences have the same length and
+    /// contain only alignment characters. Exits with an error if the check fails.
+    CheckAlignment {
+        /// The input aligned FASTA file
+        #[arg(short = 'i', long)]
+        input_file: PathBuf,
+    },
+
+    /// Remove non-unique sequences. Output contains only unique sequences.
+    Collapse {
+        /// The input FASTA file(s) containing uncollapsed sequences. Multiple files are read as if
+        /// they were concatenated
+        #[arg(short = 'i', long, required = true, num_args = 1..)]
+        input_file: Vec<PathBuf>,
         /// The output file to write collapsed sequences to
         #[arg(short = 'o', long)]
         output_file: PathBuf,
@@ -174,9 +386,82 @@ pub enum Commands {
         /// Collapse on sequence identity only, ignoring gap patterns
         #[arg(short = 's', long, default_value_t = false)]
         strip_gaps: bool,
+        /// Collapse sequences that only differ in their unresolved regions: runs of Ns (or Xs in
+        /// amino acid sequences) of any length are treated as the same, and an N (or X) matches
+        /// any base. Each collapsed sequence is written as its first member, unchanged
+        #[arg(long, default_value_t = false)]
+        mask_unresolved: bool,
         /// Prefix to prepend to new sequence names after collapsing
         #[arg(short = 'p', long)]
         sequence_prefix: String,
+        /// Expand the collapsed sequences again and fail if they do not match the input
+        #[arg(long, default_value_t = false)]
+        verify_roundtrip: bool,
+        /// Optional TSV file to write each output record's ID and sequence checksum (SHA-256) to
+        #[arg(long)]
+        checksum_output: Option<PathBuf>,
+    },
+
+    /// Report the percent identity of each sequence in an MSA to its consensus, counting only
+    /// columns where neither the sequence nor the consensus has a gap. Sequences far from the
+    /// consensus are likely contaminants or errors.
+    ConsensusIdentity {
+        /// Path to the input MSA FASTA file
+        #[arg(short = 'i', long)]
+        input_msa: PathBuf,
+        /// The TSV file to write each sequence's aligned columns, matches and percent identity to
+        #[arg(short = 'o', long)]
+        output_file: PathBuf,
+        /// Optional FASTA file containing a consensus aligned to the MSA. If not given, the
+        /// consensus is built from the MSA using --ambiguity-mode.
+        #[arg(short = 'c', long)]
+        consensus: Option<PathBuf>,
+        /// How to handle ambiguous characters when building the consensus
+        #[arg(
+            short = 'a',
+            long,
+            required_unless_present = "consensus",
+            conflicts_with = "consensus"
+        )]
+        ambiguity_mode: Option<AmbiguityMode>,
+        /// Seed for breaking ties in the consensus with the "random" ambiguity mode, as in
+        /// get-consensus. A random seed is picked (and logged) if none is given
+        #[arg(short = 's', long, conflicts_with = "consensus")]
+        seed: Option<u64>,
+    },
+
+    /// Count the records and bases in FASTA files, printed as a TSV. Records are read one at a
+    /// time, so this runs in constant memory on files of any size.
+    Count {
+        /// The FASTA file(s) to count. With more than one file, a total row is also printed
+        #[arg(short = 'i', long, required = true, num_args = 1..)]
+        input_file: Vec<PathBuf>,
+    },
+
+    /// Flag reads that look chimeric: each read is aligned to the reference, and a read whose
+    /// alignment has a large internal insertion or deletion is written to a separate file with
+    /// the position of the junction.
+    DeChimera {
+        /// The input FASTA file containing reads
+        #[arg(short = 'i', long)]
+        input_file: PathBuf,
+        /// FASTA file containing the single reference sequence
+        #[arg(short = 'r', long)]
+        reference: PathBuf,
+        /// The output FASTA file to write reads that don't look chimeric to
+        #[arg(short = 'o', long)]
+        output_file: PathBuf,
+        /// The FASTA file to write chimeric reads to, with the 1-based breakpoint and indel length
+        /// in each header
+        #[arg(short = 'c', long)]
+        chimera_output: PathBuf,
+        /// The smallest internal indel (in bases) that marks a read as chimeric
+        #[arg(long, default_value_t = 50)]
+        min_indel: usize,
+        /// Write each chimeric read as two records, {id}_1 and {id}_2, split at the breakpoint.
+        /// Bases inserted at the junction are left out of both.
+        #[arg(long, default_value_t = false)]
+        split: bool,
     },
 
     /// Re-introduce duplicate sequences removed by the collapse command.
@@ -193,6 +478,16 @@ pub enum Commands {
         /// Include sequences not present in the name mapping file
         #[arg(short = 'm', long, default_value_t = false)]
         include_missing: bool,
+        /// Optional TSV file to write each output record's ID and sequence checksum (SHA-256) to
+        #[arg(long)]
+        checksum_output: Option<PathBuf>,
+        /// The original FASTA file that was collapsed. Checks that every sequence in it is
+        /// expanded under the same name with an identical sequence (in any order), and fails
+        /// listing any that are missing or changed, or any expanded names it doesn't contain
+        /// (as with --include-missing). Sequences collapsed with --strip-gaps or
+        /// --mask-unresolved are reported as changed
+        #[arg(long)]
+        verify: Option<PathBuf>,
     },
 
     /// Filter sequences by length, keeping only those within a range around a center
@@ -200,9 +495,10 @@ pub enum Commands {
     /// default the center acts as a strict minimum; add --min-tolerance/--max-tolerance/
     /// --tolerance to accept a margin (absolute or percentage) below and/or above it.
     FilterByLength {
-        /// The input FASTA file containing unaligned sequences
-        #[arg(short = 'i', long)]
-        input_file: PathBuf,
+        /// The input FASTA file(s) containing unaligned sequences. Multiple files are read as if they
+        /// were concatenated
+        #[arg(short = 'i', long, required = true, num_args = 1..)]
+        input_file: Vec<PathBuf>,
         /// The output FASTA file to write sequences meeting the length threshold to
         #[arg(short = 'o', long)]
         output_file: PathBuf,
@@ -219,6 +515,9 @@ pub enum Commands {
         /// Exclude gaps from the sequence length
         #[arg(long, default_value_t = false)]
         exclude_gaps: bool,
+        /// Optional TSV file to write each output record's ID and sequence checksum (SHA-256) to
+        #[arg(long)]
+        checksum_output: Option<PathBuf>,
     },
 
     /// Filter sequences by whether they start and/or end with an allowed k-mer (e.g. a start
@@ -226,9 +525,9 @@ pub enum Commands {
     /// the comma-separated k-mers provided for that check; IUPAC ambiguity codes in either the
     /// k-mer or the sequence are matched according to what bases they represent.
     FilterByKmer {
-        /// The input FASTA file
-        #[arg(short = 'i', long)]
-        input_file: PathBuf,
+        /// The input FASTA file(s). Multiple files are read as if they were concatenated
+        #[arg(short = 'i', long, required = true, num_args = 1..)]
+        input_file: Vec<PathBuf>,
         /// The output FASTA file to write sequences that pass all requested checks to
         #[arg(short = 'o', long)]
         output_file: PathBuf,
@@ -240,13 +539,16 @@ pub enum Commands {
         rejected_seq_output: Option<PathBuf>,
         #[command(flatten)]
         kmer_filter: KmerFilterArgs,
+        /// Optional TSV file to write each output record's ID and sequence checksum (SHA-256) to
+        #[arg(long)]
+        checksum_output: Option<PathBuf>,
     },
 
     /// Filter sequences by name using regular expressions
     FilterByName {
-        /// The input FASTA file
-        #[arg(short = 'i', long)]
-        input_file: PathBuf,
+        /// The input FASTA file(s). Multiple files are read as if they were concatenated
+        #[arg(short = 'i', long, required = true, num_args = 1..)]
+        input_file: Vec<PathBuf>,
         /// The output FASTA file to write sequences that pass all requested checks to
         #[arg(short = 'o', long)]
         output_file: PathBuf,
@@ -259,6 +561,38 @@ pub enum Commands {
         /// Exclude sequences that match the regex. (default: false)
         #[arg[short='e', long, default_value_t = false]]
         exclude: bool,
+        /// Optional TSV file to write each output record's ID and sequence checksum (SHA-256) to
+        #[arg(long)]
+        checksum_output: Option<PathBuf>,
+    },
+
+    /// Find the open reading frames (from an ATG to a stop) in each sequence and write their
+    /// translations, or their nucleotides with --nucleotide. Each record is named by its sequence,
+    /// strand and frame, and its 1-based coordinates on the input (e.g. "contig1_f2_5-397"; the
+    /// start is after the end on the reverse strand). Only the longest ORF ending at each stop is
+    /// written.
+    FindOrfs {
+        /// The input FASTA file
+        #[arg(short = 'i', long)]
+        input_file: PathBuf,
+        /// The output FASTA file to write the ORFs to
+        #[arg(short = 'o', long)]
+        output_file: PathBuf,
+        /// Only write ORFs with more than this many codons, not counting the stop
+        #[arg(short = 'm', long, default_value_t = 30)]
+        min_length: usize,
+        /// Also search the three frames of the reverse complement
+        #[arg(long, default_value_t = false)]
+        both_strands: bool,
+        /// Also write ORFs that run off the end of the sequence without reaching a stop
+        #[arg(long, default_value_t = false)]
+        include_partial: bool,
+        /// Write the nucleotide sequence of each ORF instead of its translation
+        #[arg(long, default_value_t = false)]
+        nucleotide: bool,
+        /// The NCBI genetic code whose stop codons end an ORF (1, 2, 4, 5 or 11)
+        #[arg(long, default_value_t = GeneticCode::Standard)]
+        genetic_code: GeneticCode,
     },
 
     /// Extract a feature from a GenBank file and write it to a FASTA file.
@@ -272,23 +606,91 @@ pub enum Commands {
         /// The name of the sequence to extract
         #[arg(short = 'n', long)]
         seq_name: String,
+        /// Memory-map the GenBank file and parse only its first record. Worth it for large
+        /// multi-record files, where the default reads and parses every record.
+        #[arg(long, default_value_t = false)]
+        mmap: bool,
+        /// Also extract this many nucleotides upstream and downstream of the feature, clamped to
+        /// the ends of the record. For features on the reverse strand, upstream is after the
+        /// feature in the record's coordinates.
+        #[arg(long, default_value_t = 0)]
+        flank: usize,
     },
 
     /// Get the consensus sequence of a multiple sequence alignment.
     /// Produces a single sequence where each position is the most common nucleotide.
+    /// By default gaps are counted like nucleotides, so a column where a gap is most common is a
+    /// gap in the consensus, and is left out of the (degapped) output; see --gap-threshold.
     GetConsensus {
         /// Path to the input MSA FASTA file
         #[arg(short = 'i', long)]
         input_msa: PathBuf,
-        /// Path to write the consensus sequence as a FASTA file
-        #[arg(short = 'o', long)]
-        output_file: PathBuf,
-        /// Name for the consensus sequence in the FASTA file
+        /// Path to write the consensus sequence as a FASTA file, with its gaps removed
+        #[arg(short = 'o', long, required_unless_present = "both_outputs")]
+        output_file: Option<PathBuf>,
+        /// Instead of --output-file, write the degapped consensus to {prefix}.degapped.fa and the
+        /// consensus with the length of the alignment (keeping gaps) to {prefix}.aligned.fa
+        #[arg(long, conflicts_with = "output_file")]
+        both_outputs: Option<String>,
+        /// Name for the consensus sequence in the FASTA file (unused with --groups)
         #[arg(short = 'n', long)]
         consensus_name: String,
         /// How to handle ambiguous characters
         #[arg(short = 'a', long)]
         ambiguity_mode: AmbiguityMode,
+        /// Optional headerless TSV mapping sequence IDs to groups. One consensus is built per group
+        /// and named after it; sequences not in the file form an "other" group.
+        #[arg(short = 'g', long)]
+        groups: Option<PathBuf>,
+        /// Skip sequences that are not in the groups file instead of building an "other" consensus
+        #[arg(long, default_value_t = false, requires = "groups")]
+        skip_ungrouped: bool,
+        /// Optional FASTA file containing a single reference aligned to the MSA. Ties are broken
+        /// in favour of the reference base, falling back to the ambiguity mode where the
+        /// reference has a gap or ambiguity code, or does not match any of the tied bases.
+        #[arg(short = 'r', long)]
+        reference: Option<PathBuf>,
+        /// Optional path to write a TSV listing each alignment position where the consensus has
+        /// an ambiguity code, with the tied bases and their counts (only used with use-iupac)
+        #[arg(long)]
+        ambiguity_report: Option<PathBuf>,
+        /// Optional path to write the position frequency matrix: a TSV with a row for each
+        /// 1-based alignment position and the counts of A, C, G, T, N and - in that column
+        #[arg(long)]
+        pfm_output: Option<PathBuf>,
+        /// Weight each sequence by the number of reads collapsed into it, read from the count at
+        /// the end of IDs written by collapse (e.g. 42 for seq_0001_0042). Sequences whose ID
+        /// doesn't end in a count have a weight of 1
+        #[arg(long, default_value_t = false)]
+        weighted: bool,
+        /// Only call a base in columns where it is in more than this fraction of the sequences
+        /// (e.g. 0.5 for a majority-rule consensus). Other columns get the ambiguity code of the
+        /// most common bases that together exceed it with use-iupac, or N. 0 calls the most
+        /// common base in every column
+        #[arg(long, default_value_t = 0.0)]
+        min_frequency: f64,
+        /// Only call a base in columns where it makes up at least this fraction of the non-gap
+        /// characters, so columns covered by few sequences aren't called with false confidence.
+        /// Other columns get an ambiguity code or N as with --min-frequency. Columns where a gap
+        /// is most common are unaffected
+        #[arg(long, default_value_t = 0.0)]
+        min_fraction: f64,
+        /// Treat a column as a gap in the consensus only if more than this fraction of the
+        /// sequences have a gap there. Gaps in the other columns are dropped before counting, so
+        /// a base is called from the sequences that have one (e.g. 0.5 keeps a base wherever at
+        /// least half of the sequences have one)
+        #[arg(long)]
+        gap_threshold: Option<f64>,
+        /// Build the consensus only from the non-gap characters in each column, so only columns
+        /// that are all gaps are gaps in the consensus. The same as --gap-threshold 1
+        #[arg(long, default_value_t = false, conflicts_with = "gap_threshold")]
+        ignore_gaps: bool,
+        /// Seed for breaking ties with the "random" ambiguity mode. Each column is seeded from
+        /// this and its position, so the consensus is the same on every run with the same seed.
+        /// Without it, ties are broken differently on every run, and the random seed that was
+        /// picked is logged so the run can be repeated
+        #[arg(short = 's', long)]
+        seed: Option<u64>,
     },
 
     /// Get the "mindist" sequence from a Multiple Sequence Alignment.
@@ -309,6 +711,58 @@ pub enum Commands {
         compute_mode: ComputeMode,
     },
 
+    /// Report the reading frame most likely to be coding for each sequence: the frame (out of all
+    /// six, including the reverse complement) whose translation has the fewest stop codons.
+    GuessFrame {
+        /// The input FASTA file containing nucleotide sequences
+        #[arg(short = 'i', long)]
+        input_file: PathBuf,
+        /// The TSV file to write each sequence's best frame and stop codon count to
+        #[arg(short = 'o', long)]
+        output_file: PathBuf,
+    },
+
+    /// Map each position of every query to the coordinate of a reference (e.g. HXB2) by aligning
+    /// the query to it, and write a TSV with a row per query base. Bases inserted relative to the
+    /// reference are numbered after the position they follow (e.g. "6225+1"), and reference bases
+    /// deleted in the query have no query position.
+    MapCoordinates {
+        /// The input FASTA file containing the queries
+        #[arg(short = 'i', long)]
+        input_file: PathBuf,
+        /// FASTA file containing the single reference sequence
+        #[arg(short = 'r', long)]
+        reference: PathBuf,
+        /// The TSV file to write the query-to-reference positions to
+        #[arg(short = 'o', long)]
+        output_file: PathBuf,
+        /// The coordinate of the first base of the reference, if it is a region of a longer
+        /// reference (e.g. 6225 for HXB2 env)
+        #[arg(long, default_value_t = 1)]
+        reference_start: usize,
+        #[command(flatten)]
+        scoring: PairwiseScoringArgs,
+    },
+
+    /// Classify reads by the strand they match a reference on (by locally aligning each read
+    /// and its reverse complement to it), and write them out in the forward orientation.
+    OrientReads {
+        /// The input FASTA file containing reads
+        #[arg(short = 'i', long)]
+        input_file: PathBuf,
+        /// FASTA file containing the single reference sequence, in the forward orientation
+        #[arg(short = 'r', long)]
+        reference: PathBuf,
+        #[command(flatten)]
+        outputs: OrientOutputArgs,
+        /// Optional FASTA file to write reads that matched neither strand to
+        #[arg(long)]
+        unmatched_output: Option<PathBuf>,
+        /// The minimum local alignment score for a read to count as matching the reference
+        #[arg(long, default_value_t = 20, allow_hyphen_values = true)]
+        min_score: i32,
+    },
+
     #[cfg(feature = "process-miniprot")]
     /// Given PAF output from miniprot, return trimmed templates from a FASTA file.
     ProcessMiniprot {
@@ -326,7 +780,7 @@ pub enum Commands {
         output_dir: PathBuf,
     },
 
-    /// Convert IUPAC ambiguity codes to one of their possible nucleotides randomly.
+    /// Convert IUPAC ambiguity codes to one of their possible nucleotides.
     ReplaceAmbiguities {
         /// The input FASTA file
         #[arg(short = 'i', long)]
@@ -334,9 +788,31 @@ pub enum Commands {
         /// The output FASTA file to write the resolved sequences to
         #[arg(short = 'o', long)]
         output_file: PathBuf,
-        /// Seed for the random number generator
-        #[arg(short = 's', long, default_value_t = 42)]
-        seed: u64,
+        /// Seed for the random number generator. A random seed is picked (and logged, so the run
+        /// can be repeated) if none is given
+        #[arg(short = 's', long)]
+        seed: Option<u64>,
+        /// How to choose which of the possible nucleotides to use
+        #[arg(long, value_enum, default_value = "random")]
+        strategy: ResolutionStrategy,
+        /// An aligned reference or consensus sequence, used by the "reference" strategy
+        #[arg(short = 'r', long, required_if_eq("strategy", "reference"))]
+        reference: Option<PathBuf>,
+    },
+
+    /// Reverse complement sequences, optionally only those whose ID matches a pattern.
+    #[command(visible_alias = "reverse-complement")]
+    RevComp {
+        /// The input FASTA file
+        #[arg(short = 'i', long)]
+        input_file: PathBuf,
+        /// The output FASTA file to write the sequences to
+        #[arg(short = 'o', long)]
+        output_file: PathBuf,
+        /// Only reverse complement sequences whose ID matches this regex; the rest are written
+        /// unchanged
+        #[arg(long)]
+        only_matching: Option<String>,
     },
 
     /// Reverse translate a multiple sequence alignment.
@@ -352,6 +828,14 @@ pub enum Commands {
         /// Where to write the translated, aligned nt FASTA file
         #[arg(short, long)]
         output_file_path: PathBuf,
+        /// Produce a strict codon alignment: fail if the amino acid sequences are not aligned or
+        /// if any sequence cannot be reverse translated, so every output has the same length
+        #[arg(long, default_value_t = false)]
+        codon_alignment: bool,
+        /// How to write amino acid gaps in the nucleotide output: as a gap codon, as NNN, or
+        /// removed to give the ungapped coding sequence (not allowed with --codon-alignment)
+        #[arg(long, value_enum, default_value = "gap")]
+        gap_output: GapOutput,
     },
 
     /// Trims the nucleotides after the first stop codon in a sequence
@@ -371,12 +855,79 @@ pub enum Commands {
 
     /// Translate sequences from nucleotides into amino acids.
     Translate {
-        /// The FASTA file containing nucleotide sequences to translate
-        #[arg(short = 'i', long)]
-        input_file: PathBuf,
-        /// The output file to write the translated amino acid sequences to
+        /// The FASTA or FASTQ file(s) containing nucleotide sequences to translate. Multiple files
+        /// are read as if they were concatenated. Use "-" to read from stdin
+        #[arg(short = 'i', long, required = true, num_args = 1..)]
+        input_file: Vec<PathBuf>,
+        /// The output file to write the translated amino acid sequences to, or "-" for stdout
         #[arg(short = 'o', long)]
         output_file: PathBuf,
+        /// Annotate each output header with the reading frame and genetic code table used
+        /// (e.g. "frame=1 table=1")
+        #[arg(long, default_value_t = false)]
+        annotate_header: bool,
+        /// Infer each sequence's reading frame by aligning it to the single reference sequence in
+        /// this FASTA file. Sequences are degapped before aligning and translating.
+        #[arg(long)]
+        frame_reference: Option<PathBuf>,
+        /// The reading frame of the --frame-reference sequence (0-based)
+        #[arg(long, default_value_t = 0, requires = "frame_reference")]
+        reference_frame: usize,
+        /// Treat the input as a codon alignment: every sequence must have the same length (a
+        /// multiple of 3 after the reading frame), and each codon, including gap codons, is
+        /// translated to exactly one column so the output is a protein alignment
+        #[arg(long, default_value_t = false, conflicts_with = "frame_reference")]
+        msa_mode: bool,
+        /// Translate each sequence in the forward reading frame (overriding --reading-frame) with
+        /// the fewest internal stop codons, for inputs that are not consistently in frame
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["frame_reference", "msa_mode"]
+        )]
+        auto_frame: bool,
+        /// With --auto-frame (or --reading-frame auto), also write each translation to a file in
+        /// this directory named after the output file and the frame picked for it (0-based), e.g.
+        /// out.frame0.fa
+        #[arg(long)]
+        split_by_frame: Option<PathBuf>,
+        /// Translate all six reading frames (forward 1-3, then reverse 1-3) and write them as a
+        /// single record per sequence, joined by --frame-separator, as some HMM search tools expect
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["frame_reference", "msa_mode", "auto_frame", "annotate_header"]
+        )]
+        concatenate_frames: bool,
+        /// Translate all six reading frames (forward 1-3, then reverse 1-3), overriding
+        /// --reading-frame, and write each as its own record named with a suffix of _f1.._f3 or
+        /// _r1.._r3
+        #[arg(
+            long,
+            visible_alias = "all-frames",
+            default_value_t = false,
+            conflicts_with_all = ["frame_reference", "msa_mode", "auto_frame", "concatenate_frames", "annotate_header"]
+        )]
+        six_frame: bool,
+        /// Optional TSV file to write the codon each residue was translated from, with a row per
+        /// residue (record, residue_index, amino_acid, codon). Only used for a single fixed frame
+        #[arg(
+            long,
+            conflicts_with_all = ["frame_reference", "msa_mode", "auto_frame", "concatenate_frames", "six_frame"]
+        )]
+        codon_output: Option<PathBuf>,
+        /// A custom codon table to translate with instead of --genetic-code: a file with a row per
+        /// codon, giving the codon and its amino acid (* for a stop) separated by whitespace
+        #[arg(long, visible_alias = "codon-table-file", conflicts_with = "genetic_code")]
+        codon_table: Option<PathBuf>,
+        /// The character placed between frames with --concatenate-frames. The default matches the
+        /// stop character, so each frame reads as if it ended in a stop codon.
+        #[arg(long, default_value_t = '*', requires = "concatenate_frames")]
+        frame_separator: char,
+        /// The number of threads to translate with in the default per-sequence mode. 0 (the
+        /// default) uses every core; the output order doesn't depend on it
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
         #[command(flatten)]
         translation_options: TranslateCliOptions,
     },
@@ -392,6 +943,28 @@ pub enum Commands {
         /// Include the stop codon in the output
         #[arg(long, default_value_t = true)]
         include_stop: bool,
+        /// The NCBI genetic code whose stop codons to trim at (1, 2, 4, 5 or 11)
+        #[arg(long, default_value_t = GeneticCode::Standard)]
+        genetic_code: GeneticCode,
+    },
+
+    /// Trim leading and trailing homopolymer runs (e.g. polyA/polyT tails) from each sequence.
+    TrimHomopolymer {
+        /// The input FASTA file
+        #[arg(short = 'i', long)]
+        input_file: PathBuf,
+        /// The output FASTA file to write the trimmed sequences to
+        #[arg(short = 'o', long)]
+        output_file: PathBuf,
+        /// Comma-separated list of bases whose runs should be trimmed
+        #[arg(short = 'b', long, value_delimiter = ',', default_value = "A,T")]
+        bases: Vec<char>,
+        /// The minimum length of a run for it to be trimmed
+        #[arg(short = 'l', long, default_value_t = 10)]
+        min_length: usize,
+        /// The number of other bases tolerated inside a run
+        #[arg(short = 'm', long, default_value_t = 1)]
+        max_mismatches: usize,
     },
 
     #[cfg(feature = "trim-sam")]
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// The failure modes that library users are likely to want to handle, rather than just report.
+/// Functions that fail in one of these ways return a `PursError`, which the CLI (and anything
+/// else using `anyhow`) can still wrap with more context for display.
+#[derive(Debug, Error)]
+pub enum PursError {
+    #[error("The file {path:?} does not exist.")]
+    FileNotFound { path: PathBuf },
+
+    #[error("Could not read the file {path:?}.")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// `record` is the 1-based index of the record in the file, since a record that fails to
+    /// parse doesn't have an ID yet. The message includes `error`, so it isn't also chained as the
+    /// source.
+    #[error("Failed to parse record {record} of {path:?}: {error}")]
+    Parse {
+        path: PathBuf,
+        record: usize,
+        error: std::io::Error,
+    },
+
+    /// `record` is the 1-based index of the record in the file.
+    #[error("Failed to parse FASTQ record {record} of {path:?}: {message}")]
+    FastqParse {
+        path: PathBuf,
+        record: usize,
+        message: String,
+    },
+
+    #[error(
+        "The sequence ID {id:?} appears more than once in {path:?}. Use --on-duplicate to keep the first or last record with the ID, or to rename the repeats."
+    )]
+    DuplicateId { path: PathBuf, id: String },
+
+    #[error("There are no sequences to build an alignment from.")]
+    EmptyAlignment,
+
+    /// `index` is the 1-based index of the first sequence with a different length.
+    #[error(
+        "Not all sequences in the MSA have the same length. The length of the 1st seq is {expected} and the length of seq {index} is {found}."
+    )]
+    AlignmentLengthMismatch {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("No GenBank records were found in {path:?}.")]
+    NoGenbankRecords { path: PathBuf },
+
+    #[error("No feature in the GenBank record has a 'note' qualifier matching {name:?}.")]
+    MissingFeature { name: String },
+
+    #[error(
+        "Failed to grab a codon from {start} to {end} on the nucleotide sequence of length {len}. Index out of bounds."
+    )]
+    CodonOutOfBounds { start: usize, end: usize, len: usize },
+
+    #[error("{id:?} is not a supported NCBI genetic code (expected one of 1, 2, 4, 5 or 11).")]
+    UnknownGeneticCode { id: String },
+}
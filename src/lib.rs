@@ -1,4 +1,5 @@
 pub mod cli;
+pub mod error;
 #[cfg(feature = "python")]
 pub mod python;
 pub mod tools;
@@ -1,43 +1,159 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 use purs::cli;
-use purs::cli::Commands;
+use purs::cli::{Commands, ReadingFrameArg};
 use purs::tools;
+use purs::tools::replace_ambiguities::validate_ambiguity_lookup;
+use purs::utils::fasta_utils;
+use purs::utils::output;
+use purs::utils::progress;
+use purs::utils::summary::RunSummary;
+use purs::utils::translate::validate_codon_tables;
 
 fn main() -> Result<()> {
     simple_logger::SimpleLogger::new().env().init()?;
 
-    let cli = cli::Cli::parse();
+    let matches = cli::Cli::command().get_matches();
+    let cli = cli::Cli::from_arg_matches(&matches)?;
+    let mut summary = RunSummary::new(matches.subcommand_name().unwrap_or_default());
+    output::set_no_clobber(cli.no_clobber);
+    progress::set_progress_interval(cli.progress_interval.unwrap_or_default());
+    fasta_utils::set_line_width(cli.line_width);
+    fasta_utils::set_duplicate_id_policy(cli.on_duplicate);
+    fasta_utils::set_skip_invalid(cli.skip_invalid);
+    validate_codon_tables()?;
+    validate_ambiguity_lookup()?;
 
     match cli.command {
         Commands::ReverseTranslate {
             aa_filepath,
             nt_filepath,
             output_file_path,
+            codon_alignment,
+            gap_output,
         } => {
-            tools::reverse_translate::run(&aa_filepath, &nt_filepath, &output_file_path)?;
+            tools::reverse_translate::run(
+                &aa_filepath,
+                &nt_filepath,
+                &output_file_path,
+                codon_alignment,
+                gap_output,
+                &mut summary,
+            )?;
+        }
+        Commands::RevComp {
+            input_file,
+            output_file,
+            only_matching,
+        } => {
+            tools::rev_comp::run(&input_file, &output_file, only_matching.as_deref(), &mut summary)?;
+        }
+        Commands::AlignPair {
+            input_file,
+            second_input_file,
+            first_id,
+            second_id,
+            alignment_type,
+            translated,
+            width,
+            scoring,
+        } => {
+            tools::align_pair::run(
+                &input_file,
+                second_input_file.as_ref(),
+                &first_id,
+                &second_id,
+                &(&scoring).into(),
+                alignment_type,
+                translated,
+                width,
+                &mut summary,
+            )?;
+        }
+        Commands::CheckAlignment { input_file } => {
+            tools::check_alignment::run(&input_file, &mut summary)?;
         }
         Commands::GetConsensus {
             input_msa,
             output_file,
+            both_outputs,
             consensus_name,
             ambiguity_mode,
+            groups,
+            skip_ungrouped,
+            reference,
+            ambiguity_report,
+            pfm_output,
+            weighted,
+            min_frequency,
+            min_fraction,
+            gap_threshold,
+            ignore_gaps,
+            seed,
         } => {
-            tools::get_consensus::run(&input_msa, &output_file, &consensus_name, ambiguity_mode)?;
+            tools::get_consensus::run(
+                &input_msa,
+                output_file.as_ref(),
+                both_outputs.as_deref(),
+                &consensus_name,
+                ambiguity_mode,
+                groups.as_ref(),
+                skip_ungrouped,
+                reference.as_ref(),
+                ambiguity_report.as_ref(),
+                pfm_output.as_ref(),
+                weighted,
+                min_frequency,
+                min_fraction,
+                ignore_gaps.then_some(1.0).or(gap_threshold),
+                seed,
+                &mut summary,
+            )?;
         }
         Commands::Translate {
             input_file,
             output_file,
+            annotate_header,
+            frame_reference,
+            reference_frame,
+            msa_mode,
+            auto_frame,
+            split_by_frame,
+            concatenate_frames,
+            six_frame,
+            frame_separator,
+            codon_output,
+            codon_table,
+            threads,
             translation_options,
         } => {
-            tools::translate::run(&input_file, &output_file, &(&translation_options).into())?;
+            tools::translate::run(
+                &input_file,
+                &output_file,
+                &(&translation_options).into(),
+                annotate_header,
+                frame_reference.as_ref(),
+                reference_frame,
+                msa_mode,
+                auto_frame || translation_options.reading_frame == ReadingFrameArg::Auto,
+                six_frame,
+                split_by_frame.as_ref(),
+                concatenate_frames.then_some(frame_separator as u8),
+                codon_output.as_ref(),
+                codon_table.as_ref(),
+                threads,
+                &mut summary,
+            )?;
         }
         Commands::Collapse {
             input_file,
             output_file,
             name_output_file,
             strip_gaps,
+            mask_unresolved,
             sequence_prefix,
+            verify_roundtrip,
+            checksum_output,
         } => {
             tools::collapse::run(
                 &input_file,
@@ -45,6 +161,47 @@ fn main() -> Result<()> {
                 &name_output_file,
                 &sequence_prefix,
                 strip_gaps,
+                mask_unresolved,
+                verify_roundtrip,
+                checksum_output.as_ref(),
+                &mut summary,
+            )?;
+        }
+        Commands::ConsensusIdentity {
+            input_msa,
+            output_file,
+            consensus,
+            ambiguity_mode,
+            seed,
+        } => {
+            tools::consensus_identity::run(
+                &input_msa,
+                &output_file,
+                consensus.as_ref(),
+                ambiguity_mode,
+                seed,
+                &mut summary,
+            )?;
+        }
+        Commands::Count { input_file } => {
+            tools::count::run(&input_file, &mut summary)?;
+        }
+        Commands::DeChimera {
+            input_file,
+            reference,
+            output_file,
+            chimera_output,
+            min_indel,
+            split,
+        } => {
+            tools::de_chimera::run(
+                &input_file,
+                &reference,
+                &output_file,
+                &chimera_output,
+                min_indel,
+                split,
+                &mut summary,
             )?;
         }
         Commands::Expand {
@@ -52,8 +209,18 @@ fn main() -> Result<()> {
             name_input_file,
             output_file,
             include_missing,
+            checksum_output,
+            verify,
         } => {
-            tools::expand::run(&input_file, &name_input_file, &output_file, include_missing)?;
+            tools::expand::run(
+                &input_file,
+                &name_input_file,
+                &output_file,
+                include_missing,
+                checksum_output.as_ref(),
+                verify.as_ref(),
+                &mut summary,
+            )?;
         }
         Commands::FilterByLength {
             input_file,
@@ -62,7 +229,8 @@ fn main() -> Result<()> {
             rejected_seq_output,
             threshold,
             tolerance,
-            exclude_gaps
+            exclude_gaps,
+            checksum_output,
         } => {
             tools::filter_by_length::run(
                 &input_file,
@@ -71,6 +239,8 @@ fn main() -> Result<()> {
                 rejected_seq_output.as_ref(),
                 (&threshold, &tolerance).into(),
                 exclude_gaps,
+                checksum_output.as_ref(),
+                &mut summary,
             )?;
         }
         Commands::FilterByKmer {
@@ -79,6 +249,7 @@ fn main() -> Result<()> {
             report_file,
             rejected_seq_output,
             kmer_filter,
+            checksum_output,
         } => {
             let start_kmers = kmer_filter.start_kmers_bytes();
             let end_kmers = kmer_filter.end_kmers_bytes();
@@ -89,6 +260,8 @@ fn main() -> Result<()> {
                 rejected_seq_output.as_ref(),
                 start_kmers.as_deref(),
                 end_kmers.as_deref(),
+                checksum_output.as_ref(),
+                &mut summary,
             )?;
         }
         Commands::FilterByName {
@@ -96,16 +269,94 @@ fn main() -> Result<()> {
             output_file,
             pattern,
             rejected_seq_output,
-            exclude
+            exclude,
+            checksum_output,
+        } => {
+            tools::filter_by_name::run(
+                &input_file,
+                &output_file,
+                rejected_seq_output.as_ref(),
+                pattern,
+                exclude,
+                checksum_output.as_ref(),
+                &mut summary,
+            )?;
+        }
+        Commands::FindOrfs {
+            input_file,
+            output_file,
+            min_length,
+            both_strands,
+            include_partial,
+            nucleotide,
+            genetic_code,
         } => {
-            tools::filter_by_name::run(&input_file, &output_file, rejected_seq_output.as_ref(), pattern, exclude)?;
+            tools::find_orfs::run(
+                &input_file,
+                &output_file,
+                min_length,
+                both_strands,
+                include_partial,
+                nucleotide,
+                genetic_code,
+                &mut summary,
+            )?;
         }
         Commands::GbExtract {
             input_file,
             output_file,
             seq_name,
+            mmap,
+            flank,
         } => {
-            tools::gb_extract::run(&input_file, &output_file, &seq_name)?;
+            tools::gb_extract::run(
+                &input_file,
+                &output_file,
+                &seq_name,
+                mmap,
+                flank,
+                &mut summary,
+            )?;
+        }
+        Commands::GuessFrame {
+            input_file,
+            output_file,
+        } => {
+            tools::guess_frame::run(&input_file, &output_file, &mut summary)?;
+        }
+        Commands::MapCoordinates {
+            input_file,
+            reference,
+            output_file,
+            reference_start,
+            scoring,
+        } => {
+            tools::map_coordinates::run(
+                &input_file,
+                &reference,
+                &output_file,
+                reference_start,
+                &(&scoring).into(),
+                &mut summary,
+            )?;
+        }
+        Commands::OrientReads {
+            input_file,
+            reference,
+            outputs,
+            unmatched_output,
+            min_score,
+        } => {
+            tools::orient_reads::run(
+                &input_file,
+                &reference,
+                outputs.forward_output.as_ref(),
+                outputs.reverse_output.as_ref(),
+                outputs.reverse_complement_output.as_ref(),
+                unmatched_output.as_ref(),
+                min_score,
+                &mut summary,
+            )?;
         }
         #[cfg(feature = "trim-sam")]
         Commands::TrimSam {
@@ -114,14 +365,23 @@ fn main() -> Result<()> {
             trim_from,
             trim_to,
         } => {
-            tools::trim_sam::run(&input_file, &output_file, trim_from, trim_to)?;
+            tools::trim_sam::run(&input_file, &output_file, trim_from, trim_to, &mut summary)?;
         }
         Commands::ReplaceAmbiguities {
             input_file,
             output_file,
             seed,
+            strategy,
+            reference,
         } => {
-            tools::replace_ambiguities::run(&input_file, &output_file, seed)?;
+            tools::replace_ambiguities::run(
+                &input_file,
+                &output_file,
+                seed,
+                strategy,
+                reference.as_ref(),
+                &mut summary,
+            )?;
         }
         #[cfg(feature = "process-miniprot")]
         Commands::ProcessMiniprot {
@@ -130,21 +390,45 @@ fn main() -> Result<()> {
             prepend,
             output_dir,
         } => {
-            tools::process_miniprot::run(&input_file, &paf_file, &prepend, &output_dir)?;
+            tools::process_miniprot::run(&input_file, &paf_file, &prepend, &output_dir, &mut summary)?;
         }
         Commands::TrimAfterStop {
             input_file,
             output_file,
             include_stop,
+            genetic_code,
+        } => {
+            tools::trim_after_stop_codon::run(
+                &input_file,
+                &output_file,
+                include_stop,
+                genetic_code,
+                &mut summary,
+            )?;
+        }
+        Commands::TrimHomopolymer {
+            input_file,
+            output_file,
+            bases,
+            min_length,
+            max_mismatches,
         } => {
-            tools::trim_after_stop_codon::run(&input_file, &output_file, include_stop)?;
+            let bases: Vec<u8> = bases.iter().map(|b| b.to_ascii_uppercase() as u8).collect();
+            tools::trim_homopolymer::run(
+                &input_file,
+                &output_file,
+                &bases,
+                min_length,
+                max_mismatches,
+                &mut summary,
+            )?;
         }
         Commands::StripGapCols {
             input_file,
             output_file,
             min_gap_pct,
         } => {
-            tools::strip_gap_cols::run(&input_file, &output_file, min_gap_pct)?;
+            tools::strip_gap_cols::run(&input_file, &output_file, min_gap_pct, &mut summary)?;
         }
         Commands::GetMindistSeq {
             input_msa,
@@ -152,9 +436,20 @@ fn main() -> Result<()> {
             ambiguity_mode,
             compute_mode
         } => {
-            tools::get_mindist_seq::run(&input_msa, &output_file, ambiguity_mode, compute_mode)?;
+            tools::get_mindist_seq::run(
+                &input_msa,
+                &output_file,
+                ambiguity_mode,
+                compute_mode,
+                &mut summary,
+            )?;
         }
     }
 
+    if let Some(summary_json) = cli.summary_json {
+        log::info!("Writing run summary to {:?}", summary_json);
+        summary.write(&summary_json)?;
+    }
+
     Ok(())
 }
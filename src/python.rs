@@ -1,11 +1,12 @@
 use crate::tools;
 use crate::tools::get_consensus::AmbiguityMode;
+use crate::utils::codon_tables::GeneticCode;
 use crate::utils::fasta_utils::FastaRecords;
 use crate::tools::get_mindist_seq::{ComputeMode};
-use crate::utils::translate::TranslationOptions;
+use crate::utils::translate::{StopRecoding, TranslationOptions};
 
-fn to_pyerr(e: anyhow::Error) -> pyo3::PyErr {
-    pyo3::exceptions::PyRuntimeError::new_err(e.to_string())
+fn to_pyerr(e: impl Into<anyhow::Error>) -> pyo3::PyErr {
+    pyo3::exceptions::PyRuntimeError::new_err(e.into().to_string())
 }
 
 fn dict_to_records(seqs: std::collections::HashMap<String, String>) -> FastaRecords {
@@ -68,7 +69,22 @@ pub mod purs {
     }
 
     #[pyfunction]
-    fn get_consensus(seqs: Vec<String>, ambiguity_mode: String) -> PyResult<String> {
+    #[pyo3(signature = (
+        seqs,
+        ambiguity_mode,
+        min_frequency=0.0,
+        min_fraction=0.0,
+        gap_threshold=None,
+        seed=None
+    ))]
+    fn get_consensus(
+        seqs: Vec<String>,
+        ambiguity_mode: String,
+        min_frequency: f64,
+        min_fraction: f64,
+        gap_threshold: Option<f64>,
+        seed: Option<u64>,
+    ) -> PyResult<String> {
         let msa: Vec<Vec<u8>> = seqs.into_iter().map(String::into_bytes).collect();
         let mode = match ambiguity_mode.as_str() {
             "IUPAC" => AmbiguityMode::UseIUPAC,
@@ -83,7 +99,13 @@ pub mod purs {
         };
 
         let matrix = tools::get_consensus::sequences_to_matrix(&msa).map_err(to_pyerr)?;
-        let consensus = tools::get_consensus::build_consensus(&matrix, mode).map_err(to_pyerr)?;
+        let thresholds = tools::get_consensus::ConsensusThresholds {
+            min_frequency,
+            min_fraction,
+            gap_threshold,
+        };
+        let consensus = tools::get_consensus::build_consensus(&matrix, mode, thresholds, seed)
+            .map_err(to_pyerr)?;
 
         String::from_utf8(consensus)
             .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
@@ -101,6 +123,18 @@ pub mod purs {
         strip_gaps=false,
         ignore_gap_codons=false,
         drop_incomplete_codons=true,
+        incomplete_as_gap=false,
+        codon_start=None,
+        codon_end=None,
+        recover_frame=false,
+        strict_translation=false,
+        strict_frame=false,
+        genetic_code=1,
+        reverse_complement=false,
+        exhaustive_ambiguities=false,
+        recode_stops=None,
+        sec_aa='U',
+        pyl_aa='O',
     ))]
     fn translate(
         seqs: HashMap<String, String>,
@@ -113,7 +147,32 @@ pub mod purs {
         strip_gaps: bool,
         ignore_gap_codons: bool,
         drop_incomplete_codons: bool,
+        incomplete_as_gap: bool,
+        codon_start: Option<usize>,
+        codon_end: Option<usize>,
+        recover_frame: bool,
+        strict_translation: bool,
+        strict_frame: bool,
+        genetic_code: u8,
+        reverse_complement: bool,
+        exhaustive_ambiguities: bool,
+        recode_stops: Option<String>,
+        sec_aa: char,
+        pyl_aa: char,
     ) -> PyResult<HashMap<String, String>> {
+        let genetic_code = GeneticCode::try_from(genetic_code)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        let recode_stops = match recode_stops.as_deref() {
+            None => None,
+            Some("sec") => Some(StopRecoding::Sec),
+            Some("pyl") => Some(StopRecoding::Pyl),
+            Some("both") => Some(StopRecoding::Both),
+            Some(other) => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Unknown stop recoding: {other:?}. Expected one of \"sec\", \"pyl\" or \"both\"."
+                )));
+            }
+        };
         let options = TranslationOptions {
             unknown_aa: unknown_aa as u8,
             stop_aa: stop_aa as u8,
@@ -124,6 +183,19 @@ pub mod purs {
             strip_gaps,
             ignore_gap_codons,
             drop_incomplete_codons,
+            incomplete_as_gap,
+            codon_start,
+            codon_end,
+            recover_frame,
+            strict_translation,
+            strict_frame,
+            genetic_code,
+            custom_codon_table: None,
+            reverse_complement,
+            exhaustive_ambiguities,
+            recode_stops,
+            sec_aa: sec_aa as u8,
+            pyl_aa: pyl_aa as u8,
         };
 
         let translated = tools::translate::translate_records(dict_to_records(seqs), &options)
@@ -139,6 +211,7 @@ pub mod purs {
         let result = tools::reverse_translate::process_sequences(
             dict_to_records(aa_seqs),
             dict_to_records(nt_seqs),
+            tools::reverse_translate::GapOutput::Gap,
         )
             .map_err(to_pyerr)?;
         records_to_dict(result)
@@ -150,20 +223,31 @@ pub mod purs {
         seed: u64,
     ) -> PyResult<HashMap<String, String>> {
         let result =
-            tools::replace_ambiguities::replace_ambiguities_records(dict_to_records(seqs), seed)
+            tools::replace_ambiguities::replace_ambiguities_records(
+                dict_to_records(seqs),
+                seed,
+                tools::replace_ambiguities::ResolutionStrategy::Random,
+                None,
+            )
                 .map_err(to_pyerr)?;
         records_to_dict(result)
     }
 
     #[pyfunction]
-    #[pyo3(signature = (seqs, include_stop_codon=true))]
+    #[pyo3(signature = (seqs, include_stop_codon=true, genetic_code=1))]
     fn trim_after_stop_codon(
         seqs: HashMap<String, String>,
         include_stop_codon: bool,
+        genetic_code: u8,
     ) -> PyResult<HashMap<String, String>> {
-        let result =
-            tools::trim_after_stop_codon::process_file(dict_to_records(seqs), include_stop_codon)
-                .map_err(to_pyerr)?;
+        let genetic_code = GeneticCode::try_from(genetic_code)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        let result = tools::trim_after_stop_codon::process_file(
+            dict_to_records(seqs),
+            include_stop_codon,
+            genetic_code,
+        )
+        .map_err(to_pyerr)?;
         records_to_dict(result)
     }
 
@@ -211,17 +295,25 @@ pub mod purs {
     }
 
     #[pyfunction]
-    #[pyo3(signature = (seqs, seq_prefix, strip_gaps=false))]
+    #[pyo3(signature = (seqs, seq_prefix, strip_gaps=false, mask_unresolved=false))]
     fn collapse(
         seqs: HashMap<String, String>,
         seq_prefix: String,
         strip_gaps: bool,
+        mask_unresolved: bool,
     ) -> PyResult<(HashMap<String, String>, HashMap<String, Vec<String>>)> {
-        let collapsed = tools::collapse::collapse_sequences(dict_to_records(seqs), strip_gaps)
-            .map_err(to_pyerr)?;
+        let collapsed = tools::collapse::collapse_sequences(
+            dict_to_records(seqs),
+            strip_gaps,
+            mask_unresolved,
+        )
+        .map_err(to_pyerr)?;
         let (records, name_mapping) =
             tools::collapse::build_collapsed_output(collapsed, &seq_prefix);
-        Ok((records_to_dict(records)?, name_mapping))
+        Ok((
+            records_to_dict(records)?,
+            name_mapping.into_iter().collect(),
+        ))
     }
 
     #[pyfunction]
@@ -233,7 +325,7 @@ pub mod purs {
     ) -> PyResult<HashMap<String, String>> {
         let expanded = tools::expand::uncollapse_sequences(
             dict_to_records(seqs),
-            name_mapping,
+            name_mapping.into_iter().collect(),
             include_missing,
         )
             .map_err(to_pyerr)?;
@@ -0,0 +1,165 @@
+use crate::utils::fasta_utils::load_fasta;
+use crate::utils::summary::RunSummary;
+use crate::utils::translate::{translate, TranslationOptions};
+use anyhow::{Context, Result};
+use bio::alignment::pairwise::Aligner;
+use bio::alignment::Alignment;
+use bio::scores::blosum62;
+use clap::ValueEnum;
+use colored::Colorize;
+use std::path::PathBuf;
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum AlignmentType {
+    Global,
+    Semiglobal,
+    Local,
+}
+
+/// Scoring parameters for a pairwise alignment. The match/mismatch scores are only used for
+/// nucleotide alignments; translated alignments are scored with BLOSUM62.
+#[derive(Clone, Copy)]
+pub struct PairwiseScoring {
+    pub match_score: i32,
+    pub mismatch_score: i32,
+    pub gap_open: i32,
+    pub gap_extend: i32,
+}
+
+impl Default for PairwiseScoring {
+    fn default() -> Self {
+        Self {
+            match_score: 1,
+            mismatch_score: -1,
+            gap_open: -5,
+            gap_extend: -1,
+        }
+    }
+}
+
+fn run_aligner<F: bio::alignment::pairwise::MatchFunc>(
+    aligner: &mut Aligner<F>,
+    x: &[u8],
+    y: &[u8],
+    alignment_type: AlignmentType,
+) -> Alignment {
+    match alignment_type {
+        AlignmentType::Global => aligner.global(x, y),
+        AlignmentType::Semiglobal => aligner.semiglobal(x, y),
+        AlignmentType::Local => aligner.local(x, y),
+    }
+}
+
+/// Align `x` against `y`, either as nucleotides using the match/mismatch scores in `scoring`, or
+/// as amino acids (scored with BLOSUM62) if the sequences have already been translated.
+pub(crate) fn align_pair(
+    x: &[u8],
+    y: &[u8],
+    scoring: &PairwiseScoring,
+    alignment_type: AlignmentType,
+    translated: bool,
+) -> Alignment {
+    if translated {
+        let mut aligner = Aligner::with_capacity(
+            x.len(),
+            y.len(),
+            scoring.gap_open,
+            scoring.gap_extend,
+            blosum62,
+        );
+        run_aligner(&mut aligner, x, y, alignment_type)
+    } else {
+        let (match_score, mismatch_score) = (scoring.match_score, scoring.mismatch_score);
+        let score = |a: u8, b: u8| if a == b { match_score } else { mismatch_score };
+        let mut aligner =
+            Aligner::with_capacity(x.len(), y.len(), scoring.gap_open, scoring.gap_extend, score);
+        run_aligner(&mut aligner, x, y, alignment_type)
+    }
+}
+
+fn load_sequence(file_path: &PathBuf, seq_id: &str) -> Result<Vec<u8>> {
+    let mut sequences = load_fasta(file_path)
+        .with_context(|| format!("Failed to read sequences from {:?}", file_path))?;
+    sequences
+        .swap_remove(seq_id)
+        .with_context(|| format!("Could not find a sequence named {:?} in {:?}", seq_id, file_path))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    input_file: &PathBuf,
+    second_input_file: Option<&PathBuf>,
+    first_id: &str,
+    second_id: &str,
+    scoring: &PairwiseScoring,
+    alignment_type: AlignmentType,
+    translated: bool,
+    width: usize,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    log::info!(
+        "{}",
+        format!("This is 'align-pair' version {}", env!("CARGO_PKG_VERSION"))
+            .bold()
+            .bright_blue()
+    );
+
+    let mut first_seq = load_sequence(input_file, first_id)?;
+    let mut second_seq = load_sequence(second_input_file.unwrap_or(input_file), second_id)?;
+    summary.input(input_file);
+    if let Some(second_input_file) = second_input_file {
+        summary.input(second_input_file);
+    }
+    summary.count("read", 2);
+
+    if translated {
+        log::info!("Translating both sequences before aligning.");
+        let options = TranslationOptions {
+            strip_gaps: true,
+            ..TranslationOptions::default()
+        };
+        first_seq = translate(&first_seq, &options)?;
+        second_seq = translate(&second_seq, &options)?;
+    }
+
+    let alignment = align_pair(&first_seq, &second_seq, scoring, alignment_type, translated);
+
+    println!("{} vs {}", first_id.bold(), second_id.bold());
+    println!("Score: {}\n", alignment.score);
+    println!("{}", alignment.pretty(&first_seq, &second_seq, width));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_nucleotide_alignment() {
+        let scoring = PairwiseScoring::default();
+        let alignment = align_pair(b"ATGACG", b"ATGACG", &scoring, AlignmentType::Global, false);
+
+        assert_eq!(alignment.score, 6);
+    }
+
+    #[test]
+    fn test_custom_scoring() {
+        let scoring = PairwiseScoring {
+            match_score: 2,
+            mismatch_score: -3,
+            ..PairwiseScoring::default()
+        };
+        let alignment = align_pair(b"ATGACG", b"ATGTCG", &scoring, AlignmentType::Global, false);
+
+        assert_eq!(alignment.score, 5 * 2 - 3);
+    }
+
+    #[test]
+    fn test_translated_alignment_uses_blosum62() {
+        let scoring = PairwiseScoring::default();
+        let alignment = align_pair(b"MW", b"MW", &scoring, AlignmentType::Global, true);
+
+        assert_eq!(alignment.score, blosum62(b'M', b'M') + blosum62(b'W', b'W'));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::utils::codon_tables::{DEFAULT_STOP_CHAR, GAP_CHAR};
+use crate::utils::fasta_utils::{load_fasta, FastaRecords};
+use crate::utils::summary::RunSummary;
+use anyhow::{bail, Result};
+use colored::Colorize;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Characters other than letters that may legitimately appear in an alignment: gaps, the
+/// alternative gap character '.', stop codons, and the default incomplete-codon character.
+const EXTRA_ALIGNMENT_CHARS: [u8; 4] = [GAP_CHAR, b'.', DEFAULT_STOP_CHAR, b'?'];
+
+fn is_alignment_char(character: u8) -> bool {
+    character.is_ascii_alphabetic() || EXTRA_ALIGNMENT_CHARS.contains(&character)
+}
+
+pub(crate) struct AlignmentReport {
+    /// Maps each sequence length seen to the number of sequences with that length.
+    pub(crate) length_counts: BTreeMap<usize, usize>,
+    /// Records containing characters that should not appear in an alignment, along with the
+    /// distinct offending characters.
+    pub(crate) invalid_records: Vec<(String, Vec<u8>)>,
+}
+
+impl AlignmentReport {
+    pub(crate) fn passed(&self) -> bool {
+        self.length_counts.len() == 1 && self.invalid_records.is_empty()
+    }
+}
+
+/// Check that the sequences form a valid alignment: every sequence has the same length and
+/// contains only alignment characters. Unlike `sequences_to_matrix`, a failed check is reported
+/// rather than returned as an error.
+pub(crate) fn check_alignment(sequences: &FastaRecords) -> Result<AlignmentReport> {
+    if sequences.is_empty() {
+        bail!("No sequences were provided.")
+    }
+
+    let mut length_counts = BTreeMap::new();
+    let mut invalid_records = Vec::new();
+
+    for (seq_name, seq) in sequences {
+        *length_counts.entry(seq.len()).or_insert(0) += 1;
+
+        let invalid_chars: Vec<u8> = seq
+            .iter()
+            .copied()
+            .filter(|&c| !is_alignment_char(c))
+            .unique()
+            .sorted()
+            .collect();
+        if !invalid_chars.is_empty() {
+            invalid_records.push((seq_name.clone(), invalid_chars));
+        }
+    }
+
+    invalid_records.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(AlignmentReport {
+        length_counts,
+        invalid_records,
+    })
+}
+
+pub fn run(input_file: &PathBuf, summary: &mut RunSummary) -> Result<()> {
+    log::info!(
+        "{}",
+        format!(
+            "This is 'check-alignment' version {}",
+            env!("CARGO_PKG_VERSION")
+        )
+        .bold()
+        .bright_cyan()
+    );
+
+    log::info!("Reading input file {:?}", input_file);
+    let sequences = load_fasta(input_file)?;
+    let report = check_alignment(&sequences)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+    summary.count("invalid", report.invalid_records.len());
+
+    if report.length_counts.len() > 1 {
+        log::warn!("Not all sequences have the same length. Length distribution:");
+        for (length, count) in &report.length_counts {
+            log::warn!("\tlength {}: {} sequence(s)", length, count);
+        }
+    }
+
+    for (seq_name, invalid_chars) in &report.invalid_records {
+        log::warn!(
+            "The sequence {} contains non-alignment characters: {:?}",
+            seq_name,
+            String::from_utf8_lossy(invalid_chars)
+        );
+    }
+
+    if !report.passed() {
+        bail!("{} is not a valid alignment.", input_file.display());
+    }
+
+    log::info!(
+        "{}",
+        format!(
+            "PASS: {} sequences of length {}.",
+            sequences.len(),
+            report.length_counts.keys().next().unwrap()
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use velcro::map_iter;
+
+    #[test]
+    fn test_valid_alignment() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATG-CA".to_vec(),
+            "B".to_string(): b"ATGNCA".to_vec(),
+        ).collect();
+
+        let report = check_alignment(&sequences)?;
+        assert!(report.passed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unequal_lengths() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATGCA".to_vec(),
+            "B".to_string(): b"ATGCA".to_vec(),
+            "C".to_string(): b"ATG".to_vec(),
+        ).collect();
+
+        let report = check_alignment(&sequences)?;
+        assert!(!report.passed());
+        assert_eq!(report.length_counts[&5], 2);
+        assert_eq!(report.length_counts[&3], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_characters() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"AT1C ".to_vec(),
+            "B".to_string(): b"ATG-C".to_vec(),
+        ).collect();
+
+        let report = check_alignment(&sequences)?;
+        assert!(!report.passed());
+        assert_eq!(report.invalid_records.len(), 1);
+        assert_eq!(report.invalid_records[0].0, "A");
+        assert_eq!(report.invalid_records[0].1, b" 1".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert!(check_alignment(&FastaRecords::new()).is_err());
+    }
+}
@@ -1,31 +1,104 @@
+use crate::tools::expand::{uncollapse_sequences, NewToOldNameMapping};
+use crate::utils::checksum::write_checksums;
 use crate::utils::codon_tables::GAP_CHAR;
-use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
-use anyhow::Result;
+use crate::utils::fasta_utils::{load_fastas, write_fasta_sequences, FastaRecords};
+use crate::utils::output::create_output_file;
+use crate::utils::summary::RunSummary;
+use anyhow::{bail, Result};
 use colored::Colorize;
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use std::io::Write;
 use std::path::PathBuf;
 
-pub(crate) type SeqToNameMapping = HashMap<Vec<u8>, Vec<String>>;
+/// The sequences that collapse together.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CollapsedGroup {
+    /// The sequence written for the group: that of its first member, without gaps if they were
+    /// stripped.
+    pub(crate) sequence: Vec<u8>,
+    pub(crate) names: Vec<String>,
+}
+
+/// The collapsed groups, keyed by the normalized sequence they collapse on (see
+/// `normalize_sequence`), in the order each group was first seen.
+pub(crate) type SeqToNameMapping = IndexMap<Vec<u8>, CollapsedGroup>;
+
+/// Unresolved positions: N in nucleotide sequences and X in amino acid sequences.
+const UNRESOLVED_CHARS: [u8; 2] = [b'N', b'X'];
+
+fn is_unresolved(c: &u8) -> bool {
+    UNRESOLVED_CHARS.contains(c)
+}
+
+/// Apply the normalization that sequences are collapsed on. With `strip_gaps`, gaps are removed.
+/// With `mask_unresolved`, each run of Ns (or Xs) is shortened to a single N (or X), so sequences
+/// that only differ in the length of their unresolved regions collapse together.
+pub(crate) fn normalize_sequence(seq: &mut Vec<u8>, strip_gaps: bool, mask_unresolved: bool) {
+    if strip_gaps {
+        seq.retain(|&val| val != GAP_CHAR);
+    }
+    if mask_unresolved {
+        seq.dedup_by(|a, b| a == b && is_unresolved(a));
+    }
+}
+
+/// Whether two normalized sequences are the same apart from their unresolved positions, where an
+/// N (or X) in either sequence matches any character in the other.
+fn matches_unresolved(seq: &[u8], other: &[u8]) -> bool {
+    seq.len() == other.len()
+        && seq
+            .iter()
+            .zip(other)
+            .all(|(a, b)| a == b || is_unresolved(a) || is_unresolved(b))
+}
 
+/// Group identical sequences, after the normalization in `normalize_sequence`. With
+/// `mask_unresolved`, a sequence that doesn't match a group exactly joins the first group it
+/// matches apart from its unresolved positions (see `matches_unresolved`). The normalized
+/// sequence is only used to group the sequences: each group keeps the sequence of its first
+/// member.
 pub(crate) fn collapse_sequences(
     sequences: FastaRecords,
     strip_gaps: bool,
+    mask_unresolved: bool,
 ) -> Result<SeqToNameMapping> {
     let mut unique_sequences: SeqToNameMapping =
         SeqToNameMapping::with_capacity(sequences.capacity());
+    // The groups whose key has an unresolved position, which a fully resolved sequence could
+    // still match.
+    let mut unresolved_groups: Vec<usize> = Vec::new();
 
-    for fasta_record in sequences {
-        let record_id = fasta_record.0;
-        let mut record_seq = fasta_record.1;
-
+    for (record_id, mut record_seq) in sequences {
         if strip_gaps {
             record_seq.retain(|&val| val != GAP_CHAR);
         }
+        let mut key = record_seq.clone();
+        normalize_sequence(&mut key, false, mask_unresolved);
 
-        unique_sequences
-            .entry(record_seq)
-            .and_modify(|seq_name_vec| seq_name_vec.push(record_id.to_owned()))
-            .or_insert(vec![record_id.to_owned()]);
+        let mut group_idx = unique_sequences.get_index_of(&key);
+        if group_idx.is_none() && mask_unresolved {
+            let group_key = |idx: usize| unique_sequences.get_index(idx).unwrap().0;
+            group_idx = if key.iter().any(is_unresolved) {
+                (0..unique_sequences.len()).find(|&idx| matches_unresolved(&key, group_key(idx)))
+            } else {
+                (unresolved_groups.iter().copied())
+                    .find(|&idx| matches_unresolved(&key, group_key(idx)))
+            };
+        }
+
+        match group_idx {
+            Some(idx) => unique_sequences[idx].names.push(record_id),
+            None => {
+                if key.iter().any(is_unresolved) {
+                    unresolved_groups.push(unique_sequences.len());
+                }
+                let group = CollapsedGroup {
+                    sequence: record_seq,
+                    names: vec![record_id],
+                };
+                unique_sequences.insert(key, group);
+            }
+        }
     }
 
     Ok(unique_sequences)
@@ -34,56 +107,110 @@ pub(crate) fn collapse_sequences(
 pub(crate) fn build_collapsed_output(
     collapsed_seqs: SeqToNameMapping,
     seq_prefix: &str,
-) -> (FastaRecords, HashMap<String, Vec<String>>) {
+) -> (FastaRecords, NewToOldNameMapping) {
     let mut collapsed_sequences: FastaRecords = FastaRecords::with_capacity(collapsed_seqs.len());
-    let mut name_mapping: HashMap<String, Vec<String>> =
-        HashMap::with_capacity(collapsed_seqs.len());
+    let mut name_mapping = NewToOldNameMapping::with_capacity(collapsed_seqs.len());
 
-    let mut counter = 0;
-    for (sequence, sequence_names) in collapsed_seqs {
+    for (counter, group) in collapsed_seqs.into_values().enumerate() {
         // This will generate a sequence with a unique int for each collapsed seq, and a count
         // for the sequences that make up this collapsed one
-        let seq_name = format!(
-            "{}_{:0>4}_{:0>4}",
-            seq_prefix,
-            counter,
-            sequence_names.len()
-        );
+        let seq_name = format!("{}_{:0>4}_{:0>4}", seq_prefix, counter, group.names.len());
 
-        collapsed_sequences.insert(seq_name.clone(), sequence);
-        counter += 1;
-        name_mapping.insert(seq_name, sequence_names);
+        collapsed_sequences.insert(seq_name.clone(), group.sequence);
+        name_mapping.insert(seq_name, group.names);
     }
 
     (collapsed_sequences, name_mapping)
 }
 
+/// The number of sequences merged into a collapsed one, from the count at the end of a name made
+/// by `build_collapsed_output` (e.g. 42 for `prefix_0001_0042`). None if the name doesn't end in
+/// a counter and a non-zero count.
+pub(crate) fn collapsed_count(seq_name: &str) -> Option<usize> {
+    let mut fields = seq_name.rsplitn(3, '_');
+    let count = fields.next()?.parse::<usize>().ok()?;
+    fields.next()?.parse::<usize>().ok()?;
+    fields.next()?;
+    Some(count).filter(|&count| count > 0)
+}
+
+/// Expand the collapsed output again and check that it reproduces the original sequences (without
+/// gaps if they were stripped, and only up to their unresolved positions with `mask_unresolved`),
+/// catching any drift between the collapsed names and the name mapping that expand relies on.
+pub(crate) fn verify_roundtrip(
+    original_sequences: &FastaRecords,
+    collapsed_sequences: &FastaRecords,
+    name_mapping: &NewToOldNameMapping,
+    strip_gaps: bool,
+    mask_unresolved: bool,
+) -> Result<()> {
+    let expanded_sequences =
+        uncollapse_sequences(collapsed_sequences.clone(), name_mapping.clone(), false)?;
+
+    let mut mismatches = 0;
+    for (seq_name, seq) in original_sequences {
+        let mut expected_seq = seq.clone();
+        normalize_sequence(&mut expected_seq, strip_gaps, mask_unresolved);
+        let matches = |expanded_seq: &Vec<u8>| {
+            let mut expanded_seq = expanded_seq.clone();
+            normalize_sequence(&mut expanded_seq, false, mask_unresolved);
+            if mask_unresolved {
+                matches_unresolved(&expanded_seq, &expected_seq)
+            } else {
+                expanded_seq == expected_seq
+            }
+        };
+        if !expanded_sequences.get(seq_name).is_some_and(matches) {
+            log::error!("The sequence {seq_name} did not survive the collapse/expand round trip.");
+            mismatches += 1;
+        }
+    }
+
+    if mismatches > 0 || expanded_sequences.len() != original_sequences.len() {
+        bail!(
+            "Round-trip verification failed: {} of {} sequences did not match after expanding ({} sequences expanded).",
+            mismatches,
+            original_sequences.len(),
+            expanded_sequences.len()
+        );
+    }
+
+    log::info!("Round-trip verification passed.");
+    Ok(())
+}
+
 fn write_sequences_and_name_mapping(
-    collapsed_seqs: SeqToNameMapping,
+    collapsed_sequences: &FastaRecords,
+    name_mapping: &NewToOldNameMapping,
     output_file: &PathBuf,
     name_mapping_output: &PathBuf,
-    seq_prefix: &String,
 ) -> Result<()> {
-    let (collapsed_sequences, name_mapping) = build_collapsed_output(collapsed_seqs, seq_prefix);
-
     log::info!("Writing unique sequences to file {:?}", output_file);
-    write_fasta_sequences(output_file, &collapsed_sequences)?;
+    write_fasta_sequences(output_file, collapsed_sequences)?;
 
     log::info!("Writing name mapping to {:?}", name_mapping_output);
-    std::fs::write(
-        name_mapping_output,
-        serde_json::to_string(&name_mapping).expect("Error serializing the name map."),
-    )
-    .expect("Error with writing the name map to the disk.");
-    Ok(())
+    let mut name_mapping_file = create_output_file(name_mapping_output)?;
+    name_mapping_file
+        .write_all(
+            serde_json::to_string(name_mapping)
+                .expect("Error serializing the name map.")
+                .as_bytes(),
+        )
+        .expect("Error with writing the name map to the disk.");
+    name_mapping_file.finish()
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
-    input_file: &PathBuf,
+    input_files: &[PathBuf],
     output_file: &PathBuf,
     namefile_output: &PathBuf,
-    seq_name_prefix: &String,
+    seq_name_prefix: &str,
     strip_gaps: bool,
+    mask_unresolved: bool,
+    verify: bool,
+    checksum_output: Option<&PathBuf>,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -92,16 +219,225 @@ pub fn run(
             .bright_yellow()
     );
 
-    log::info!("Reading input file {:?}", input_file);
-    let sequences = load_fasta(input_file)?;
-    let collapsed_seqs = collapse_sequences(sequences, strip_gaps)?;
+    log::info!("Reading input files {:?}", input_files);
+    let sequences = load_fastas(input_files)?;
+    for input_file in input_files {
+        summary.input(input_file);
+    }
+    summary.count("read", sequences.len());
+    let original_sequences = verify.then(|| sequences.clone());
+    let collapsed_seqs = collapse_sequences(sequences, strip_gaps, mask_unresolved)?;
+    let (collapsed_sequences, name_mapping) =
+        build_collapsed_output(collapsed_seqs, seq_name_prefix);
+
+    if let Some(original_sequences) = original_sequences {
+        log::info!("Verifying that the collapsed sequences expand back to the input.");
+        verify_roundtrip(
+            &original_sequences,
+            &collapsed_sequences,
+            &name_mapping,
+            strip_gaps,
+            mask_unresolved,
+        )?;
+    }
 
     write_sequences_and_name_mapping(
-        collapsed_seqs,
+        &collapsed_sequences,
+        &name_mapping,
         output_file,
         namefile_output,
-        seq_name_prefix,
     )?;
+    summary.output(output_file);
+    summary.output(namefile_output);
+    summary.count("written", collapsed_sequences.len());
+
+    if let Some(checksum_output) = checksum_output {
+        log::info!("Writing sequence checksums to {:?}", checksum_output);
+        write_checksums(checksum_output, &collapsed_sequences)?;
+        summary.output(checksum_output);
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fasta_utils::load_fasta;
+    use crate::utils::test_utils::TempPath;
+    use velcro::map_iter;
+
+    #[test]
+    fn test_roundtrip_with_non_ascii_names() -> Result<()> {
+        let input_file = TempPath::new("utf8-input.fasta");
+        let output_file = TempPath::new("utf8-collapsed.fasta");
+        let name_file = TempPath::new("utf8-names.json");
+        std::fs::write(
+            &input_file,
+            ">Müller_2019 São Paulo isolate\nATGCA\n>Ångström_01\natgca\n>Øre_ñ_02\nATGGG\n",
+        )?;
+
+        let sequences = load_fastas(std::slice::from_ref(&*input_file))?;
+        let collapsed = collapse_sequences(sequences.clone(), false, false)?;
+        let (collapsed_sequences, name_mapping) = build_collapsed_output(collapsed, "seq");
+        write_sequences_and_name_mapping(
+            &collapsed_sequences,
+            &name_mapping,
+            &output_file,
+            &name_file,
+        )?;
+
+        let name_mapping = serde_json::from_reader(std::fs::File::open(&name_file)?)?;
+        let expanded = uncollapse_sequences(load_fasta(&output_file)?, name_mapping, false)?;
+
+        assert_eq!(expanded, sequences);
+        assert_eq!(expanded["Müller_2019"], b"ATGCA".to_vec());
+        assert_eq!(expanded["Ångström_01"], b"ATGCA".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip_with_duplicates() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATG-CA".to_vec(),
+            "B".to_string(): b"ATG-CA".to_vec(),
+            "C".to_string(): b"ATGGCA".to_vec(),
+        ).collect();
+
+        let collapsed = collapse_sequences(sequences.clone(), false, false)?;
+        let (collapsed_sequences, name_mapping) = build_collapsed_output(collapsed, "seq");
+
+        assert_eq!(collapsed_sequences.len(), 2);
+        verify_roundtrip(&sequences, &collapsed_sequences, &name_mapping, false, false)
+    }
+
+    #[test]
+    fn test_collapse_keeps_input_order() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"TTTT".to_vec(),
+            "B".to_string(): b"CCCC".to_vec(),
+            "C".to_string(): b"TTTT".to_vec(),
+            "D".to_string(): b"AAAA".to_vec(),
+            "E".to_string(): b"GGGG".to_vec(),
+            "F".to_string(): b"CCCC".to_vec(),
+        ).collect();
+
+        let collapsed = collapse_sequences(sequences, false, false)?;
+        let (collapsed_sequences, name_mapping) = build_collapsed_output(collapsed, "c");
+
+        let names: Vec<&str> = collapsed_sequences.keys().map(String::as_str).collect();
+        let expected_names = ["c_0000_0002", "c_0001_0002", "c_0002_0001", "c_0003_0001"];
+        assert_eq!(names, expected_names);
+        assert!(name_mapping.keys().eq(collapsed_sequences.keys()));
+        let seqs: Vec<&[u8]> = collapsed_sequences.values().map(Vec::as_slice).collect();
+        assert_eq!(seqs, [b"TTTT", b"CCCC", b"AAAA", b"GGGG"]);
+        assert_eq!(name_mapping["c_0001_0002"], ["B", "F"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_collapsed_count() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATGCA".to_vec(),
+            "B".to_string(): b"ATGCA".to_vec(),
+            "C".to_string(): b"ATGCA".to_vec(),
+        ).collect();
+
+        let collapsed = collapse_sequences(sequences, false, false)?;
+        let (collapsed_sequences, _) = build_collapsed_output(collapsed, "my_prefix");
+        let seq_name = collapsed_sequences.keys().next().unwrap();
+        assert_eq!(collapsed_count(seq_name), Some(3));
+
+        assert_eq!(collapsed_count("seq_0001_12345"), Some(12345));
+        for seq_name in [
+            "0001_0042",
+            "seq_0042",
+            "seq_0001_0000",
+            "seq_one_0042",
+            "seq_0001_",
+        ] {
+            assert_eq!(collapsed_count(seq_name), None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip_with_stripped_gaps() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATG-CA".to_vec(),
+            "B".to_string(): b"ATGC-A".to_vec(),
+        ).collect();
+
+        let collapsed = collapse_sequences(sequences.clone(), true, false)?;
+        let (collapsed_sequences, name_mapping) = build_collapsed_output(collapsed, "seq");
+
+        assert_eq!(collapsed_sequences.len(), 1);
+        verify_roundtrip(&sequences, &collapsed_sequences, &name_mapping, true, false)
+    }
+
+    #[test]
+    fn test_mask_unresolved() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATGNNNCA".to_vec(),
+            "B".to_string(): b"ATGNCA".to_vec(),
+            "C".to_string(): b"ATGNNCAA".to_vec(),
+            "D".to_string(): b"MKXXL".to_vec(),
+            "E".to_string(): b"MKXL".to_vec(),
+        ).collect();
+
+        assert_eq!(collapse_sequences(sequences.clone(), false, false)?.len(), 5);
+
+        let collapsed = collapse_sequences(sequences.clone(), false, true)?;
+        assert_eq!(collapsed.len(), 3);
+        assert_eq!(collapsed[&b"ATGNCA".to_vec()].names, ["A", "B"]);
+        assert_eq!(collapsed[&b"MKXL".to_vec()].names, ["D", "E"]);
+
+        // The first member's sequence is written, at its full length.
+        let (collapsed_sequences, name_mapping) = build_collapsed_output(collapsed, "seq");
+        assert_eq!(collapsed_sequences["seq_0000_0002"], b"ATGNNNCA".to_vec());
+        assert_eq!(collapsed_sequences["seq_0002_0002"], b"MKXXL".to_vec());
+        verify_roundtrip(&sequences, &collapsed_sequences, &name_mapping, false, true)
+    }
+
+    #[test]
+    fn test_mask_unresolved_matches_any_base() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATGNCA".to_vec(),
+            "B".to_string(): b"ATGACA".to_vec(),
+            "C".to_string(): b"ATNACA".to_vec(),
+            "D".to_string(): b"ATGACT".to_vec(),
+            "E".to_string(): b"ATGACN".to_vec(),
+        ).collect();
+
+        let uncollapsed = collapse_sequences(sequences.clone(), false, false)?;
+        assert_eq!(uncollapsed.len(), 5);
+
+        // E could join either group, and joins the first.
+        let collapsed = collapse_sequences(sequences.clone(), false, true)?;
+        assert_eq!(collapsed.len(), 2);
+        assert_eq!(collapsed[0].names, ["A", "B", "C", "E"]);
+        assert_eq!(collapsed[0].sequence, b"ATGNCA".to_vec());
+        assert_eq!(collapsed[1].names, ["D"]);
+
+        let (collapsed_sequences, name_mapping) = build_collapsed_output(collapsed, "seq");
+        verify_roundtrip(&sequences, &collapsed_sequences, &name_mapping, false, true)
+    }
+
+    #[test]
+    fn test_roundtrip_detects_broken_mapping() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATGCA".to_vec(),
+            "B".to_string(): b"ATGCA".to_vec(),
+        ).collect();
+
+        let collapsed = collapse_sequences(sequences.clone(), false, false)?;
+        let (collapsed_sequences, mut name_mapping) = build_collapsed_output(collapsed, "seq");
+        name_mapping.values_mut().for_each(|names| names.truncate(1));
+
+        assert!(
+            verify_roundtrip(&sequences, &collapsed_sequences, &name_mapping, false, false)
+                .is_err()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::tools::get_consensus::{
+    AmbiguityMode, ConsensusThresholds, build_consensus, sequences_to_matrix,
+};
+use crate::utils::codon_tables::GAP_CHAR;
+use crate::utils::fasta_utils::{load_fasta, load_single_sequence};
+use crate::utils::output::{create_output_file, finish_csv};
+use crate::utils::summary::RunSummary;
+use anyhow::{Result, bail};
+use colored::Colorize;
+use nalgebra::DMatrix;
+use std::path::PathBuf;
+
+/// How closely one sequence in an MSA matches the consensus.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ConsensusIdentity {
+    /// The number of columns where neither the sequence nor the consensus has a gap.
+    pub(crate) aligned_columns: usize,
+    /// The number of those columns where the sequence has the consensus character.
+    pub(crate) matches: usize,
+}
+
+impl ConsensusIdentity {
+    /// The percentage of aligned columns that match, or 0 if no columns are aligned.
+    pub(crate) fn percent_identity(&self) -> f64 {
+        if self.aligned_columns == 0 {
+            0.0
+        } else {
+            100.0 * self.matches as f64 / self.aligned_columns as f64
+        }
+    }
+}
+
+/// Compare each row of the MSA to the consensus, column by column, skipping columns where either
+/// has a gap.
+pub(crate) fn identity_to_consensus(
+    msa: &DMatrix<u8>,
+    consensus: &[u8],
+) -> Result<Vec<ConsensusIdentity>> {
+    if consensus.len() != msa.ncols() {
+        bail!(
+            "The consensus has length {}, but the alignment has length {}.",
+            consensus.len(),
+            msa.ncols()
+        );
+    }
+
+    Ok(msa
+        .row_iter()
+        .map(|row| {
+            let aligned = row.iter().zip(consensus).filter(|(base, consensus_base)| {
+                **base != GAP_CHAR && **consensus_base != GAP_CHAR
+            });
+            let (aligned_columns, matches) = aligned.fold((0, 0), |(columns, matches), (a, b)| {
+                (columns + 1, matches + usize::from(a == b))
+            });
+            ConsensusIdentity {
+                aligned_columns,
+                matches,
+            }
+        })
+        .collect())
+}
+
+pub fn run(
+    input_msa: &PathBuf,
+    output_file: &PathBuf,
+    consensus_file: Option<&PathBuf>,
+    ambiguity_mode: Option<AmbiguityMode>,
+    seed: Option<u64>,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    log::info!(
+        "{}",
+        format!(
+            "This is 'consensus-identity' version {}",
+            env!("CARGO_PKG_VERSION")
+        )
+        .bold()
+        .bright_green()
+    );
+
+    log::info!("Reading input MSA {:?}", input_msa);
+    let seqs_map = load_fasta(input_msa)?;
+    summary.input(input_msa);
+    summary.count("read", seqs_map.len());
+
+    let (seq_names, seqs): (Vec<String>, Vec<Vec<u8>>) =
+        seqs_map.into_iter().unzip();
+    let msa = sequences_to_matrix(&seqs)?;
+
+    let consensus = match (consensus_file, ambiguity_mode) {
+        (Some(consensus_file), _) => {
+            log::info!("Reading the consensus from {:?}", consensus_file);
+            summary.input(consensus_file);
+            load_single_sequence(consensus_file)?
+        }
+        (None, Some(ambiguity_mode)) => {
+            log::info!("Building the consensus of the alignment.");
+            build_consensus(&msa, ambiguity_mode, ConsensusThresholds::default(), seed)?
+        }
+        (None, None) => bail!("Either a consensus file or an ambiguity mode is needed."),
+    };
+
+    let identities = identity_to_consensus(&msa, &consensus)?;
+
+    log::info!("Writing percent identities to {:?}", output_file);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(create_output_file(output_file)?);
+    writer.write_record(["seq_name", "aligned_columns", "matches", "percent_identity"])?;
+    for (seq_name, identity) in seq_names.iter().zip(&identities) {
+        writer.write_record([
+            seq_name.clone(),
+            identity.aligned_columns.to_string(),
+            identity.matches.to_string(),
+            format!("{:.2}", identity.percent_identity()),
+        ])?;
+    }
+    finish_csv(writer)?;
+    summary.output(output_file);
+    summary.count("written", identities.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_to_consensus() -> Result<()> {
+        let seqs: Vec<Vec<u8>> = vec![b"ATGCA-".to_vec(), b"ATGGA-".to_vec(), b"A-GCAT".to_vec()];
+        let msa = sequences_to_matrix(&seqs)?;
+
+        let identities = identity_to_consensus(&msa, b"ATGCA-")?;
+
+        assert_eq!(
+            identities[0],
+            ConsensusIdentity {
+                aligned_columns: 5,
+                matches: 5
+            }
+        );
+        assert_eq!(identities[1].matches, 4);
+        assert_eq!(identities[1].percent_identity(), 80.0);
+        // The gap in the sequence and the gap in the consensus are both skipped.
+        assert_eq!(
+            identities[2],
+            ConsensusIdentity {
+                aligned_columns: 4,
+                matches: 4
+            }
+        );
+
+        assert!(identity_to_consensus(&msa, b"ATG").is_err());
+        Ok(())
+    }
+}
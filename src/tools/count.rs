@@ -0,0 +1,90 @@
+use crate::error::PursError;
+use crate::utils::fasta_utils::open_fasta;
+use crate::utils::summary::RunSummary;
+use anyhow::Result;
+use colored::Colorize;
+use std::path::PathBuf;
+
+/// The number of records in a FASTA file and the total length of their sequences.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FastaCounts {
+    pub(crate) records: usize,
+    /// Every character in the sequences, including gaps and ambiguity codes.
+    pub(crate) bases: usize,
+}
+
+/// Count the records and bases in a FASTA file one record at a time, without keeping the
+/// sequences in memory.
+pub(crate) fn count_fasta(file_path: &PathBuf) -> Result<FastaCounts, PursError> {
+    let mut counts = FastaCounts::default();
+
+    for (idx, result) in open_fasta(file_path)?.records().enumerate() {
+        let record = result.map_err(|error| PursError::Parse {
+            path: file_path.clone(),
+            record: idx + 1,
+            error,
+        })?;
+        counts.records += 1;
+        counts.bases += record.seq().len();
+    }
+
+    Ok(counts)
+}
+
+pub fn run(input_files: &[PathBuf], summary: &mut RunSummary) -> Result<()> {
+    log::info!(
+        "{}",
+        format!("This is 'count' version {}", env!("CARGO_PKG_VERSION"))
+            .bold()
+            .bright_cyan()
+    );
+
+    let mut total = FastaCounts::default();
+
+    println!("file\trecords\tbases");
+    for input_file in input_files {
+        log::info!("Counting records in {:?}", input_file);
+        let counts = count_fasta(input_file)?;
+        summary.input(input_file);
+        println!(
+            "{}\t{}\t{}",
+            input_file.display(),
+            counts.records,
+            counts.bases
+        );
+        total.records += counts.records;
+        total.bases += counts.bases;
+    }
+
+    if input_files.len() > 1 {
+        println!("total\t{}\t{}", total.records, total.bases);
+    }
+
+    summary.count("read", total.records);
+    summary.count("bases", total.bases);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempPath;
+
+    #[test]
+    fn test_count_fasta() -> Result<()> {
+        let path = TempPath::new("count.fasta");
+        std::fs::write(&path, ">A\nATG-CA\n>B\nAT\nGC\n>C\n\n")?;
+
+        let counts = count_fasta(&path)?;
+
+        assert_eq!(
+            counts,
+            FastaCounts {
+                records: 3,
+                bases: 10
+            }
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use crate::tools::align_pair::{AlignmentType, PairwiseScoring, align_pair};
+use crate::utils::codon_tables::GAP_CHAR;
+use crate::utils::fasta_utils::{
+    FastaRecords, FastaWriter, load_fasta, load_single_sequence, write_fasta_sequences,
+};
+use crate::utils::progress::Progress;
+use crate::utils::summary::RunSummary;
+use anyhow::Result;
+use bio::alignment::AlignmentOperation;
+use colored::Colorize;
+use itertools::Itertools;
+use std::path::PathBuf;
+
+/// The largest internal indel in a read's alignment to the reference, taken as the junction of a
+/// chimera. Positions are 0-based on the degapped read: for a deletion `start == end`, and for an
+/// insertion `start..end` are the inserted bases.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Breakpoint {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) indel_length: usize,
+}
+
+impl Breakpoint {
+    /// The header description for a flagged read. The breakpoint is reported 1-based.
+    pub(crate) fn description(&self) -> String {
+        format!("breakpoint={} indel={}", self.start + 1, self.indel_length)
+    }
+}
+
+/// Align a (degapped) read semiglobally to the reference and return its largest indel of at least
+/// `min_indel` bases that has aligned bases on both sides. Overhangs at either end of the read
+/// are not internal, so they never count.
+pub(crate) fn find_breakpoint(
+    read: &[u8],
+    reference: &[u8],
+    scoring: &PairwiseScoring,
+    min_indel: usize,
+) -> Option<Breakpoint> {
+    let alignment = align_pair(read, reference, scoring, AlignmentType::Semiglobal, false);
+
+    let mut read_pos = alignment.xstart;
+    let mut seen_aligned = false;
+    let mut pending: Option<Breakpoint> = None;
+    let mut best: Option<Breakpoint> = None;
+
+    for (op, group) in &alignment.operations.iter().chunk_by(|op| **op) {
+        let length = group.count();
+        match op {
+            AlignmentOperation::Match | AlignmentOperation::Subst => {
+                // The indel before this block is internal, since there were aligned bases before it.
+                if let Some(indel) = pending.take()
+                    && best
+                        .as_ref()
+                        .is_none_or(|best| indel.indel_length > best.indel_length)
+                {
+                    best = Some(indel);
+                }
+                seen_aligned = true;
+                read_pos += length;
+            }
+            AlignmentOperation::Del | AlignmentOperation::Ins => {
+                let read_length = if op == AlignmentOperation::Ins {
+                    length
+                } else {
+                    0
+                };
+                if seen_aligned && length >= min_indel {
+                    pending = Some(Breakpoint {
+                        start: read_pos,
+                        end: read_pos + read_length,
+                        indel_length: length,
+                    });
+                } else {
+                    pending = None;
+                }
+                read_pos += read_length;
+            }
+            AlignmentOperation::Xclip(clip) => read_pos += clip,
+            AlignmentOperation::Yclip(_) => {}
+        }
+    }
+
+    best
+}
+
+/// Reads split by whether they look chimeric, with the breakpoint found in each flagged read.
+#[derive(Default)]
+pub(crate) struct DeChimeraResult {
+    pub(crate) kept: FastaRecords,
+    pub(crate) chimeras: Vec<(String, Vec<u8>, Breakpoint)>,
+}
+
+pub(crate) fn de_chimera(
+    sequences: FastaRecords,
+    reference: &[u8],
+    min_indel: usize,
+) -> DeChimeraResult {
+    let mut degapped_reference = reference.to_vec();
+    degapped_reference.retain(|&nt| nt != GAP_CHAR);
+    let scoring = PairwiseScoring::default();
+
+    let mut result = DeChimeraResult::default();
+
+    let mut progress = Progress::new(sequences.len());
+    for (seq_name, mut seq) in sequences {
+        seq.retain(|&nt| nt != GAP_CHAR);
+        match find_breakpoint(&seq, &degapped_reference, &scoring, min_indel) {
+            Some(breakpoint) => {
+                log::debug!("{} looks chimeric: {}", seq_name, breakpoint.description());
+                result.chimeras.push((seq_name, seq, breakpoint));
+            }
+            None => {
+                result.kept.insert(seq_name, seq);
+            }
+        }
+        progress.tick();
+    }
+
+    result
+}
+
+pub fn run(
+    input_file: &PathBuf,
+    reference_file: &PathBuf,
+    output_file: &PathBuf,
+    chimera_output: &PathBuf,
+    min_indel: usize,
+    split: bool,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    log::info!(
+        "{}",
+        format!("This is 'de-chimera' version {}", env!("CARGO_PKG_VERSION"))
+            .bold()
+            .bright_green()
+    );
+
+    let reference = load_single_sequence(reference_file)?;
+    summary.input(reference_file);
+
+    log::info!("Reading input file {:?}", input_file);
+    let sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+
+    let result = de_chimera(sequences, &reference, min_indel);
+    log::info!(
+        "{} reads kept, {} flagged as chimeric (an internal indel of at least {} bases).",
+        result.kept.len(),
+        result.chimeras.len(),
+        min_indel
+    );
+
+    log::info!("Writing kept reads to {:?}", output_file);
+    write_fasta_sequences(output_file, &result.kept)?;
+    summary.output(output_file);
+    summary.count("written", result.kept.len());
+
+    log::info!("Writing chimeric reads to {:?}", chimera_output);
+    let mut writer = FastaWriter::new(chimera_output)?;
+    for (seq_name, seq, breakpoint) in &result.chimeras {
+        let description = breakpoint.description();
+        if split {
+            // Bases inserted at the junction are in neither part.
+            writer.write_record(
+                &format!("{}_1", seq_name),
+                Some(&description),
+                &seq[..breakpoint.start],
+            )?;
+            writer.write_record(
+                &format!("{}_2", seq_name),
+                Some(&description),
+                &seq[breakpoint.end..],
+            )?;
+        } else {
+            writer.write_record(seq_name, Some(&description), seq)?;
+        }
+    }
+    writer.finish()?;
+    summary.output(chimera_output);
+    summary.count("chimeric", result.chimeras.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &[u8] = b"ATGGGTGCGAGAGCGTCAGTATTAAGCGGG";
+    const MIDDLE: &[u8] = b"CCCGCCCGGCCGCGCCCGGGCCCGCGCCCC";
+    const LAST: &[u8] = b"TTAATATAATTAAATAATTTATATAAATTA";
+
+    #[test]
+    fn test_find_breakpoint() {
+        let reference = [FIRST, MIDDLE, LAST].concat();
+        let scoring = PairwiseScoring::default();
+
+        // The middle of the reference is missing, so the read jumps from FIRST to LAST.
+        let deletion = [FIRST, LAST].concat();
+        assert_eq!(
+            find_breakpoint(&deletion, &reference, &scoring, 20),
+            Some(Breakpoint {
+                start: 30,
+                end: 30,
+                indel_length: 30,
+            })
+        );
+        assert_eq!(find_breakpoint(&deletion, &reference, &scoring, 31), None);
+
+        // A whole read from the reference, or one that overhangs its end, is not chimeric.
+        assert_eq!(find_breakpoint(&reference, &reference, &scoring, 20), None);
+        let overhang = [MIDDLE, LAST, FIRST].concat();
+        assert_eq!(
+            find_breakpoint(&overhang, &[MIDDLE, LAST].concat(), &scoring, 20),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_breakpoint_insertion() {
+        let reference = [FIRST, LAST].concat();
+
+        let insertion = [FIRST, MIDDLE, LAST].concat();
+        assert_eq!(
+            find_breakpoint(&insertion, &reference, &PairwiseScoring::default(), 20),
+            Some(Breakpoint {
+                start: 30,
+                end: 60,
+                indel_length: 30,
+            })
+        );
+    }
+}
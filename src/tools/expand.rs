@@ -1,11 +1,16 @@
+use crate::utils::checksum::write_checksums;
 use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
-use anyhow::{Context, Result};
+use crate::utils::summary::RunSummary;
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
+use indexmap::IndexMap;
 use serde_json::from_reader;
-use std::collections::HashMap;
 use std::fs::File;
 use std::path::PathBuf;
-type NewToOldNameMapping = HashMap<String, Vec<String>>;
+
+/// The names of the original sequences behind each collapsed sequence, in the order collapse wrote
+/// the collapsed sequences.
+pub(crate) type NewToOldNameMapping = IndexMap<String, Vec<String>>;
 
 pub fn uncollapse_sequences(
     collapsed_seqs: FastaRecords,
@@ -36,11 +41,87 @@ pub fn uncollapse_sequences(
     Ok(expanded_seqs)
 }
 
+/// How expanded sequences differ from the original sequences they were collapsed from.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct RoundTripReport {
+    /// Original names that don't reappear in the expanded sequences.
+    pub(crate) missing: Vec<String>,
+    /// Original names that reappear with a different sequence.
+    pub(crate) changed: Vec<String>,
+    /// Expanded names that aren't in the original sequences, such as collapsed sequences that
+    /// were kept under their collapsed name with `include_missing_seqs`.
+    pub(crate) unexpected: Vec<String>,
+}
+
+impl RoundTripReport {
+    pub(crate) fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.changed.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Check that every original sequence reappears in the expanded sequences under the same name and
+/// with an identical sequence. The order of the sequences doesn't matter.
+pub(crate) fn compare_to_original(
+    original_seqs: &FastaRecords,
+    expanded_seqs: &FastaRecords,
+) -> RoundTripReport {
+    let mut report = RoundTripReport::default();
+    for (seq_name, seq) in original_seqs {
+        match expanded_seqs.get(seq_name) {
+            None => report.missing.push(seq_name.clone()),
+            Some(expanded_seq) if expanded_seq != seq => report.changed.push(seq_name.clone()),
+            Some(_) => {}
+        }
+    }
+    report.unexpected = expanded_seqs
+        .keys()
+        .filter(|seq_name| !original_seqs.contains_key(*seq_name))
+        .cloned()
+        .collect();
+
+    report
+}
+
+/// Log every difference in a round-trip report, and fail if there are any.
+fn check_round_trip(report: &RoundTripReport, original_file: &PathBuf) -> Result<()> {
+    for seq_name in &report.missing {
+        log::error!("{} is missing from the expanded sequences.", seq_name);
+    }
+    for seq_name in &report.changed {
+        log::error!("{} was expanded with a different sequence.", seq_name);
+    }
+    for seq_name in &report.unexpected {
+        log::error!(
+            "{} was expanded, but is not in {:?}.",
+            seq_name,
+            original_file
+        );
+    }
+
+    if !report.is_ok() {
+        bail!(
+            "The expanded sequences don't match {:?}: {} missing, {} changed and {} unexpected.",
+            original_file,
+            report.missing.len(),
+            report.changed.len(),
+            report.unexpected.len()
+        );
+    }
+    log::info!(
+        "All sequences in {:?} were expanded unchanged.",
+        original_file
+    );
+    Ok(())
+}
+
 pub fn run(
     input_file: &PathBuf,
     name_mapping_file: &PathBuf,
     output_file: &PathBuf,
     include_missing_seqs: bool,
+    checksum_output: Option<&PathBuf>,
+    original_file: Option<&PathBuf>,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -55,14 +136,95 @@ pub fn run(
 
     let collapsed_sequences = load_fasta(input_file)
         .with_context(|| format!("Failed to read sequences from {:?}", input_file))?;
+    summary.input(input_file);
+    summary.input(name_mapping_file);
+    summary.count("read", collapsed_sequences.len());
 
     let name_mapping: NewToOldNameMapping = from_reader(File::open(name_mapping_file)?)
         .with_context(|| format!("Failed to read name mapping from {:?}", name_mapping_file))?;
 
+    if original_file.is_some() {
+        // Original sequences behind these names can't be expanded, so they'll be reported as
+        // missing. Say where they went.
+        for (collapsed_seq_name, old_seq_names) in &name_mapping {
+            if !collapsed_sequences.contains_key(collapsed_seq_name) {
+                log::warn!(
+                    "{:?} is in the name mapping but not in {:?}, so its {} sequences aren't expanded.",
+                    collapsed_seq_name,
+                    input_file,
+                    old_seq_names.len()
+                );
+            }
+        }
+    }
+
     let expanded_sequences =
         uncollapse_sequences(collapsed_sequences, name_mapping, include_missing_seqs)?;
 
     write_fasta_sequences(output_file, &expanded_sequences)?;
+    summary.output(output_file);
+    summary.count("written", expanded_sequences.len());
+
+    if let Some(checksum_output) = checksum_output {
+        log::info!("Writing sequence checksums to {:?}", checksum_output);
+        write_checksums(checksum_output, &expanded_sequences)?;
+        summary.output(checksum_output);
+    }
+
+    if let Some(original_file) = original_file {
+        log::info!("Comparing the expanded sequences to {:?}", original_file);
+        let original_sequences = load_fasta(original_file)
+            .with_context(|| format!("Failed to read sequences from {:?}", original_file))?;
+        summary.input(original_file);
+        let report = compare_to_original(&original_sequences, &expanded_sequences);
+        summary.count("missing", report.missing.len());
+        summary.count("changed", report.changed.len());
+        check_round_trip(&report, original_file)?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use velcro::map_iter;
+
+    #[test]
+    fn test_compare_to_original() -> Result<()> {
+        let original: FastaRecords = map_iter!(
+            "A".to_string(): b"ATGCA".to_vec(),
+            "B".to_string(): b"ATGCA".to_vec(),
+            "C".to_string(): b"ATGGG".to_vec(),
+            "D".to_string(): b"TTTTT".to_vec(),
+        ).collect();
+        let collapsed: FastaRecords = map_iter!(
+            "seq_0000_0002".to_string(): b"ATGCA".to_vec(),
+            "seq_0001_0001".to_string(): b"ATGGA".to_vec(),
+            "seq_0002_0001".to_string(): b"CCCCC".to_vec(),
+        ).collect();
+        // seq_0003_0001 (for D) isn't in the collapsed sequences, and seq_0002_0001 isn't in the
+        // mapping.
+        let name_mapping: NewToOldNameMapping = map_iter!(
+            "seq_0000_0002".to_string(): vec!["B".to_string(), "A".to_string()],
+            "seq_0001_0001".to_string(): vec!["C".to_string()],
+            "seq_0003_0001".to_string(): vec!["D".to_string()],
+        ).collect();
+
+        let expanded = uncollapse_sequences(collapsed.clone(), name_mapping.clone(), false)?;
+        let report = compare_to_original(&original, &expanded);
+        assert_eq!(report.missing, vec!["D".to_string()]);
+        assert_eq!(report.changed, vec!["C".to_string()]);
+        assert!(report.unexpected.is_empty());
+        assert!(!report.is_ok());
+
+        let expanded = uncollapse_sequences(collapsed, name_mapping, true)?;
+        let report = compare_to_original(&original, &expanded);
+        assert_eq!(report.unexpected, vec!["seq_0002_0001".to_string()]);
+
+        let mut expanded = original.clone();
+        expanded.reverse();
+        assert!(compare_to_original(&original, &expanded).is_ok());
+        Ok(())
+    }
+}
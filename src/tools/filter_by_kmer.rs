@@ -1,5 +1,8 @@
+use crate::utils::checksum::write_checksums;
 use crate::utils::codon_tables::AMBIGUOUS_NT_LOOKUP;
-use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
+use crate::utils::fasta_utils::{load_fastas, write_fasta_sequences, FastaRecords};
+use crate::utils::output::{create_output_file, finish_csv};
+use crate::utils::summary::RunSummary;
 use anyhow::{bail, Result};
 use colored::Colorize;
 use std::path::PathBuf;
@@ -92,7 +95,7 @@ fn fmt_match(m: Option<bool>) -> String {
 }
 
 fn write_report(report_file: &PathBuf, rows: &[FilterReportRow]) -> Result<()> {
-    let mut writer = csv::Writer::from_path(report_file)?;
+    let mut writer = csv::Writer::from_writer(create_output_file(report_file)?);
     writer.write_record(["seq_name", "start_match", "end_match", "kept"])?;
 
     for row in rows {
@@ -104,17 +107,19 @@ fn write_report(report_file: &PathBuf, rows: &[FilterReportRow]) -> Result<()> {
         ])?;
     }
 
-    writer.flush()?;
-    Ok(())
+    finish_csv(writer)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
-    input_file: &PathBuf,
+    input_files: &[PathBuf],
     output_file: &PathBuf,
     report_file: Option<&PathBuf>,
     rejected_seq_output: Option<&PathBuf>,
     start_kmers: Option<&[Vec<u8>]>,
     end_kmers: Option<&[Vec<u8>]>,
+    checksum_output: Option<&PathBuf>,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -126,21 +131,36 @@ pub fn run(
         .bright_yellow()
     );
 
-    log::info!("Reading input file {:?}", input_file);
-    let sequences = load_fasta(input_file)?;
+    log::info!("Reading input files {:?}", input_files);
+    let sequences = load_fastas(input_files)?;
+    for input_file in input_files {
+        summary.input(input_file);
+    }
+    summary.count("read", sequences.len());
     let (kept_sequences, rejected_sequences, report_rows) =
         filter_by_kmer(sequences, start_kmers, end_kmers)?;
 
     write_fasta_sequences(output_file, &kept_sequences)?;
+    summary.output(output_file);
+    summary.count("written", kept_sequences.len());
+    summary.count("rejected", rejected_sequences.len());
+
+    if let Some(checksum_output) = checksum_output {
+        log::info!("Writing sequence checksums to {:?}", checksum_output);
+        write_checksums(checksum_output, &kept_sequences)?;
+        summary.output(checksum_output);
+    }
 
     if let Some(rejected_seq_output) = rejected_seq_output {
         log::info!("Writing rejected sequences to {:?}", rejected_seq_output);
         write_fasta_sequences(rejected_seq_output, &rejected_sequences)?;
+        summary.output(rejected_seq_output);
     }
 
     if let Some(report_file) = report_file {
         log::info!("Writing filter report to {:?}", report_file);
         write_report(report_file, &report_rows)?;
+        summary.output(report_file);
     }
 
     Ok(())
@@ -149,7 +169,7 @@ pub fn run(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use velcro::hash_map;
+    use velcro::map_iter;
 
     #[test]
     fn test_bases_compatible_exact_match() {
@@ -204,10 +224,10 @@ mod tests {
 
     #[test]
     fn test_filter_by_kmer_start_only() -> Result<()> {
-        let sequences: FastaRecords = hash_map!(
+        let sequences: FastaRecords = map_iter!(
             "A".to_string(): b"ATGACGT".to_vec(),
             "B".to_string(): b"GTGACGT".to_vec(),
-        );
+        ).collect();
 
         let start_kmers = vec![b"ATG".to_vec()];
         let (kept, rejected, report) =
@@ -228,10 +248,10 @@ mod tests {
 
     #[test]
     fn test_filter_by_kmer_end_only() -> Result<()> {
-        let sequences: FastaRecords = hash_map!(
+        let sequences: FastaRecords = map_iter!(
             "A".to_string(): b"ATGACGTAA".to_vec(),
             "B".to_string(): b"ATGACGTAC".to_vec(),
-        );
+        ).collect();
 
         let end_kmers = vec![b"TAA".to_vec(), b"TAG".to_vec(), b"TGA".to_vec()];
         let (kept, rejected, _) = filter_by_kmer(sequences, None, Some(&end_kmers))?;
@@ -246,14 +266,14 @@ mod tests {
 
     #[test]
     fn test_filter_by_kmer_start_and_end() -> Result<()> {
-        let sequences: FastaRecords = hash_map!(
+        let sequences: FastaRecords = map_iter!(
             // Passes both checks.
             "A".to_string(): b"ATGACGTAA".to_vec(),
             // Fails end check only.
             "B".to_string(): b"ATGACGTAC".to_vec(),
             // Fails start check only.
             "C".to_string(): b"GTGACGTAA".to_vec(),
-        );
+        ).collect();
 
         let start_kmers = vec![b"ATG".to_vec()];
         let end_kmers = vec![b"TAA".to_vec(), b"TAG".to_vec(), b"TGA".to_vec()];
@@ -271,9 +291,9 @@ mod tests {
 
     #[test]
     fn test_filter_by_kmer_fails_all_candidates() -> Result<()> {
-        let sequences: FastaRecords = hash_map!(
+        let sequences: FastaRecords = map_iter!(
             "A".to_string(): b"ATGACGTCC".to_vec(),
-        );
+        ).collect();
 
         let end_kmers = vec![b"TAA".to_vec(), b"TAG".to_vec(), b"TGA".to_vec()];
         let (kept, rejected, _) = filter_by_kmer(sequences, None, Some(&end_kmers))?;
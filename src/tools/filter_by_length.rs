@@ -1,10 +1,13 @@
-use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
+use crate::utils::checksum::write_checksums;
+use crate::utils::fasta_utils::{load_fastas, write_fasta_sequences, FastaRecords};
+use crate::utils::output::{create_output_file, finish_csv};
 use anyhow::{bail, Result};
 use colored::Colorize;
 use std::fmt;
 use std::path::PathBuf;
 use std::str::FromStr;
 use crate::utils::codon_tables::GAP_CHAR;
+use crate::utils::summary::RunSummary;
 
 pub enum LengthThreshold {
     Fixed(usize),
@@ -74,7 +77,7 @@ fn threshold_value(lengths: &[usize], threshold: &LengthThreshold) -> f64 {
             let mut sorted_lengths = lengths.to_vec();
             sorted_lengths.sort_unstable();
             let mid = sorted_lengths.len() / 2;
-            if sorted_lengths.len() % 2 == 0 {
+            if sorted_lengths.len().is_multiple_of(2) {
                 (sorted_lengths[mid - 1] + sorted_lengths[mid]) as f64 / 2f64
             } else {
                 sorted_lengths[mid] as f64
@@ -146,7 +149,7 @@ pub(crate) fn filter_by_length(
 }
 
 fn write_report(report_file: &PathBuf, rows: &[FilterReportRow]) -> Result<()> {
-    let mut writer = csv::Writer::from_path(report_file)?;
+    let mut writer = csv::Writer::from_writer(create_output_file(report_file)?);
     writer.write_record(["seq_name", "length", "filter_result"])?;
 
     for row in rows {
@@ -157,17 +160,19 @@ fn write_report(report_file: &PathBuf, rows: &[FilterReportRow]) -> Result<()> {
         ])?;
     }
 
-    writer.flush()?;
-    Ok(())
+    finish_csv(writer)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
-    input_file: &PathBuf,
+    input_files: &[PathBuf],
     output_file: &PathBuf,
     report_file: Option<&PathBuf>,
     rejected_seq_output: Option<&PathBuf>,
     range: LengthRange,
     exclude_gaps: bool,
+    checksum_output: Option<&PathBuf>,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -179,20 +184,35 @@ pub fn run(
         .bright_yellow()
     );
 
-    log::info!("Reading input file {:?}", input_file);
-    let sequences = load_fasta(input_file)?;
+    log::info!("Reading input files {:?}", input_files);
+    let sequences = load_fastas(input_files)?;
+    for input_file in input_files {
+        summary.input(input_file);
+    }
+    summary.count("read", sequences.len());
     let (kept_sequences, rejected_sequences, report_rows) = filter_by_length(sequences, range, exclude_gaps)?;
 
     write_fasta_sequences(output_file, &kept_sequences)?;
+    summary.output(output_file);
+    summary.count("written", kept_sequences.len());
+    summary.count("rejected", rejected_sequences.len());
+
+    if let Some(checksum_output) = checksum_output {
+        log::info!("Writing sequence checksums to {:?}", checksum_output);
+        write_checksums(checksum_output, &kept_sequences)?;
+        summary.output(checksum_output);
+    }
 
     if let Some(rejected_seq_output) = rejected_seq_output {
         log::info!("Writing rejected sequences to {:?}", rejected_seq_output);
         write_fasta_sequences(rejected_seq_output, &rejected_sequences)?;
+        summary.output(rejected_seq_output);
     }
 
     if let Some(report_file) = report_file {
         log::info!("Writing filter report to {:?}", report_file);
         write_report(report_file, &report_rows)?;
+        summary.output(report_file);
     }
 
     Ok(())
@@ -201,7 +221,7 @@ pub fn run(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use velcro::hash_map;
+    use velcro::map_iter;
 
     fn center_only(center: LengthThreshold) -> LengthRange {
         LengthRange {
@@ -213,11 +233,11 @@ mod tests {
 
     #[test]
     fn test_fixed_threshold() -> Result<()> {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "A".to_string(): vec![b'A'; 5],
             "B".to_string(): vec![b'A'; 10],
             "C".to_string(): vec![b'A'; 15],
-        );
+        ).collect();
 
         let (output, rejected, report) =
             filter_by_length(input_seqs, center_only(LengthThreshold::Fixed(10)), false)?;
@@ -243,10 +263,10 @@ mod tests {
 
     #[test]
     fn test_gap_exclusion() -> Result<()> {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "A".to_string(): vec![b'A', b'T', b'-', b'-', b'G'],
             "B".to_string(): vec![b'A'; 10],
-        );
+        ).collect();
 
         let (output, _, _) =
             filter_by_length(input_seqs.clone(), center_only(LengthThreshold::Fixed(4)), false)?;
@@ -267,11 +287,11 @@ mod tests {
 
     #[test]
     fn test_median_threshold_odd_count() -> Result<()> {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "A".to_string(): vec![b'A'; 5],
             "B".to_string(): vec![b'A'; 10],
             "C".to_string(): vec![b'A'; 15],
-        );
+        ).collect();
 
         // Median length is 10.
         let (output, _, _) = filter_by_length(input_seqs, center_only(LengthThreshold::Median), false)?;
@@ -285,12 +305,12 @@ mod tests {
 
     #[test]
     fn test_median_threshold_even_count() -> Result<()> {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "A".to_string(): vec![b'A'; 5],
             "B".to_string(): vec![b'A'; 10],
             "C".to_string(): vec![b'A'; 20],
             "D".to_string(): vec![b'A'; 25],
-        );
+        ).collect();
 
         // Median length is (10 + 20) / 2 = 15.
         let (output, _, _) = filter_by_length(input_seqs, center_only(LengthThreshold::Median), false)?;
@@ -304,11 +324,11 @@ mod tests {
 
     #[test]
     fn test_mean_threshold() -> Result<()> {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "A".to_string(): vec![b'A'; 5],
             "B".to_string(): vec![b'A'; 10],
             "C".to_string(): vec![b'A'; 15],
-        );
+        ).collect();
 
         // Mean length is 10.
         let (output, _, _) = filter_by_length(input_seqs, center_only(LengthThreshold::Mean), false)?;
@@ -328,11 +348,11 @@ mod tests {
 
     #[test]
     fn test_min_tolerance_absolute() -> Result<()> {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "A".to_string(): vec![b'A'; 75],
             "B".to_string(): vec![b'A'; 80],
             "C".to_string(): vec![b'A'; 100],
-        );
+        ).collect();
 
         // length 100, min-tolerance 20 -> keep [80, inf)
         let (output, rejected, _) = filter_by_length(
@@ -356,12 +376,12 @@ mod tests {
 
     #[test]
     fn test_symmetric_percent_tolerance_around_median() -> Result<()> {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "A".to_string(): vec![b'A'; 50],
             "B".to_string(): vec![b'A'; 100],
             "C".to_string(): vec![b'A'; 105],
             "D".to_string(): vec![b'A'; 150],
-        );
+        ).collect();
 
         // Median length is (100 + 105) / 2 = 102.5, 10% tolerance -> keep [92.25, 112.75]
         let (output, rejected, _) = filter_by_length(
@@ -1,50 +1,43 @@
-use crate::utils::fasta_utils::{FastaRecords, load_fasta, write_fasta_sequences};
+use crate::utils::checksum::{sequence_checksum, write_checksum_table};
+use crate::utils::fasta_utils::{FastaRecords, FastaWriter, load_fastas};
+use crate::utils::summary::RunSummary;
 use anyhow::{Result, bail};
 use colored::Colorize;
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use regex::Regex;
 
-pub(crate) fn filter_by_name(
+/// Filter sequences on whether their name matches `pattern` (or doesn't, with `exclude`), passing
+/// each one to `on_record` along with whether it was kept, so they can be written out as they are
+/// filtered.
+pub(crate) fn filter_by_name_each(
     sequences: FastaRecords,
-    pattern: Regex,
+    pattern: &Regex,
     exclude: bool,
-) -> Result<(FastaRecords, FastaRecords)> {
+    mut on_record: impl FnMut(String, Vec<u8>, bool) -> Result<()>,
+) -> Result<()> {
     if sequences.is_empty() {
         bail!("No sequences were provided.")
     }
 
-    let mut kept_sequences = FastaRecords::with_capacity(sequences.len());
-    let mut rejected_sequences = FastaRecords::new();
-
     for (seq_name, seq) in sequences {
-        match pattern.is_match(seq_name.as_str()) {
-            true => {
-                if exclude {
-                    rejected_sequences.insert(seq_name, seq);
-                } else {
-                    kept_sequences.insert(seq_name, seq);
-                }
-            }
-            false => {
-                if exclude {
-                    kept_sequences.insert(seq_name, seq);
-                } else {
-                    rejected_sequences.insert(seq_name, seq);
-                }
-            }
-        }
+        let kept = pattern.is_match(seq_name.as_str()) != exclude;
+        on_record(seq_name, seq, kept)?;
     }
-    Ok((kept_sequences, rejected_sequences))
+
+    Ok(())
 }
 
 pub fn run(
-    input_file: &PathBuf,
+    input_files: &[PathBuf],
     output_file: &PathBuf,
     rejected_seq_output: Option<&PathBuf>,
     pattern_string: String,
     exclude: bool,
+    checksum_output: Option<&PathBuf>,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -56,16 +49,55 @@ pub fn run(
         .bright_white()
     );
 
-    log::info!("Reading input file {:?}", input_file);
-    let sequences = load_fasta(input_file)?;
+    log::info!("Reading input files {:?}", input_files);
+    let sequences = load_fastas(input_files)?;
+    for input_file in input_files {
+        summary.input(input_file);
+    }
+    summary.count("read", sequences.len());
     let pattern = Regex::new(pattern_string.as_str())?;
-    let (kept_sequences, rejected_sequences) = filter_by_name(sequences, pattern, exclude)?;
 
-    write_fasta_sequences(output_file, &kept_sequences)?;
+    let mut kept_writer = FastaWriter::new(output_file)?;
+    let mut rejected_writer = match rejected_seq_output {
+        Some(rejected_seq_output) => {
+            log::info!("Writing rejected sequences to {:?}", rejected_seq_output);
+            Some(FastaWriter::new(rejected_seq_output)?)
+        }
+        None => None,
+    };
+    let mut checksums = BTreeMap::new();
+    let mut num_rejected = 0;
+
+    filter_by_name_each(sequences, &pattern, exclude, |seq_name, seq, kept| {
+        if kept {
+            if checksum_output.is_some() {
+                checksums.insert(seq_name.clone(), sequence_checksum(&seq));
+            }
+            kept_writer.write_record(&seq_name, None, &seq)
+        } else {
+            num_rejected += 1;
+            match rejected_writer.as_mut() {
+                Some(rejected_writer) => rejected_writer.write_record(&seq_name, None, &seq),
+                None => Ok(()),
+            }
+        }
+    })?;
+
+    let num_written = kept_writer.finish()?;
+    summary.output(output_file);
+    summary.count("written", num_written);
+    summary.count("rejected", num_rejected);
+
+    if let Some(checksum_output) = checksum_output {
+        log::info!("Writing sequence checksums to {:?}", checksum_output);
+        write_checksum_table(checksum_output, &checksums)?;
+        summary.output(checksum_output);
+    }
 
-    if let Some(rejected_seq_output) = rejected_seq_output {
-        log::info!("Writing rejected sequences to {:?}", rejected_seq_output);
-        write_fasta_sequences(rejected_seq_output, &rejected_sequences)?;
+    if let (Some(rejected_seq_output), Some(rejected_writer)) = (rejected_seq_output, rejected_writer)
+    {
+        rejected_writer.finish()?;
+        summary.output(rejected_seq_output);
     }
 
     Ok(())
@@ -74,14 +106,33 @@ pub fn run(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
 
     fn to_fasta_records(names: Vec<&str>, sequences: Vec<&str>) -> FastaRecords {
         names
             .iter()
             .zip(sequences.iter())
             .map(|(name, seq)| (name.to_owned().to_owned(), seq.as_bytes().to_vec()))
-            .collect::<HashMap<String, Vec<u8>>>()
+            .collect::<FastaRecords>()
+    }
+
+    fn filter_by_name(
+        sequences: FastaRecords,
+        pattern: Regex,
+        exclude: bool,
+    ) -> Result<(FastaRecords, FastaRecords)> {
+        let mut kept_sequences = FastaRecords::with_capacity(sequences.len());
+        let mut rejected_sequences = FastaRecords::new();
+
+        filter_by_name_each(sequences, &pattern, exclude, |seq_name, seq, kept| {
+            if kept {
+                kept_sequences.insert(seq_name, seq);
+            } else {
+                rejected_sequences.insert(seq_name, seq);
+            }
+            Ok(())
+        })?;
+
+        Ok((kept_sequences, rejected_sequences))
     }
 
     #[test]
@@ -0,0 +1,250 @@
+use crate::utils::codon_tables::GeneticCode;
+use crate::utils::fasta_utils::{load_fasta, reverse_complement, FastaWriter};
+use crate::utils::progress::Progress;
+use crate::utils::summary::RunSummary;
+use crate::utils::translate::{translate, TranslationOptions};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::path::PathBuf;
+
+const START_CODON: &[u8; 3] = b"ATG";
+
+/// An open reading frame found by `find_orfs`. `start` and `end` are the 1-based, inclusive
+/// coordinates of its first and last base on the input sequence, so `start > end` for an ORF on
+/// the reverse strand.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Orf {
+    pub(crate) reading_frame: usize,
+    pub(crate) reverse: bool,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    /// Whether the ORF ends at a stop codon, rather than running off the end of the sequence.
+    pub(crate) terminated: bool,
+    pub(crate) nucleotides: Vec<u8>,
+    /// The translation, ending with the stop character if `terminated`.
+    pub(crate) protein: Vec<u8>,
+}
+
+impl Orf {
+    /// The record ID for an ORF: the sequence name, the strand and 1-based frame as in
+    /// `--six-frame` (`_f1`..`_r3`), then the coordinates, e.g. `contig1_r2_905-611`.
+    pub(crate) fn record_name(&self, seq_name: &str) -> String {
+        let strand = if self.reverse { 'r' } else { 'f' };
+        format!(
+            "{}_{}{}_{}-{}",
+            seq_name,
+            strand,
+            self.reading_frame + 1,
+            self.start,
+            self.end
+        )
+    }
+}
+
+/// Find the ORFs in a sequence's three forward frames (and those of its reverse complement with
+/// `both_strands`): runs from an ATG (in either case) to the next stop in the same frame, with
+/// more than `min_length` codons before the stop. Only the longest ORF ending at each stop is reported, so
+/// an ATG inside an ORF doesn't start another one. With `include_partial`, an ORF that runs off
+/// the end of the sequence without a stop is reported too.
+pub(crate) fn find_orfs(
+    seq: &[u8],
+    min_length: usize,
+    both_strands: bool,
+    include_partial: bool,
+    genetic_code: GeneticCode,
+) -> Result<Vec<Orf>> {
+    let mut orfs = Vec::new();
+
+    for reverse in [false, true] {
+        if reverse && !both_strands {
+            break;
+        }
+        // Soft-masked (lowercase) bases are uppercased, so their start and stop codons are found.
+        let oriented = if reverse {
+            reverse_complement(seq)
+        } else {
+            seq.to_vec()
+        }
+        .to_ascii_uppercase();
+        for reading_frame in 0..3 {
+            let options = TranslationOptions {
+                reading_frame,
+                genetic_code,
+                ..TranslationOptions::default()
+            };
+            // Incomplete codons are dropped, so residue i comes from the codon at
+            // reading_frame + 3 * i.
+            let protein = translate(&oriented, &options)?;
+
+            let mut orf_start = None;
+            let mut add_orf = |first: usize, last: usize, terminated: bool| {
+                let num_codons = last - first + 1 - usize::from(terminated);
+                if num_codons <= min_length {
+                    return;
+                }
+                let nt_start = reading_frame + 3 * first;
+                let nt_end = reading_frame + 3 * (last + 1);
+                let (start, end) = if reverse {
+                    (seq.len() - nt_start, seq.len() - nt_end + 1)
+                } else {
+                    (nt_start + 1, nt_end)
+                };
+                orfs.push(Orf {
+                    reading_frame,
+                    reverse,
+                    start,
+                    end,
+                    terminated,
+                    nucleotides: oriented[nt_start..nt_end].to_vec(),
+                    protein: protein[first..=last].to_vec(),
+                });
+            };
+
+            for (idx, &amino_acid) in protein.iter().enumerate() {
+                let codon_start = reading_frame + 3 * idx;
+                if orf_start.is_none() && &oriented[codon_start..codon_start + 3] == START_CODON {
+                    orf_start = Some(idx);
+                }
+                if amino_acid == options.stop_aa
+                    && let Some(first) = orf_start.take()
+                {
+                    add_orf(first, idx, true);
+                }
+            }
+            if let Some(first) = orf_start.filter(|_| include_partial) {
+                add_orf(first, protein.len() - 1, false);
+            }
+        }
+    }
+
+    Ok(orfs)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    input_file: &PathBuf,
+    output_file: &PathBuf,
+    min_length: usize,
+    both_strands: bool,
+    include_partial: bool,
+    nucleotide: bool,
+    genetic_code: GeneticCode,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    log::info!(
+        "{}",
+        format!("This is 'find-orfs' version {}", env!("CARGO_PKG_VERSION"))
+            .bold()
+            .bright_green()
+    );
+
+    log::info!("Reading input file {:?}", input_file);
+    let sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+
+    log::info!(
+        "Writing ORFs longer than {} codons to {:?}",
+        min_length,
+        output_file
+    );
+    let mut writer = FastaWriter::new(output_file)?;
+    let mut num_partial = 0;
+    let mut progress = Progress::new(sequences.len());
+    for (seq_name, seq) in &sequences {
+        let orfs = find_orfs(seq, min_length, both_strands, include_partial, genetic_code)
+            .with_context(|| format!("Failed to find the ORFs in {}", seq_name))?;
+        for orf in orfs {
+            let record = if nucleotide {
+                &orf.nucleotides
+            } else {
+                &orf.protein
+            };
+            writer.write_record(&orf.record_name(seq_name), None, record)?;
+            num_partial += usize::from(!orf.terminated);
+        }
+        progress.tick();
+    }
+
+    let num_written = writer.finish()?;
+    log::info!(
+        "Found {} ORFs in {} sequences",
+        num_written,
+        sequences.len()
+    );
+    summary.output(output_file);
+    summary.count("written", num_written);
+    if include_partial {
+        summary.count("partial", num_partial);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_orfs() -> Result<()> {
+        // Frame 1 is ATG AAA TGA TAA. Frame 3 has an ATG but no stop after it.
+        let seq = b"ATGAAATGATAAGG";
+        let orfs = find_orfs(seq, 1, false, false, GeneticCode::Standard)?;
+
+        assert_eq!(orfs.len(), 1);
+        assert_eq!(orfs[0].record_name("seq"), "seq_f1_1-9");
+        assert_eq!(orfs[0].nucleotides, b"ATGAAATGA".to_vec());
+        assert_eq!(orfs[0].protein, b"MK*".to_vec());
+        assert!(orfs[0].terminated);
+
+        // The ORF has 2 codons before the stop, so it isn't longer than a minimum of 2.
+        assert!(find_orfs(seq, 2, false, false, GeneticCode::Standard)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_soft_masked_sequence() -> Result<()> {
+        let orfs = find_orfs(b"ccatgaaatgaTAA", 1, false, false, GeneticCode::Standard)?;
+
+        assert_eq!(orfs.len(), 1);
+        assert_eq!(orfs[0].nucleotides, b"ATGAAATGA".to_vec());
+        assert_eq!(orfs[0].record_name("seq"), "seq_f3_3-11");
+        assert_eq!(orfs[0].protein, b"MK*".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_starts_give_one_orf() -> Result<()> {
+        let orfs = find_orfs(b"ATGATGAAATAG", 1, false, false, GeneticCode::Standard)?;
+
+        assert_eq!(orfs.len(), 1);
+        assert_eq!(orfs[0].protein, b"MMK*".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_orfs() -> Result<()> {
+        let seq = b"CCATGAAACCCGG";
+        assert!(find_orfs(seq, 1, false, false, GeneticCode::Standard)?.is_empty());
+
+        let orfs = find_orfs(seq, 1, false, true, GeneticCode::Standard)?;
+        assert_eq!(orfs.len(), 1);
+        assert_eq!(orfs[0].record_name("seq"), "seq_f3_3-11");
+        assert_eq!(orfs[0].protein, b"MKP".to_vec());
+        assert!(!orfs[0].terminated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_strand_coordinates() -> Result<()> {
+        // The reverse complement is GGATGAAATAGCC, with ATG AAA TAG in its frame 3.
+        let seq = b"GGCTATTTCATCC";
+        assert!(find_orfs(seq, 1, false, false, GeneticCode::Standard)?.is_empty());
+
+        let orfs = find_orfs(seq, 1, true, false, GeneticCode::Standard)?;
+        assert_eq!(orfs.len(), 1);
+        assert_eq!(orfs[0].record_name("seq"), "seq_r3_11-3");
+        assert_eq!(orfs[0].nucleotides, b"ATGAAATAG".to_vec());
+        Ok(())
+    }
+}
@@ -1,10 +1,121 @@
+use crate::error::PursError;
+use crate::utils::fasta_utils::FastaWriter;
+use crate::utils::summary::RunSummary;
 use anyhow::{anyhow, Context, Result};
-use bio::io::fasta;
 use colored::Colorize;
-use gb_io::reader::parse_file;
+use gb_io::reader::{parse_file, parse_slice};
+use gb_io::seq::{Feature, Location, Seq};
+use memmap2::Mmap;
+use std::fs::File;
 use std::path::PathBuf;
 
-pub fn run(genbank_file: &PathBuf, output_file: &PathBuf, sequence_name: &String) -> Result<()> {
+/// Find the byte range of the first record in a GenBank file: from the start of its `LOCUS` line
+/// up to and including the `//` line that terminates it. The rest of the file is never scanned.
+pub(crate) fn first_record_bounds(data: &[u8]) -> Option<(usize, usize)> {
+    let mut record_start = None;
+    let mut line_start = 0;
+
+    for line in data.split_inclusive(|&c| c == b'\n') {
+        let line_end = line_start + line.len();
+        if line.starts_with(b"LOCUS") {
+            record_start = Some(line_start);
+        } else if line.starts_with(b"//")
+            && let Some(start) = record_start
+        {
+            return Some((start, line_end));
+        }
+        line_start = line_end;
+    }
+
+    None
+}
+
+/// Memory-map the GenBank file and parse only its first record, rather than reading and parsing
+/// every record in the file.
+fn read_first_record_mmap(genbank_file: &PathBuf) -> Result<Vec<Seq>> {
+    let file = File::open(genbank_file)
+        .with_context(|| format!("Could not open genbank file {:?}", genbank_file))?;
+    // Safety: the file is only read, and is not expected to be modified while we hold the map.
+    let mmap = unsafe { Mmap::map(&file) }
+        .with_context(|| format!("Could not memory-map genbank file {:?}", genbank_file))?;
+
+    let (start, end) = first_record_bounds(&mmap).ok_or_else(|| PursError::NoGenbankRecords {
+        path: genbank_file.clone(),
+    })?;
+    log::debug!(
+        "The first record ends at byte {} of {:?}",
+        end,
+        genbank_file
+    );
+
+    parse_slice(&mmap[start..end]).context("Error parsing genbank file")
+}
+
+/// Find the feature in a GenBank record whose "note" qualifier is `sequence_name`.
+pub fn find_noted_feature<'a>(
+    record: &'a Seq,
+    sequence_name: &str,
+) -> Result<&'a Feature, PursError> {
+    record
+        .features
+        .iter()
+        .find(|feature| {
+            feature
+                .qualifiers
+                .iter()
+                .find(|qualifier| qualifier.0 == "note")
+                .and_then(|note| note.1.as_deref())
+                == Some(sequence_name)
+        })
+        .ok_or_else(|| PursError::MissingFeature {
+            name: sequence_name.to_string(),
+        })
+}
+
+/// The 0-based, end-exclusive span of a feature widened by a flank on each side.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FlankedBounds {
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    /// How many of the requested upstream bases were cut off by the end of the record.
+    pub(crate) upstream_truncated: usize,
+    /// How many of the requested downstream bases were cut off by the end of the record.
+    pub(crate) downstream_truncated: usize,
+}
+
+/// Widen the feature spanning `from..to` by `flank` bases on each side, clamped to a record of
+/// length `seq_len`. On the reverse strand, upstream is after the feature in record coordinates.
+pub(crate) fn flank_bounds(
+    from: usize,
+    to: usize,
+    seq_len: usize,
+    flank: usize,
+    reverse: bool,
+) -> FlankedBounds {
+    let left_truncated = flank.saturating_sub(from);
+    let right_truncated = (to + flank).saturating_sub(seq_len);
+    let (upstream_truncated, downstream_truncated) = if reverse {
+        (right_truncated, left_truncated)
+    } else {
+        (left_truncated, right_truncated)
+    };
+
+    FlankedBounds {
+        from: from.saturating_sub(flank),
+        to: (to + flank).min(seq_len),
+        upstream_truncated,
+        downstream_truncated,
+    }
+}
+
+pub fn run(
+    genbank_file: &PathBuf,
+    output_file: &PathBuf,
+    sequence_name: &str,
+    use_mmap: bool,
+    flank: usize,
+    summary: &mut RunSummary,
+) -> Result<()> {
     log::info!(
         "{}",
         format!(
@@ -17,42 +128,47 @@ pub fn run(genbank_file: &PathBuf, output_file: &PathBuf, sequence_name: &String
     );
 
     log::info!("Reading file {:?}", genbank_file);
-    let genbank_contents = parse_file(genbank_file).context("Error parsing genbank file")?;
-
-    // Complex series of steps here.
-    // Iterate through the genbank features, looking to see which ones has a feature with the "note"
-    // parameter. If it has a note param, then check if the value of that param is set.
-    // If the param is set, then check if its value is equal to the name of the sequence we want
-    let seq_of_interest = genbank_contents
-        .get(0)
-        .expect("Genbank file was empty")
-        .features
-        .to_owned()
-        .into_iter()
-        .find(|feature| {
-            if let Some(note_feature) = feature
-                .clone()
-                .qualifiers
-                .iter()
-                .find(|qualifier| qualifier.0 == "note")
-            {
-                if let Some(note_name) = note_feature.1.as_ref() {
-                    note_name == sequence_name
-                } else {
-                    false
-                }
-            } else {
-                false
-            }
-        }).with_context(|| anyhow!("We were not able to find a feature in the genbank file that had a 'note' field which matched {}", sequence_name.bold()))?;
+    let genbank_contents = if use_mmap {
+        read_first_record_mmap(genbank_file)?
+    } else {
+        parse_file(genbank_file).context("Error parsing genbank file")?
+    };
+    summary.input(genbank_file);
+    summary.count("read", genbank_contents.len());
+
+    let record = genbank_contents
+        .first()
+        .ok_or_else(|| PursError::NoGenbankRecords {
+            path: genbank_file.clone(),
+        })?;
+    let seq_of_interest = find_noted_feature(record, sequence_name)?;
 
     log::debug!("Found sequence of interest! Extracting nucleotide sequence");
 
     let nt_seq = match seq_of_interest.location.clone().find_bounds() {
         Ok(bounds) => {
-            let from_idx = bounds.0 as usize;
-            let to_idx = bounds.1 as usize;
-            genbank_contents[0].seq[from_idx..to_idx].to_vec()
+            let record_seq = &genbank_contents[0].seq;
+            let reverse = matches!(seq_of_interest.location, Location::Complement(_));
+            let flanked = flank_bounds(
+                bounds.0 as usize,
+                bounds.1 as usize,
+                record_seq.len(),
+                flank,
+                reverse,
+            );
+            if flanked.upstream_truncated > 0 {
+                log::warn!(
+                    "The upstream flank was truncated by {} bases at the end of the record.",
+                    flanked.upstream_truncated
+                );
+            }
+            if flanked.downstream_truncated > 0 {
+                log::warn!(
+                    "The downstream flank was truncated by {} bases at the end of the record.",
+                    flanked.downstream_truncated
+                );
+            }
+            record_seq[flanked.from..flanked.to].to_vec()
         }
         Err(e) => {
             anyhow::bail!(
@@ -62,20 +178,86 @@ pub fn run(genbank_file: &PathBuf, output_file: &PathBuf, sequence_name: &String
         }
     };
     log::info!("Successfully extracted nucleotide sequence from main reference.");
-    let output_record =
-        fasta::Record::with_attrs(sequence_name, None, nt_seq.to_ascii_uppercase().as_slice());
-
     log::info!("Writing record to {:?}", output_file);
-    fasta::Writer::to_file(output_file)
-        .with_context(|| anyhow!("Failed to write to file {:?}", output_file))?
-        .write_record(&output_record)
+    let mut writer = FastaWriter::new(output_file)?;
+    writer
+        .write_record(sequence_name, None, &nt_seq.to_ascii_uppercase())
+        .and_then(|_| writer.finish())
         .with_context(|| {
             anyhow!(
                 "Could not write record {:?} to file {:?}",
-                output_record,
+                sequence_name,
                 output_file
             )
         })?;
+    summary.output(output_file);
+    summary.count("written", 1);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_record_bounds() -> Result<()> {
+        let first = b"LOCUS       A 4 bp DNA\nORIGIN\n        1 acgt\n//\n";
+        // Anything after the first record is never looked at, even if it isn't valid GenBank.
+        let data = [first.as_slice(), b"LOCUS       B\nnot a record\n"].concat();
+
+        let (start, end) = first_record_bounds(&data).unwrap();
+        assert_eq!((start, end), (0, first.len()));
+        let records = parse_slice(&data[start..end])?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].seq, b"acgt".to_vec());
+
+        assert_eq!(first_record_bounds(b"LOCUS       A\nORIGIN\n"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flank_bounds() {
+        assert_eq!(
+            flank_bounds(10, 20, 100, 5, false),
+            FlankedBounds {
+                from: 5,
+                to: 25,
+                upstream_truncated: 0,
+                downstream_truncated: 0,
+            }
+        );
+
+        // Only 3 bases are available before the feature, and 2 after it.
+        let flanked = flank_bounds(3, 98, 100, 5, false);
+        assert_eq!((flanked.from, flanked.to), (0, 100));
+        assert_eq!(flanked.upstream_truncated, 2);
+        assert_eq!(flanked.downstream_truncated, 3);
+
+        // On the reverse strand the same span is truncated upstream at the end of the record.
+        let flanked = flank_bounds(3, 98, 100, 5, true);
+        assert_eq!((flanked.from, flanked.to), (0, 100));
+        assert_eq!(flanked.upstream_truncated, 3);
+        assert_eq!(flanked.downstream_truncated, 2);
+    }
+
+    #[test]
+    fn test_find_noted_feature() -> Result<()> {
+        let data = b"LOCUS       TEST                      12 bp    DNA     linear   UNA 01-JAN-1980
+FEATURES             Location/Qualifiers
+     CDS             1..6
+                     /note=\"gag\"
+ORIGIN
+        1 atgaaatagc cc
+//
+";
+        let record = parse_slice(data)?.remove(0);
+
+        assert!(find_noted_feature(&record, "gag").is_ok());
+        assert!(matches!(
+            find_noted_feature(&record, "env"),
+            Err(PursError::MissingFeature { .. })
+        ));
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
+use crate::error::PursError;
+use crate::tools::collapse::collapsed_count;
 use crate::utils;
-use anyhow::{anyhow, Result};
-use bio::io::fasta;
+use crate::utils::codon_tables::{AMBIGUOUS_NT_LOOKUP, GAP_CHAR};
+use crate::utils::fasta_utils::FastaRecords;
+use crate::utils::output::{create_output_file, finish_csv};
+use crate::utils::summary::RunSummary;
+use anyhow::{anyhow, bail, Context, Result};
 use clap::ValueEnum;
 use colored::Colorize;
 use itertools::Itertools;
 use nalgebra::DMatrix;
-use rand::seq::IteratorRandom;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use utils::fasta_utils;
 use utils::translate::find_ambiguity_code;
@@ -19,26 +23,22 @@ pub enum AmbiguityMode {
     MarkN,
 }
 
-pub(crate) fn sequences_to_matrix(sequences: &Vec<Vec<u8>>) -> Result<DMatrix<u8>> {
-    // Check if sequences are empty
+pub fn sequences_to_matrix(sequences: &[Vec<u8>]) -> Result<DMatrix<u8>, PursError> {
     if sequences.is_empty() {
-        return Err(anyhow!(
-            "There are no sequences in the sequence vector passed to the sequence_to_matrix function."
-        ));
+        return Err(PursError::EmptyAlignment);
     }
 
     // Check that all sequences are the same length (this is an MSA)
-    let mut count = 0;
-    for seq in sequences {
-        count = count + 1;
-        if seq.len() != sequences[0].len() {
-            return Err(anyhow!(
-                "Not all sequences in the MSA have the same length. The length of the 1st seq is {} and the length of the {} seq is {}",
-                sequences[0].len(),
-                count,
-                seq.len()
-            ));
-        }
+    if let Some((idx, seq)) = sequences
+        .iter()
+        .enumerate()
+        .find(|(_, seq)| seq.len() != sequences[0].len())
+    {
+        return Err(PursError::AlignmentLengthMismatch {
+            index: idx + 1,
+            expected: sequences[0].len(),
+            found: seq.len(),
+        });
     }
 
     Ok(DMatrix::from_row_slice(
@@ -48,28 +48,228 @@ pub(crate) fn sequences_to_matrix(sequences: &Vec<Vec<u8>>) -> Result<DMatrix<u8
     ))
 }
 
-pub(crate) fn build_consensus(msa: &DMatrix<u8>, ambiguity_mode: AmbiguityMode) -> Result<Vec<u8>> {
+/// The seed used for `AmbiguityMode::Random` when the caller doesn't give one.
+pub(crate) const DEFAULT_SEED: u64 = 42;
+
+/// The random number generator used to break a tie in one column in `AmbiguityMode::Random`.
+/// Each column gets its own stream derived from the seed and the column index, so the choice in a
+/// column doesn't depend on how many random draws were made in other columns, or in what order the
+/// columns were visited.
+fn column_rng(seed: u64, col_idx: usize) -> oorandom::Rand32 {
+    oorandom::Rand32::new_inc(seed, col_idx as u64)
+}
+
+/// How common the most common base in a column must be for the consensus to call it. The
+/// defaults call the most common base in every column.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ConsensusThresholds {
+    /// The base must be in more than this fraction of the sequences.
+    pub min_frequency: f64,
+    /// The base must make up at least this fraction of the non-gap characters in the column.
+    pub min_fraction: f64,
+    /// If set, a column is a gap in the consensus only if more than this fraction of the
+    /// sequences have a gap there. Otherwise its gaps are dropped before counting, so the most
+    /// common base is called even if gaps outnumber it. If not set, gaps are counted like bases.
+    pub gap_threshold: Option<f64>,
+}
+
+/// The seed to break ties with. With `AmbiguityMode::Random` and no `seed`, a random seed is picked
+/// and logged so the run can be repeated. The other modes don't use it.
+pub(crate) fn pick_seed(ambiguity_mode: AmbiguityMode, seed: Option<u64>) -> u64 {
+    match (ambiguity_mode, seed) {
+        (AmbiguityMode::Random, None) => {
+            let seed = rand::random();
+            log::info!(
+                "No seed was given, so the random seed {} was picked. Pass --seed {} to repeat this run.",
+                seed,
+                seed
+            );
+            seed
+        }
+        (_, seed) => seed.unwrap_or(DEFAULT_SEED),
+    }
+}
+
+/// Build the consensus of an MSA from the most common base in each column, with ties resolved by
+/// `ambiguity_mode`, using `seed` for `AmbiguityMode::Random` (see `pick_seed`). Bases that don't
+/// meet the `thresholds` aren't called (see `build_consensus_with_report`).
+pub(crate) fn build_consensus(
+    msa: &DMatrix<u8>,
+    ambiguity_mode: AmbiguityMode,
+    thresholds: ConsensusThresholds,
+    seed: Option<u64>,
+) -> Result<Vec<u8>> {
+    let seed = pick_seed(ambiguity_mode, seed);
+    let (consensus, _) =
+        build_consensus_with_report(msa, ambiguity_mode, None, None, thresholds, seed)?;
+    Ok(consensus)
+}
+
+/// Whether a reference base can be used to break a tie: gaps and ambiguity codes (including N)
+/// cannot.
+fn is_tie_breaking_base(base: u8) -> bool {
+    base != GAP_CHAR && base != b'N' && !AMBIGUOUS_NT_LOOKUP.contains_key(&[base])
+}
+
+/// The fewest most common bases in a column whose combined count is `enough`, sorted by base.
+/// Bases tied with the last one needed are included too.
+fn most_common_bases<'a>(
+    col_count: &HashMap<&'a u8, usize>,
+    enough: impl Fn(usize) -> bool,
+) -> Vec<&'a u8> {
+    let mut bases = Vec::new();
+    let mut total = 0;
+    let mut last_count = None;
+    for (&base, &count) in col_count
+        .iter()
+        .sorted_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)))
+    {
+        if enough(total) && last_count != Some(count) {
+            break;
+        }
+        bases.push(base);
+        total += count;
+        last_count = Some(count);
+    }
+    bases.sort();
+    bases
+}
+
+/// A column of the MSA where the consensus has an IUPAC ambiguity code.
+#[derive(Debug, PartialEq)]
+pub(crate) struct AmbiguousSite {
+    /// The 1-based column in the alignment.
+    pub position: usize,
+    pub code: u8,
+    /// The tied bases that make up the code, with their count in the column, sorted by base.
+    pub bases: Vec<(u8, usize)>,
+}
+
+/// Build a consensus as in `build_consensus`, and also return every column where an ambiguity
+/// code was used in `AmbiguityMode::UseIUPAC`. Ties are broken in favour of the base at the same
+/// column of `reference` (which must be aligned to the MSA), if it is one of the tied bases; ties
+/// the reference can't break fall back to `ambiguity_mode`, and in `AmbiguityMode::Random` are
+/// broken with `seed`.
+///
+/// With `weights` (one for each row of the MSA), each sequence counts that many times in the
+/// column counts, e.g. the number of reads a collapsed sequence stands for.
+///
+/// With a `gap_threshold`, columns with more gaps than that are gaps in the consensus, and the
+/// gaps in the others are dropped before anything else is counted.
+///
+/// A column whose most common base doesn't meet the `thresholds` isn't called: it gets the
+/// ambiguity code of the fewest most common bases that together meet them with
+/// `AmbiguityMode::UseIUPAC` (or N, if they include a gap), and N in the other modes. The
+/// `min_frequency` is a fraction of all the (weighted) sequences, while the `min_fraction` only
+/// counts the non-gap characters in the column and doesn't apply where a gap is most common.
+pub(crate) fn build_consensus_with_report(
+    msa: &DMatrix<u8>,
+    ambiguity_mode: AmbiguityMode,
+    reference: Option<&[u8]>,
+    weights: Option<&[usize]>,
+    thresholds: ConsensusThresholds,
+    seed: u64,
+) -> Result<(Vec<u8>, Vec<AmbiguousSite>)> {
+    if let Some(reference) = reference
+        && reference.len() != msa.ncols()
+    {
+        bail!(
+            "The reference has length {}, but the alignment has length {}.",
+            reference.len(),
+            msa.ncols()
+        );
+    }
+
+    if let Some(weights) = weights
+        && weights.len() != msa.nrows()
+    {
+        bail!(
+            "There are {} weights, but the alignment has {} sequences.",
+            weights.len(),
+            msa.nrows()
+        );
+    }
+    let weight_of = |row: usize| weights.map_or(1, |weights| weights[row]);
+    let total_weight: usize = (0..msa.nrows()).map(weight_of).sum();
+
     let mut consensus: Vec<u8> = Vec::new();
+    let mut ambiguous_sites = Vec::new();
 
-    for col in msa.column_iter() {
+    for (col_idx, col) in msa.column_iter().enumerate() {
         let mut col_count = HashMap::new();
 
-        for item in col {
-            *col_count.entry(item).or_insert(0) += 1;
+        for (row, item) in col.iter().enumerate() {
+            *col_count.entry(item).or_insert(0) += weight_of(row);
+        }
+
+        if let Some(gap_threshold) = thresholds.gap_threshold {
+            let gap_count = col_count.remove(&GAP_CHAR).unwrap_or(0);
+            if col_count.is_empty() || gap_count as f64 > gap_threshold * total_weight as f64 {
+                consensus.push(GAP_CHAR);
+                continue;
+            }
         }
 
         // Attempt to get the item in the column with the largest count, or if there
         // are multiple then get the set.
         let largest_items: Vec<&u8> = col_count
             .iter()
-            .max_set_by(|a, b| a.1.cmp(&b.1))
+            .max_set_by(|a, b| a.1.cmp(b.1))
             .iter()
             .cloned()
             .map(|(k, _v)| *k)
             .collect();
 
+        let largest_count = col_count[largest_items[0]] as f64;
+        let min_count = thresholds.min_frequency * total_weight as f64;
+        let base_count: HashMap<&u8, usize> = col_count
+            .iter()
+            .filter(|(item, _)| ***item != GAP_CHAR)
+            .map(|(&item, &count)| (item, count))
+            .collect();
+        let min_base_count = thresholds.min_fraction * base_count.values().sum::<usize>() as f64;
+
+        let uncalled_bases = if largest_count <= min_count {
+            Some(most_common_bases(&col_count, |total| {
+                total as f64 > min_count
+            }))
+        } else if !largest_items.contains(&&GAP_CHAR) && largest_count < min_base_count {
+            Some(most_common_bases(&base_count, |total| {
+                total as f64 >= min_base_count
+            }))
+        } else {
+            None
+        };
+        if let Some(bases) = uncalled_bases {
+            let code = match ambiguity_mode {
+                AmbiguityMode::UseIUPAC => find_ambiguity_code(&bases).map(|code| code[0]),
+                _ => None,
+            };
+            match code {
+                Some(code) => {
+                    consensus.push(code);
+                    ambiguous_sites.push(AmbiguousSite {
+                        position: col_idx + 1,
+                        code,
+                        bases: bases
+                            .iter()
+                            .map(|&&base| (base, col_count[&base]))
+                            .collect(),
+                    });
+                }
+                None => consensus.push(b'N'),
+            }
+            continue;
+        }
+
+        let reference_base = reference
+            .map(|reference| reference[col_idx])
+            .filter(|base| is_tie_breaking_base(*base) && largest_items.contains(&base));
+
         if largest_items.len() == 1 {
             consensus.push(*largest_items[0]);
+        } else if let Some(reference_base) = reference_base {
+            consensus.push(reference_base);
         } else {
             match ambiguity_mode {
                 AmbiguityMode::UseIUPAC => {
@@ -82,6 +282,15 @@ pub(crate) fn build_consensus(msa: &DMatrix<u8>, ambiguity_mode: AmbiguityMode)
                         }
                         Some(code) => {
                             consensus.push(code[0]);
+                            ambiguous_sites.push(AmbiguousSite {
+                                position: col_idx + 1,
+                                code: code[0],
+                                bases: largest_items
+                                    .iter()
+                                    .map(|&&base| (base, col_count[&base]))
+                                    .sorted()
+                                    .collect(),
+                            });
                         }
                     }
                 }
@@ -98,8 +307,10 @@ pub(crate) fn build_consensus(msa: &DMatrix<u8>, ambiguity_mode: AmbiguityMode)
                     consensus.push(first_item);
                 }
                 AmbiguityMode::Random => {
-                    let random_item = largest_items.iter().choose(&mut rand::rng()).unwrap();
-                    consensus.push(**random_item);
+                    // Sort first, since the order of the tied bases follows the HashMap.
+                    let tied_items: Vec<u8> = largest_items.iter().map(|x| **x).sorted().collect();
+                    let idx = column_rng(seed, col_idx).rand_range(0..tied_items.len() as u32);
+                    consensus.push(tied_items[idx as usize]);
                 }
                 AmbiguityMode::MarkN => {
                     consensus.push(b'N');
@@ -108,24 +319,235 @@ pub(crate) fn build_consensus(msa: &DMatrix<u8>, ambiguity_mode: AmbiguityMode)
         }
     }
 
-    Ok(consensus)
+    Ok((consensus, ambiguous_sites))
+}
+
+/// The characters counted in each column of a position frequency matrix, in the order of its
+/// columns.
+pub(crate) const PFM_CHARS: [u8; 6] = [b'A', b'C', b'G', b'T', b'N', GAP_CHAR];
+
+/// Count the characters in `PFM_CHARS` in each column of an MSA, so there is one row per column.
+/// Lowercase bases are counted with uppercase ones, and other characters aren't counted. With
+/// `weights`, each sequence counts as many times as its weight.
+pub(crate) fn position_frequency_matrix(
+    msa: &DMatrix<u8>,
+    weights: Option<&[usize]>,
+) -> Vec<[usize; PFM_CHARS.len()]> {
+    msa.column_iter()
+        .map(|col| {
+            let mut counts = [0; PFM_CHARS.len()];
+            for (row, item) in col.iter().enumerate() {
+                let item = item.to_ascii_uppercase();
+                if let Some(idx) = PFM_CHARS.iter().position(|&pfm_char| pfm_char == item) {
+                    counts[idx] += weights.map_or(1, |weights| weights[row]);
+                }
+            }
+            counts
+        })
+        .collect()
+}
+
+/// The name of the group that sequences missing from the groups file are placed in.
+pub(crate) const UNGROUPED_NAME: &str = "other";
+
+/// Read a headerless, tab-separated file mapping sequence IDs to group names.
+pub(crate) fn read_groups(groups_file: &PathBuf) -> Result<HashMap<String, String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .from_path(groups_file)
+        .with_context(|| format!("Could not open groups file {:?}", groups_file))?;
+
+    let mut groups = HashMap::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        match (record.get(0), record.get(1)) {
+            (Some(seq_id), Some(group)) => {
+                groups.insert(seq_id.to_string(), group.to_string());
+            }
+            _ => bail!(
+                "Line {} of the groups file {:?} should have a sequence ID and a group separated by a tab.",
+                line + 1,
+                groups_file
+            ),
+        }
+    }
+
+    Ok(groups)
+}
+
+/// Split sequences into their groups. Sequences without a group are placed in the
+/// `UNGROUPED_NAME` group, or dropped if `skip_ungrouped` is set. It is an error for a sequence
+/// to be ungrouped when the groups file has a group of that name, since the two would be merged.
+pub(crate) fn partition_by_group(
+    sequences: FastaRecords,
+    groups: &HashMap<String, String>,
+    skip_ungrouped: bool,
+) -> Result<BTreeMap<String, FastaRecords>> {
+    let mut partitioned: BTreeMap<String, FastaRecords> = BTreeMap::new();
+    let has_ungrouped_name = groups.values().any(|group| group == UNGROUPED_NAME);
+
+    for (seq_id, seq) in sequences {
+        let group = match groups.get(&seq_id) {
+            Some(group) => group.clone(),
+            None if skip_ungrouped => {
+                log::warn!("Skipping {} since it is not in any group.", seq_id);
+                continue;
+            }
+            None if has_ungrouped_name => bail!(
+                "{} is not in any group, but the groups file already has a group named {:?}, which is where ungrouped sequences go. Rename that group or pass --skip-ungrouped.",
+                seq_id,
+                UNGROUPED_NAME
+            ),
+            None => UNGROUPED_NAME.to_string(),
+        };
+        partitioned.entry(group).or_default().insert(seq_id, seq);
+    }
+
+    Ok(partitioned)
 }
 
-fn write_consensus(output_file: &PathBuf, seq_name: &str, seq: &Vec<u8>) -> Result<()> {
-    let mut writer = fasta::Writer::to_file(output_file)?;
-    let mut degapped_seq = seq.clone();
-    let gap_char = b'-';
-    degapped_seq.retain(|&val| val != gap_char);
-    writer.write(seq_name, None, &degapped_seq)?;
+/// Write the consensus sequences, degapped unless `keep_gaps` is set, in which case they keep the
+/// length of the alignment.
+fn write_consensus(
+    output_file: &PathBuf,
+    consensus_seqs: &[(String, Vec<u8>)],
+    keep_gaps: bool,
+) -> Result<()> {
+    let mut writer = fasta_utils::FastaWriter::new(output_file)?;
+    for (seq_name, seq) in consensus_seqs {
+        let mut output_seq = seq.to_vec();
+        if !keep_gaps {
+            output_seq.retain(|&val| val != GAP_CHAR);
+        }
+        writer.write_record(seq_name, None, &output_seq)?;
+    }
 
+    writer.finish()?;
     Ok(())
 }
 
+/// The files written with `--both-outputs`: the degapped consensus, then the alignment-length one.
+pub(crate) fn both_output_paths(prefix: &str) -> [(PathBuf, bool); 2] {
+    [
+        (PathBuf::from(format!("{}.degapped.fa", prefix)), false),
+        (PathBuf::from(format!("{}.aligned.fa", prefix)), true),
+    ]
+}
+
+/// Write a TSV with a row for every ambiguity code in the consensus sequences, listing the bases
+/// it stands for and how often each occurred in that column.
+fn write_ambiguity_report(
+    report_file: &PathBuf,
+    ambiguous_sites: &[(String, Vec<AmbiguousSite>)],
+) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(create_output_file(report_file)?);
+    writer.write_record(["consensus_name", "position", "code", "bases", "counts"])?;
+
+    for (consensus_name, sites) in ambiguous_sites {
+        for site in sites {
+            writer.write_record([
+                consensus_name.clone(),
+                site.position.to_string(),
+                (site.code as char).to_string(),
+                site.bases.iter().map(|(base, _)| *base as char).join(","),
+                site.bases.iter().map(|(_, count)| count).join(","),
+            ])?;
+        }
+    }
+
+    finish_csv(writer)
+}
+
+/// The consensus of one alignment, with its ambiguous sites and position frequency matrix.
+type AlignmentConsensus = (Vec<u8>, Vec<AmbiguousSite>, Vec<[usize; PFM_CHARS.len()]>);
+
+/// Write a TSV with a row for every column of each alignment, giving its 1-based position and the
+/// count of each of `PFM_CHARS` in it.
+fn write_pfm(pfm_file: &PathBuf, pfms: &[(String, Vec<[usize; PFM_CHARS.len()]>)]) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(create_output_file(pfm_file)?);
+    let mut header = vec!["consensus_name".to_string(), "position".to_string()];
+    header.extend(PFM_CHARS.map(|pfm_char| (pfm_char as char).to_string()));
+    writer.write_record(&header)?;
+
+    for (consensus_name, pfm) in pfms {
+        for (col_idx, counts) in pfm.iter().enumerate() {
+            let mut record = vec![consensus_name.clone(), (col_idx + 1).to_string()];
+            record.extend(counts.iter().map(|count| count.to_string()));
+            writer.write_record(&record)?;
+        }
+    }
+
+    finish_csv(writer)
+}
+
+/// The weight of each sequence with `--weighted`: the number of reads `collapse` merged into it,
+/// from the count at the end of its ID. Sequences whose ID doesn't end in a count get a weight of 1.
+fn collapsed_weights(seqs: &FastaRecords) -> Vec<usize> {
+    seqs.keys()
+        .map(|seq_name| {
+            collapsed_count(seq_name).unwrap_or_else(|| {
+                log::warn!(
+                    "{} doesn't end in a collapse count, so it has a weight of 1.",
+                    seq_name
+                );
+                1
+            })
+        })
+        .collect()
+}
+
+fn consensus_of(
+    seqs: FastaRecords,
+    ambiguity_mode: AmbiguityMode,
+    reference: Option<&[u8]>,
+    weighted: bool,
+    thresholds: ConsensusThresholds,
+    seed: u64,
+) -> Result<AlignmentConsensus> {
+    let weights = weighted.then(|| collapsed_weights(&seqs));
+    let seqs: Vec<Vec<u8>> = seqs.into_values().collect();
+    let seq_matrix = sequences_to_matrix(&seqs)?;
+    log::info!(
+        "Successfully created a {} by {} matrix of sequences.",
+        seq_matrix.nrows(),
+        seq_matrix.ncols()
+    );
+
+    let (consensus, ambiguous_sites) = build_consensus_with_report(
+        &seq_matrix,
+        ambiguity_mode,
+        reference,
+        weights.as_deref(),
+        thresholds,
+        seed,
+    )?;
+    let pfm = position_frequency_matrix(&seq_matrix, weights.as_deref());
+    Ok((consensus, ambiguous_sites, pfm))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     input_seqs_aligned: &PathBuf,
-    output_path: &PathBuf,
-    consensus_name: &String,
+    output_path: Option<&PathBuf>,
+    both_outputs: Option<&str>,
+    consensus_name: &str,
     ambiguity_mode: AmbiguityMode,
+    groups_file: Option<&PathBuf>,
+    skip_ungrouped: bool,
+    reference_file: Option<&PathBuf>,
+    ambiguity_report: Option<&PathBuf>,
+    pfm_output: Option<&PathBuf>,
+    weighted: bool,
+    min_frequency: f64,
+    min_fraction: f64,
+    gap_threshold: Option<f64>,
+    seed: Option<u64>,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -137,24 +559,129 @@ pub fn run(
         .bright_green()
     );
 
+    if !(0.0..1.0).contains(&min_frequency) {
+        bail!(
+            "The minimum frequency must be at least 0 and less than 1, not {}.",
+            min_frequency
+        );
+    }
+    if !(0.0..=1.0).contains(&min_fraction) {
+        bail!(
+            "The minimum fraction must be between 0 and 1, not {}.",
+            min_fraction
+        );
+    }
+    if let Some(gap_threshold) = gap_threshold
+        && !(0.0..=1.0).contains(&gap_threshold)
+    {
+        bail!(
+            "The gap threshold must be between 0 and 1, not {}.",
+            gap_threshold
+        );
+    }
+    let thresholds = ConsensusThresholds {
+        min_frequency,
+        min_fraction,
+        gap_threshold,
+    };
+
+    let seed = pick_seed(ambiguity_mode, seed);
+
     log::info!("Reading input FASTA file: {:?}", input_seqs_aligned);
     let seqs_map = fasta_utils::load_fasta(input_seqs_aligned)?;
-    let seqs: Vec<Vec<u8>> = seqs_map.into_iter().map(|(_, seq)| seq).collect();
+    summary.input(input_seqs_aligned);
+    summary.count("read", seqs_map.len());
+    log::info!("Successfully read {} sequences into memory.", seqs_map.len());
 
-    log::info!("Successfully read {} sequences into memory.", seqs.len());
+    let reference = match reference_file {
+        Some(reference_file) => {
+            log::info!("Breaking ties with the reference in {:?}", reference_file);
+            summary.input(reference_file);
+            Some(fasta_utils::load_single_sequence(reference_file)?)
+        }
+        None => None,
+    };
 
-    let seq_matrix = sequences_to_matrix(&seqs)?;
-    log::info!(
-        "Successfully created a {} by {} matrix of sequences.",
-        seq_matrix.nrows(),
-        seq_matrix.ncols()
-    );
+    if ambiguity_report.is_some() && !matches!(ambiguity_mode, AmbiguityMode::UseIUPAC) {
+        log::warn!(
+            "Ambiguity codes are only used with use-iupac, so the ambiguity report will be empty."
+        );
+    }
+
+    let consensus_seqs = match groups_file {
+        None => {
+            log::info!("Generating consensus.");
+            vec![(
+                consensus_name.to_string(),
+                consensus_of(
+                    seqs_map,
+                    ambiguity_mode,
+                    reference.as_deref(),
+                    weighted,
+                    thresholds,
+                    seed,
+                )?,
+            )]
+        }
+        Some(groups_file) => {
+            log::info!("Reading sequence groups from {:?}", groups_file);
+            let groups = read_groups(groups_file)?;
+            summary.input(groups_file);
+            let mut consensus_seqs = Vec::new();
+            for (group, seqs) in partition_by_group(seqs_map, &groups, skip_ungrouped)? {
+                log::info!("Generating consensus for group {} ({} sequences).", group, seqs.len());
+                let consensus = consensus_of(
+                    seqs,
+                    ambiguity_mode,
+                    reference.as_deref(),
+                    weighted,
+                    thresholds,
+                    seed,
+                )?;
+                consensus_seqs.push((group, consensus));
+            }
+            consensus_seqs
+        }
+    };
+
+    let (consensus_seqs, ambiguous_sites, pfms): (Vec<_>, Vec<_>, Vec<_>) = consensus_seqs
+        .into_iter()
+        .map(|(name, (consensus, sites, pfm))| {
+            (
+                (name.clone(), consensus),
+                (name.clone(), sites),
+                (name, pfm),
+            )
+        })
+        .multiunzip();
 
-    log::info!("Generating consensus.");
-    let consensus = build_consensus(&seq_matrix, ambiguity_mode)?;
+    let outputs: Vec<(PathBuf, bool)> = match (output_path, both_outputs) {
+        (Some(output_path), None) => vec![(output_path.clone(), false)],
+        (None, Some(prefix)) => both_output_paths(prefix).to_vec(),
+        _ => bail!("Exactly one of an output file or a --both-outputs prefix is needed."),
+    };
+    for (output_path, keep_gaps) in &outputs {
+        log::info!("Writing consensus to {:?}", output_path);
+        write_consensus(output_path, &consensus_seqs, *keep_gaps)?;
+        summary.output(output_path);
+    }
+    summary.count("written", consensus_seqs.len());
 
-    log::info!("Writing consensus to {:?}", output_path);
-    write_consensus(output_path, consensus_name, &consensus)?;
+    if let Some(ambiguity_report) = ambiguity_report {
+        log::info!("Writing ambiguity report to {:?}", ambiguity_report);
+        write_ambiguity_report(ambiguity_report, &ambiguous_sites)?;
+        summary.output(ambiguity_report);
+        summary.count(
+            "ambiguous_sites",
+            ambiguous_sites.iter().map(|(_, sites)| sites.len()).sum(),
+        );
+    }
+
+    if let Some(pfm_output) = pfm_output {
+        log::info!("Writing position frequency matrix to {:?}", pfm_output);
+        write_pfm(pfm_output, &pfms)?;
+        summary.output(pfm_output);
+    }
 
     Ok(())
 }
@@ -163,14 +690,17 @@ pub fn run(
 mod tests {
 
     use super::*;
+    use crate::utils::test_utils::{TempPath, temp_path};
 
     #[test]
     fn test_ambiguities() {
         let input: Vec<Vec<u8>> = vec![vec![b'T', b'T', b'G'], vec![b'A', b'T', b'G']];
         let matrix = sequences_to_matrix(&input).unwrap();
-        let consensus_iupac = build_consensus(&matrix, AmbiguityMode::UseIUPAC).unwrap();
-        let consensus_first = build_consensus(&matrix, AmbiguityMode::First).unwrap();
-        let consensus_markn = build_consensus(&matrix, AmbiguityMode::MarkN).unwrap();
+        let consensus =
+            |mode| build_consensus(&matrix, mode, ConsensusThresholds::default(), None).unwrap();
+        let consensus_iupac = consensus(AmbiguityMode::UseIUPAC);
+        let consensus_first = consensus(AmbiguityMode::First);
+        let consensus_markn = consensus(AmbiguityMode::MarkN);
 
         assert_eq!(
             String::from("WTG"),
@@ -187,4 +717,342 @@ mod tests {
             String::from_utf8(consensus_first).unwrap()
         );
     }
+
+    #[test]
+    fn test_unaligned_sequences() {
+        let input: Vec<Vec<u8>> = vec![b"ATG".to_vec(), b"ATG".to_vec(), b"AT".to_vec()];
+
+        assert!(matches!(
+            sequences_to_matrix(&input),
+            Err(PursError::AlignmentLengthMismatch {
+                index: 3,
+                expected: 3,
+                found: 2
+            })
+        ));
+        assert!(matches!(
+            sequences_to_matrix(&[]),
+            Err(PursError::EmptyAlignment)
+        ));
+    }
+
+    #[test]
+    fn test_reference_breaks_ties() -> Result<()> {
+        let input: Vec<Vec<u8>> = vec![b"TTGA".to_vec(), b"ATCC".to_vec()];
+        let matrix = sequences_to_matrix(&input)?;
+
+        // The reference breaks the ties in the first and third columns, but an N (or any other
+        // ambiguity code) can't, so the last column falls back to the ambiguity mode.
+        let consensus_with = |reference: &[u8]| {
+            build_consensus_with_report(
+                &matrix,
+                AmbiguityMode::MarkN,
+                Some(reference),
+                None,
+                ConsensusThresholds::default(),
+                DEFAULT_SEED,
+            )
+            .map(|(consensus, _)| consensus)
+        };
+        assert_eq!("TTCN", String::from_utf8(consensus_with(b"TACN")?)?);
+        assert_eq!("NTGN", String::from_utf8(consensus_with(b"-AGR")?)?);
+        assert!(consensus_with(b"TA").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ambiguity_report() -> Result<()> {
+        let input: Vec<Vec<u8>> = vec![
+            b"TTGA".to_vec(),
+            b"ATGC".to_vec(),
+            b"ATGC".to_vec(),
+            b"TTGA".to_vec(),
+        ];
+        let matrix = sequences_to_matrix(&input)?;
+
+        let (consensus, sites) = build_consensus_with_report(
+            &matrix,
+            AmbiguityMode::UseIUPAC,
+            None,
+            None,
+            ConsensusThresholds::default(),
+            DEFAULT_SEED,
+        )?;
+        assert_eq!("WTGM", String::from_utf8(consensus)?);
+        assert_eq!(
+            sites,
+            vec![
+                AmbiguousSite {
+                    position: 1,
+                    code: b'W',
+                    bases: vec![(b'A', 2), (b'T', 2)],
+                },
+                AmbiguousSite {
+                    position: 4,
+                    code: b'M',
+                    bases: vec![(b'A', 2), (b'C', 2)],
+                },
+            ]
+        );
+
+        let (_, sites) = build_consensus_with_report(
+            &matrix,
+            AmbiguityMode::MarkN,
+            None,
+            None,
+            ConsensusThresholds::default(),
+            DEFAULT_SEED,
+        )?;
+        assert!(sites.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_ties_are_seeded_per_column() -> Result<()> {
+        let input: Vec<Vec<u8>> = vec![b"ACGTACGT".to_vec(), b"TGCATGCA".to_vec()];
+        let matrix = sequences_to_matrix(&input)?;
+
+        let thresholds = ConsensusThresholds::default();
+        let (consensus, _) =
+            build_consensus_with_report(&matrix, AmbiguityMode::Random, None, None, thresholds, 7)?;
+        let (repeat, _) =
+            build_consensus_with_report(&matrix, AmbiguityMode::Random, None, None, thresholds, 7)?;
+        assert_eq!(consensus, repeat);
+        assert_eq!(
+            build_consensus(&matrix, AmbiguityMode::Random, thresholds, Some(7))?,
+            consensus
+        );
+        for (col_idx, base) in consensus.iter().enumerate() {
+            assert!(*base == input[0][col_idx] || *base == input[1][col_idx]);
+        }
+
+        // A column's pick doesn't depend on the ties in the columns before it.
+        let unanimous: Vec<Vec<u8>> = vec![b"AAAAACGT".to_vec(), b"AAAATGCA".to_vec()];
+        let (partial, _) = build_consensus_with_report(
+            &sequences_to_matrix(&unanimous)?,
+            AmbiguityMode::Random,
+            None,
+            None,
+            ConsensusThresholds::default(),
+            7,
+        )?;
+        assert_eq!(partial[4..], consensus[4..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_frequency() -> Result<()> {
+        let input: Vec<Vec<u8>> = vec![
+            b"ATA".to_vec(),
+            b"ATA".to_vec(),
+            b"CTA".to_vec(),
+            b"CTA".to_vec(),
+            b"GTC".to_vec(),
+            b"TTG".to_vec(),
+        ];
+        let matrix = sequences_to_matrix(&input)?;
+        let thresholds = |min_frequency| ConsensusThresholds {
+            min_frequency,
+            ..Default::default()
+        };
+
+        // Without a threshold the first column is an A/C tie; at 0.6 neither base is called, and
+        // the 4/6 A's in the last column are.
+        assert_eq!(
+            build_consensus(&matrix, AmbiguityMode::MarkN, thresholds(0.0), None)?,
+            b"NTA"
+        );
+        assert_eq!(
+            build_consensus(&matrix, AmbiguityMode::First, thresholds(0.6), None)?,
+            b"NTA"
+        );
+
+        let (consensus, sites) = build_consensus_with_report(
+            &matrix,
+            AmbiguityMode::UseIUPAC,
+            None,
+            None,
+            thresholds(0.6),
+            DEFAULT_SEED,
+        )?;
+        assert_eq!(consensus, b"MTA");
+        assert_eq!(sites[0].bases, vec![(b'A', 2), (b'C', 2)]);
+
+        // At 0.7, the tied C and G are both needed in the last column, and the first is N.
+        assert_eq!(
+            build_consensus(&matrix, AmbiguityMode::UseIUPAC, thresholds(0.7), None)?,
+            b"NTV"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_fraction() -> Result<()> {
+        let input: Vec<Vec<u8>> = vec![
+            b"AAA".to_vec(),
+            b"CAC".to_vec(),
+            b"-AT".to_vec(),
+            b"-CT".to_vec(),
+            b"---".to_vec(),
+        ];
+        let matrix = sequences_to_matrix(&input)?;
+        let consensus =
+            |thresholds| build_consensus(&matrix, AmbiguityMode::MarkN, thresholds, None);
+        let min_fraction = |min_fraction| ConsensusThresholds {
+            min_fraction,
+            ..Default::default()
+        };
+
+        // The 3 A's in the second column are only 3/5 of the sequences, but 3/4 of its bases,
+        // while the T's in the last column are 2/4. Columns where a gap is most common are left
+        // alone.
+        let min_frequency = ConsensusThresholds {
+            min_frequency: 0.6,
+            ..Default::default()
+        };
+        assert_eq!(consensus(min_frequency)?, b"NNN");
+        assert_eq!(consensus(min_fraction(0.6))?, b"-AN");
+
+        // The 2/4 T's in the last column reach 0.5, but not 0.8.
+        assert_eq!(consensus(min_fraction(0.5))?, b"-AT");
+        assert_eq!(consensus(min_fraction(0.8))?, b"-NN");
+        let (consensus, sites) = build_consensus_with_report(
+            &matrix,
+            AmbiguityMode::UseIUPAC,
+            None,
+            None,
+            min_fraction(0.8),
+            DEFAULT_SEED,
+        )?;
+        assert_eq!(consensus, b"-MH");
+        assert_eq!(sites[1].bases, vec![(b'A', 1), (b'C', 1), (b'T', 2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_gap_threshold() -> Result<()> {
+        let input: Vec<Vec<u8>> = vec![
+            b"A-C-".to_vec(),
+            b"A-C-".to_vec(),
+            b"---T".to_vec(),
+            b"--GT".to_vec(),
+            b"---T".to_vec(),
+        ];
+        let matrix = sequences_to_matrix(&input)?;
+        let consensus = |gap_threshold| {
+            let thresholds = ConsensusThresholds {
+                gap_threshold,
+                ..Default::default()
+            };
+            build_consensus(&matrix, AmbiguityMode::MarkN, thresholds, None)
+        };
+
+        // By default the gaps outnumber the A's in the first column, and tie with the C's in the
+        // third.
+        assert_eq!(consensus(None)?, b"--NT");
+        // With a threshold of half of the sequences, the third column (2/5 gaps) is called from
+        // its bases, but the first (3/5 gaps) stays a gap.
+        assert_eq!(consensus(Some(0.5))?, b"--CT");
+        // Only all-gap columns are gaps when gaps are never too many.
+        assert_eq!(consensus(Some(1.0))?, b"A-CT");
+        Ok(())
+    }
+
+    #[test]
+    fn test_position_frequency_matrix() -> Result<()> {
+        let input: Vec<Vec<u8>> = vec![b"AC-Ta".to_vec(), b"ACGNR".to_vec(), b"TC-TA".to_vec()];
+        let matrix = sequences_to_matrix(&input)?;
+
+        let pfm = position_frequency_matrix(&matrix, None);
+        assert_eq!(pfm.len(), matrix.ncols());
+        assert_eq!(pfm[0], [2, 0, 0, 1, 0, 0]);
+        assert_eq!(pfm[2], [0, 0, 1, 0, 0, 2]);
+        assert_eq!(pfm[3], [0, 0, 0, 2, 1, 0]);
+        // The R isn't counted.
+        assert_eq!(pfm[4], [2, 0, 0, 0, 0, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_weighted_consensus() -> Result<()> {
+        let sequences: FastaRecords = velcro::map_iter!(
+            "collapsed_0000_0001".to_string(): b"ACGA".to_vec(),
+            "collapsed_0001_0099".to_string(): b"TCGC".to_vec(),
+        ).collect();
+        let weights = collapsed_weights(&sequences);
+        assert_eq!(weights, vec![1, 99]);
+
+        let seqs: Vec<Vec<u8>> = sequences.into_values().collect();
+        let matrix = sequences_to_matrix(&seqs)?;
+        let consensus = |weights| {
+            build_consensus_with_report(
+                &matrix,
+                AmbiguityMode::First,
+                None,
+                weights,
+                ConsensusThresholds::default(),
+                DEFAULT_SEED,
+            )
+            .map(|(consensus, _)| consensus)
+        };
+        assert_eq!(consensus(None)?, b"ACGA");
+        assert_eq!(consensus(Some(&weights))?, b"TCGC");
+        assert!(consensus(Some(&[1])).is_err());
+
+        let pfm = position_frequency_matrix(&matrix, Some(&weights));
+        assert_eq!(pfm[0], [1, 0, 0, 99, 0, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_consensus_keep_gaps() -> Result<()> {
+        let prefix = temp_path("both-outputs").to_string_lossy().into_owned();
+        let consensus_seqs = vec![("consensus".to_string(), b"AT-G-C".to_vec())];
+        let outputs =
+            both_output_paths(&prefix).map(|(path, keep_gaps)| (TempPath::from(path), keep_gaps));
+
+        for (path, keep_gaps) in &outputs {
+            write_consensus(path, &consensus_seqs, *keep_gaps)?;
+        }
+        let [(degapped_path, _), (aligned_path, _)] = &outputs;
+        let degapped = std::fs::read_to_string(degapped_path)?;
+        let aligned = std::fs::read_to_string(aligned_path)?;
+
+        assert!(degapped_path.to_string_lossy().ends_with(".degapped.fa"));
+        assert_eq!(degapped, ">consensus\nATGC\n");
+        assert_eq!(aligned, ">consensus\nAT-G-C\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_by_group() -> Result<()> {
+        let sequences: FastaRecords = velcro::map_iter!(
+            "A".to_string(): b"ATG".to_vec(),
+            "B".to_string(): b"ATG".to_vec(),
+            "C".to_string(): b"TTG".to_vec(),
+            "D".to_string(): b"CTG".to_vec(),
+        ).collect();
+        let groups: HashMap<String, String> = velcro::hash_map!(
+            "A".to_string(): "subtype_b".to_string(),
+            "B".to_string(): "subtype_b".to_string(),
+            "C".to_string(): "subtype_c".to_string(),
+        );
+
+        let partitioned = partition_by_group(sequences.clone(), &groups, false)?;
+        assert_eq!(partitioned.len(), 3);
+        assert_eq!(partitioned["subtype_b"].len(), 2);
+        assert_eq!(partitioned["subtype_c"]["C"], b"TTG".to_vec());
+        assert_eq!(partitioned[UNGROUPED_NAME]["D"], b"CTG".to_vec());
+
+        let partitioned = partition_by_group(sequences.clone(), &groups, true)?;
+        assert_eq!(partitioned.len(), 2);
+        assert!(!partitioned.contains_key(UNGROUPED_NAME));
+
+        // A real group with the ungrouped name would be merged with D.
+        let mut clashing = groups.clone();
+        clashing.insert("C".to_string(), UNGROUPED_NAME.to_string());
+        assert!(partition_by_group(sequences.clone(), &clashing, false).is_err());
+        assert_eq!(partition_by_group(sequences, &clashing, true)?.len(), 2);
+        Ok(())
+    }
 }
@@ -1,6 +1,9 @@
-use crate::tools::get_consensus::{AmbiguityMode, build_consensus, sequences_to_matrix};
+use crate::tools::get_consensus::{
+    AmbiguityMode, ConsensusThresholds, build_consensus, sequences_to_matrix,
+};
 use crate::utils::codon_tables::GAP_CHAR;
 use crate::utils::fasta_utils::{FastaRecords, load_fasta, write_fasta_sequences};
+use crate::utils::summary::RunSummary;
 use anyhow::{Result, bail};
 use clap::ValueEnum;
 use colored::Colorize;
@@ -48,7 +51,8 @@ pub fn get_most_representative_sequence(
 
     let msa_seqs: Vec<Vec<u8>> = msa.values().cloned().collect();
     let msa_matrix = sequences_to_matrix(&msa_seqs)?;
-    let consensus = build_consensus(&msa_matrix, ambiguity_mode)?;
+    let thresholds = ConsensusThresholds::default();
+    let consensus = build_consensus(&msa_matrix, ambiguity_mode, thresholds, None)?;
 
     let computed_seq_name = match compute_mode {
         ComputeMode::Exact => msa
@@ -81,6 +85,7 @@ pub fn run(
     output_file: &PathBuf,
     ambiguity_mode: AmbiguityMode,
     compute_mode: ComputeMode,
+    summary: &mut RunSummary,
 ) -> anyhow::Result<()> {
     log::info!(
         "{}",
@@ -94,6 +99,8 @@ pub fn run(
 
     log::info!("Reading input file {:?}", input_file);
     let sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
     let representative_seq_name =
         get_most_representative_sequence(&sequences, ambiguity_mode, compute_mode)?;
     log::info!("Most representative sequence: {}", representative_seq_name);
@@ -104,6 +111,8 @@ pub fn run(
     let output_sequences: FastaRecords =
         FastaRecords::from([(representative_seq_name, representative_seq)]);
     write_fasta_sequences(output_file, &output_sequences)?;
+    summary.output(output_file);
+    summary.count("written", output_sequences.len());
 
     Ok(())
 }
@@ -0,0 +1,141 @@
+use crate::utils::fasta_utils::{load_fasta, FastaRecords};
+use crate::utils::output::{create_output_file, finish_csv};
+use crate::utils::progress::Progress;
+use crate::utils::summary::RunSummary;
+use crate::utils::translate::{count_stops, translate_six_frames, TranslationOptions};
+use anyhow::{bail, Result};
+use colored::Colorize;
+use std::path::PathBuf;
+
+pub(crate) struct FrameGuess {
+    pub(crate) seq_name: String,
+    pub(crate) reading_frame: usize,
+    pub(crate) reverse: bool,
+    pub(crate) stop_count: usize,
+}
+
+/// For each sequence, find the reading frame (out of all six) whose translation has the fewest
+/// stop codons. Ties are broken in favour of the forward strand, then the lowest frame.
+pub(crate) fn guess_frames(
+    sequences: &FastaRecords,
+    translation_options: &TranslationOptions,
+) -> Result<Vec<FrameGuess>> {
+    if sequences.is_empty() {
+        bail!("No sequences were provided.")
+    }
+
+    let mut guesses = Vec::with_capacity(sequences.len());
+
+    let mut progress = Progress::new(sequences.len());
+    for (seq_name, seq) in sequences {
+        let best_frame = translate_six_frames(seq, translation_options)?
+            .into_iter()
+            .min_by_key(|translation| count_stops(&translation.protein, translation_options))
+            .expect("There are always six frame translations.");
+
+        guesses.push(FrameGuess {
+            seq_name: seq_name.clone(),
+            reading_frame: best_frame.reading_frame,
+            reverse: best_frame.reverse,
+            stop_count: count_stops(&best_frame.protein, translation_options),
+        });
+        progress.tick();
+    }
+
+    Ok(guesses)
+}
+
+fn write_report(output_file: &PathBuf, guesses: &[FrameGuess]) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(create_output_file(output_file)?);
+    writer.write_record(["seq_name", "reading_frame", "reverse", "stop_count"])?;
+
+    for guess in guesses {
+        writer.write_record([
+            guess.seq_name.as_str(),
+            guess.reading_frame.to_string().as_str(),
+            guess.reverse.to_string().as_str(),
+            guess.stop_count.to_string().as_str(),
+        ])?;
+    }
+
+    finish_csv(writer)
+}
+
+pub fn run(input_file: &PathBuf, output_file: &PathBuf, summary: &mut RunSummary) -> Result<()> {
+    log::info!(
+        "{}",
+        format!("This is 'guess-frame' version {}", env!("CARGO_PKG_VERSION"))
+            .bold()
+            .bright_cyan()
+    );
+
+    log::info!("Reading input file {:?}", input_file);
+    let sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+
+    let translation_options = TranslationOptions {
+        strip_gaps: true,
+        ..TranslationOptions::default()
+    };
+    let guesses = guess_frames(&sequences, &translation_options)?;
+
+    log::info!("Writing frame report to {:?}", output_file);
+    write_report(output_file, &guesses)?;
+    summary.output(output_file);
+    summary.count("written", guesses.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use velcro::map_iter;
+
+    #[test]
+    fn test_guess_forward_frame() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            // Forward frame 0 starts with a stop; frame 1 ("NGQR") has none.
+            "B".to_string(): b"TAATGGCCAAAGG".to_vec(),
+            // No frame has a stop, so the tie is broken in favour of forward frame 0.
+            "A".to_string(): b"ATGGCCAAAGGG".to_vec(),
+        ).collect();
+
+        let guesses = guess_frames(&sequences, &TranslationOptions::default())?;
+
+        // The guesses are in input order.
+        assert_eq!(guesses[0].seq_name, "B");
+        assert_eq!(guesses[0].reading_frame, 1);
+        assert!(!guesses[0].reverse);
+        assert_eq!(guesses[0].stop_count, 0);
+
+        assert_eq!(guesses[1].seq_name, "A");
+        assert_eq!(guesses[1].reading_frame, 0);
+        assert!(!guesses[1].reverse);
+        assert_eq!(guesses[1].stop_count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_guess_reverse_frame() -> Result<()> {
+        // Every forward frame has a stop; reverse frame 0 ("LISY") has none.
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATAGCTAATTAG".to_vec(),
+        ).collect();
+
+        let guesses = guess_frames(&sequences, &TranslationOptions::default())?;
+
+        assert!(guesses[0].reverse);
+        assert_eq!(guesses[0].reading_frame, 0);
+        assert_eq!(guesses[0].stop_count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert!(guess_frames(&FastaRecords::new(), &TranslationOptions::default()).is_err());
+    }
+}
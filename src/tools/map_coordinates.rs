@@ -0,0 +1,229 @@
+use crate::tools::align_pair::{align_pair, AlignmentType, PairwiseScoring};
+use crate::utils::codon_tables::GAP_CHAR;
+use crate::utils::fasta_utils::{load_fasta, load_single_sequence, FastaRecords};
+use crate::utils::output::{create_output_file, finish_csv};
+use crate::utils::progress::Progress;
+use crate::utils::summary::RunSummary;
+use anyhow::{bail, Result};
+use bio::alignment::AlignmentOperation;
+use colored::Colorize;
+use std::path::PathBuf;
+
+/// Where one column of a query-to-reference alignment falls in each sequence. Positions are
+/// 1-based and count ungapped bases.
+#[derive(Debug, PartialEq)]
+pub(crate) struct PositionMapping {
+    /// The query position, or `None` if the reference base is deleted in the query.
+    pub(crate) query_position: Option<usize>,
+    /// The reference position, or for an insertion the reference position it follows (0 if it
+    /// comes before the first reference base).
+    pub(crate) reference_position: usize,
+    /// 0 for a base aligned to the reference, or the 1-based index of the base in its insertion.
+    pub(crate) insertion_index: usize,
+}
+
+/// Format a reference coordinate, offset so the first reference base is `reference_start`.
+/// Insertions are written HXB2-style as the coordinate they follow plus their index, e.g. the
+/// second base inserted after 6225 is "6225+2".
+pub(crate) fn format_reference_position(
+    mapping: &PositionMapping,
+    reference_start: usize,
+) -> String {
+    let position = mapping.reference_position + reference_start - 1;
+    match mapping.insertion_index {
+        0 => position.to_string(),
+        index => format!("{}+{}", position, index),
+    }
+}
+
+/// Align a (degapped) query semiglobally to the (degapped) reference, so the whole query is
+/// placed within it, and map every query base to its reference coordinate. Reference bases deleted
+/// in the query are included with no query position.
+pub(crate) fn map_positions(
+    query: &[u8],
+    reference: &[u8],
+    scoring: &PairwiseScoring,
+) -> Vec<PositionMapping> {
+    let alignment = align_pair(query, reference, scoring, AlignmentType::Semiglobal, false);
+
+    let mut mappings = Vec::new();
+    let mut insertion_index = 0;
+
+    // Path positions are 1-based. For an insertion the reference position is the base before it,
+    // and for a deletion the query position is the base before it.
+    for (query_pos, ref_pos, op) in alignment.path() {
+        match op {
+            AlignmentOperation::Match | AlignmentOperation::Subst => {
+                insertion_index = 0;
+                mappings.push(PositionMapping {
+                    query_position: Some(query_pos),
+                    reference_position: ref_pos,
+                    insertion_index,
+                });
+            }
+            AlignmentOperation::Ins => {
+                insertion_index += 1;
+                mappings.push(PositionMapping {
+                    query_position: Some(query_pos),
+                    reference_position: ref_pos,
+                    insertion_index,
+                });
+            }
+            AlignmentOperation::Del => {
+                insertion_index = 0;
+                mappings.push(PositionMapping {
+                    query_position: None,
+                    reference_position: ref_pos,
+                    insertion_index,
+                });
+            }
+            AlignmentOperation::Xclip(_) | AlignmentOperation::Yclip(_) => {}
+        }
+    }
+
+    mappings
+}
+
+fn write_coordinates(
+    output_file: &PathBuf,
+    sequences: &FastaRecords,
+    reference: &[u8],
+    reference_start: usize,
+    scoring: &PairwiseScoring,
+) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(create_output_file(output_file)?);
+    writer.write_record([
+        "seq_name",
+        "query_position",
+        "query_base",
+        "reference_position",
+        "reference_base",
+    ])?;
+
+    let mut progress = Progress::new(sequences.len());
+    for (seq_name, query) in sequences {
+        log::debug!("Mapping {} to the reference.", seq_name);
+
+        for mapping in map_positions(query, reference, scoring) {
+            let query_base = mapping.query_position.map_or(GAP_CHAR, |pos| query[pos - 1]);
+            let reference_base = match mapping.insertion_index {
+                0 => reference[mapping.reference_position - 1],
+                _ => GAP_CHAR,
+            };
+
+            writer.write_record([
+                seq_name.clone(),
+                mapping
+                    .query_position
+                    .map(|pos| pos.to_string())
+                    .unwrap_or_default(),
+                (query_base as char).to_string(),
+                format_reference_position(&mapping, reference_start),
+                (reference_base as char).to_string(),
+            ])?;
+        }
+        progress.tick();
+    }
+
+    finish_csv(writer)
+}
+
+pub fn run(
+    input_file: &PathBuf,
+    reference_file: &PathBuf,
+    output_file: &PathBuf,
+    reference_start: usize,
+    scoring: &PairwiseScoring,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    log::info!(
+        "{}",
+        format!(
+            "This is 'map-coordinates' version {}",
+            env!("CARGO_PKG_VERSION")
+        )
+        .bold()
+        .bright_blue()
+    );
+
+    if reference_start == 0 {
+        bail!("Reference coordinates are 1-based, so --reference-start must be at least 1.")
+    }
+
+    log::info!("Reading input file {:?}", input_file);
+    let mut sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+
+    log::info!("Reading reference from {:?}", reference_file);
+    let mut reference = load_single_sequence(reference_file)?;
+    summary.input(reference_file);
+
+    reference.retain(|&nt| nt != GAP_CHAR);
+    for seq in sequences.values_mut() {
+        seq.retain(|&nt| nt != GAP_CHAR);
+    }
+
+    log::info!("Writing reference coordinates to {:?}", output_file);
+    write_coordinates(output_file, &sequences, &reference, reference_start, scoring)?;
+    summary.output(output_file);
+    summary.count("written", sequences.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(query: &[u8], reference: &[u8], reference_start: usize) -> Vec<String> {
+        map_positions(query, reference, &PairwiseScoring::default())
+            .iter()
+            .map(|mapping| format_reference_position(mapping, reference_start))
+            .collect()
+    }
+
+    #[test]
+    fn test_map_fragment() {
+        // The query covers reference positions 5-10.
+        let reference = b"AAAACGTACGTCCCC";
+        assert_eq!(
+            formatted(b"CGTACG", reference, 1),
+            vec!["5", "6", "7", "8", "9", "10"]
+        );
+        assert_eq!(
+            formatted(b"CGTACG", reference, 6225),
+            vec!["6229", "6230", "6231", "6232", "6233", "6234"]
+        );
+    }
+
+    #[test]
+    fn test_map_insertion_and_deletion() {
+        let reference = b"ATGGCCAAGTTTGGGCCCTAA";
+        let scoring = PairwiseScoring::default();
+
+        // Two bases inserted after reference position 9.
+        let mappings = map_positions(b"ATGGCCAAGCCTTTGGGCCCTAA", reference, &scoring);
+        assert_eq!(mappings.len(), 23);
+        assert_eq!(
+            mappings[9..11]
+                .iter()
+                .map(|mapping| format_reference_position(mapping, 1))
+                .collect::<Vec<_>>(),
+            vec!["9+1", "9+2"]
+        );
+        assert_eq!(format_reference_position(&mappings[11], 1), "10");
+
+        // Reference positions 10-12 deleted from the query.
+        let mappings = map_positions(b"ATGGCCAAGGGGCCCTAA", reference, &scoring);
+        let deleted: Vec<usize> = mappings
+            .iter()
+            .filter(|mapping| mapping.query_position.is_none())
+            .map(|mapping| mapping.reference_position)
+            .collect();
+        assert_eq!(deleted.len(), 3);
+        assert!(mappings.iter().all(|mapping| mapping.insertion_index == 0));
+    }
+}
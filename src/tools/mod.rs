@@ -1,16 +1,27 @@
+pub mod align_pair;
+pub mod check_alignment;
 pub mod collapse;
+pub mod consensus_identity;
+pub mod count;
+pub mod de_chimera;
 pub mod expand;
 pub mod filter_by_kmer;
 pub mod filter_by_length;
+pub mod find_orfs;
 pub mod gb_extract;
 pub mod get_consensus;
+pub mod guess_frame;
+pub mod map_coordinates;
+pub mod orient_reads;
 #[cfg(feature = "process-miniprot")]
 pub mod process_miniprot;
 pub mod replace_ambiguities;
+pub mod rev_comp;
 pub mod reverse_translate;
 pub mod strip_gap_cols;
 pub mod translate;
 pub mod trim_after_stop_codon;
+pub mod trim_homopolymer;
 #[cfg(feature = "trim-sam")]
 pub mod trim_sam;
 pub mod get_mindist_seq;
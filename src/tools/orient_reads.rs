@@ -0,0 +1,173 @@
+use crate::tools::align_pair::{align_pair, AlignmentType, PairwiseScoring};
+use crate::utils::codon_tables::GAP_CHAR;
+use crate::utils::fasta_utils::{
+    load_fasta, load_single_sequence, reverse_complement, write_fasta_sequences, FastaRecords,
+};
+use crate::utils::progress::Progress;
+use crate::utils::summary::RunSummary;
+use anyhow::Result;
+use colored::Colorize;
+use std::path::PathBuf;
+
+/// Reads split by the strand they matched the reference on. Reverse reads have already been
+/// reverse complemented, so every matched read is in the forward orientation.
+#[derive(Default)]
+pub(crate) struct OrientedReads {
+    pub(crate) forward: FastaRecords,
+    pub(crate) reverse: FastaRecords,
+    pub(crate) unmatched: FastaRecords,
+}
+
+/// Align a (degapped) read and its reverse complement locally against the reference and keep
+/// whichever orientation scores higher. A read is unmatched if neither orientation scores at
+/// least `min_score`, or if both score the same.
+pub(crate) fn orient_reads(
+    sequences: FastaRecords,
+    reference: &[u8],
+    min_score: i32,
+) -> OrientedReads {
+    let mut degapped_reference = reference.to_vec();
+    degapped_reference.retain(|&nt| nt != GAP_CHAR);
+    let scoring = PairwiseScoring::default();
+
+    let mut oriented = OrientedReads::default();
+
+    let mut progress = Progress::new(sequences.len());
+    for (seq_name, mut seq) in sequences {
+        seq.retain(|&nt| nt != GAP_CHAR);
+        let reverse_seq = reverse_complement(&seq);
+
+        let forward_score =
+            align_pair(&seq, &degapped_reference, &scoring, AlignmentType::Local, false).score;
+        let reverse_score = align_pair(
+            &reverse_seq,
+            &degapped_reference,
+            &scoring,
+            AlignmentType::Local,
+            false,
+        )
+        .score;
+        log::debug!(
+            "{}: forward score {}, reverse score {}",
+            seq_name,
+            forward_score,
+            reverse_score
+        );
+
+        if forward_score.max(reverse_score) < min_score || forward_score == reverse_score {
+            oriented.unmatched.insert(seq_name, seq);
+        } else if forward_score > reverse_score {
+            oriented.forward.insert(seq_name, seq);
+        } else {
+            oriented.reverse.insert(seq_name, reverse_seq);
+        }
+        progress.tick();
+    }
+
+    oriented
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    input_file: &PathBuf,
+    reference_file: &PathBuf,
+    forward_output: Option<&PathBuf>,
+    reverse_output: Option<&PathBuf>,
+    reverse_complement_output: Option<&PathBuf>,
+    unmatched_output: Option<&PathBuf>,
+    min_score: i32,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    log::info!(
+        "{}",
+        format!("This is 'orient-reads' version {}", env!("CARGO_PKG_VERSION"))
+            .bold()
+            .bright_green()
+    );
+
+    let reference = load_single_sequence(reference_file)?;
+    summary.input(reference_file);
+
+    log::info!("Reading input file {:?}", input_file);
+    let sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+    let oriented = orient_reads(sequences, &reference, min_score);
+
+    log::info!(
+        "{} forward, {} reverse, {} unmatched.",
+        oriented.forward.len(),
+        oriented.reverse.len(),
+        oriented.unmatched.len()
+    );
+    summary.count("forward", oriented.forward.len());
+    summary.count("reverse", oriented.reverse.len());
+    summary.count("unmatched", oriented.unmatched.len());
+
+    if let Some(forward_output) = forward_output {
+        log::info!("Writing forward reads to {:?}", forward_output);
+        write_fasta_sequences(forward_output, &oriented.forward)?;
+        summary.output(forward_output);
+    }
+
+    if let Some(reverse_output) = reverse_output {
+        log::info!("Writing reverse complemented reverse reads to {:?}", reverse_output);
+        write_fasta_sequences(reverse_output, &oriented.reverse)?;
+        summary.output(reverse_output);
+    }
+
+    if let Some(reverse_complement_output) = reverse_complement_output {
+        log::info!(
+            "Writing all matched reads in the forward orientation to {:?}",
+            reverse_complement_output
+        );
+        let mut all_forward = oriented.forward.clone();
+        all_forward.extend(oriented.reverse.clone());
+        write_fasta_sequences(reverse_complement_output, &all_forward)?;
+        summary.output(reverse_complement_output);
+    }
+
+    if let Some(unmatched_output) = unmatched_output {
+        log::info!("Writing unmatched reads to {:?}", unmatched_output);
+        write_fasta_sequences(unmatched_output, &oriented.unmatched)?;
+        summary.output(unmatched_output);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use velcro::map_iter;
+
+    const REFERENCE: &[u8] = b"ATGGCCAAAGGGTTTCCCTGGACGTACGATCG";
+
+    #[test]
+    fn test_orient_reads() {
+        let sequences: FastaRecords = map_iter!(
+            "fwd".to_string(): b"GCCAAAGGG-TTTCCCTGG".to_vec(),
+            "rev".to_string(): reverse_complement(b"AAGGGTTTCCCTGGACGTAC"),
+            "none".to_string(): b"CCCCCCCCCCCCCCCCCCCC".to_vec(),
+        ).collect();
+
+        let oriented = orient_reads(sequences, REFERENCE, 15);
+
+        assert_eq!(oriented.forward["fwd"], b"GCCAAAGGGTTTCCCTGG".to_vec());
+        assert_eq!(oriented.reverse["rev"], b"AAGGGTTTCCCTGGACGTAC".to_vec());
+        assert!(oriented.unmatched.contains_key("none"));
+        assert_eq!(oriented.forward.len(), 1);
+        assert_eq!(oriented.reverse.len(), 1);
+    }
+
+    #[test]
+    fn test_palindrome_is_unmatched() {
+        // ACGT is its own reverse complement, so both orientations score the same.
+        let sequences: FastaRecords = map_iter!(
+            "palindrome".to_string(): b"ACGTACGT".to_vec(),
+        ).collect();
+
+        let oriented = orient_reads(sequences, REFERENCE, 0);
+        assert!(oriented.unmatched.contains_key("palindrome"));
+    }
+}
@@ -1,10 +1,10 @@
 use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
+use crate::utils::summary::RunSummary;
 use anyhow::Result;
 
 use colored::Colorize;
 use polars::prelude::LazyFrame;
 use polars::prelude::*;
-use std::collections::HashMap;
 use std::path::PathBuf;
 
 fn read_fasta_into_lazyframe(fasta_file: &PathBuf) -> Result<LazyFrame> {
@@ -26,7 +26,7 @@ fn read_fasta_into_lazyframe(fasta_file: &PathBuf) -> Result<LazyFrame> {
 fn write_dataframe_to_fasta(seq_df: DataFrame, output_file: &PathBuf) -> Result<()> {
     let names = seq_df["query"].clone().take_materialized_series();
     let sequences = seq_df["new_seq_rec"].clone().take_materialized_series();
-    let mut fasta_seqs: FastaRecords = HashMap::with_capacity(names.len());
+    let mut fasta_seqs = FastaRecords::with_capacity(names.len());
 
     names
         .iter()
@@ -48,6 +48,7 @@ pub fn run(
     paf_file: &PathBuf,
     prepend: &Option<String>,
     output_dir: &PathBuf,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -123,7 +124,12 @@ pub fn run(
                 query_end_col.clone() - query_start_col.clone(),
             )
             .alias("new_seq_rec")]);
-    write_dataframe_to_fasta(trimmed_seq_df.collect()?, &output_dir)?;
+    let trimmed_seq_df = trimmed_seq_df.collect()?;
+    summary.input(input_file);
+    summary.input(paf_file);
+    summary.count("written", trimmed_seq_df.height());
+    write_dataframe_to_fasta(trimmed_seq_df, &output_dir)?;
+    summary.output(output_dir);
 
     Ok(())
 }
@@ -1,28 +1,93 @@
-use crate::utils::codon_tables::AMBIGUOUS_NT_LOOKUP;
-use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
-use anyhow::Context;
+use crate::utils::codon_tables::{AMBIGUOUS_NT_LOOKUP, GAP_CHAR};
+use crate::utils::fasta_utils::{load_fasta, load_single_sequence, FastaRecords, FastaWriter};
+use crate::utils::progress::Progress;
+use crate::utils::summary::RunSummary;
+use anyhow::{bail, Context};
+use clap::ValueEnum;
 use colored::Colorize;
-use itertools::Itertools;
 use std::path::PathBuf;
 
-fn replace_ambiguities(sequence: &[u8], rng: &mut oorandom::Rand32) -> anyhow::Result<Vec<u8>> {
-    let new_sequence: Vec<u8> = sequence
-        .iter()
-        .cloned()
-        .map(|nt| {
-            return if AMBIGUOUS_NT_LOOKUP.contains_key(&[nt]) {
-                let possible_nts = &AMBIGUOUS_NT_LOOKUP[&[nt]];
-                let index = rng.rand_range(0..possible_nts.len() as u32) as usize;
-                possible_nts
-                    .iter()
-                    .nth(index)
-                    .with_context(|| format!("Failed to get nucleotide for nt {:?}", nt))
-                    .unwrap_or(&&[nt])[0]
-            } else {
-                nt
-            };
+const CANONICAL_NTS: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ResolutionStrategy {
+    /// Pick one of the possible nucleotides at random
+    Random,
+    /// Pick the alphabetically first of the possible nucleotides
+    First,
+    /// Pick the nucleotide at the same column in a reference sequence, if it is one of the
+    /// possible nucleotides, otherwise fall back to `first`
+    Reference,
+}
+
+/// Check that every ambiguity code in the lookup maps to at least one nucleotide, so that
+/// picking a random member of its set can never fail. Run once at startup, like
+/// `validate_codon_tables`.
+pub fn validate_ambiguity_lookup() -> anyhow::Result<()> {
+    for (code, possible_nts) in AMBIGUOUS_NT_LOOKUP.entries() {
+        if possible_nts.is_empty() {
+            bail!(
+                "The ambiguity code {:?} does not map to any nucleotides.",
+                code[0] as char
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The nucleotides an ambiguous base could stand for, or `None` if it is not ambiguous.
+fn possible_nts(nt: u8) -> Option<Vec<u8>> {
+    if nt == b'N' {
+        Some(CANONICAL_NTS.to_vec())
+    } else {
+        AMBIGUOUS_NT_LOOKUP.get(&[nt]).map(|possible_nts| {
+            possible_nts
+                .iter()
+                .map(|possible_nt| possible_nt[0])
+                .collect()
         })
-        .collect();
+    }
+}
+
+fn replace_ambiguities(
+    sequence: &[u8],
+    rng: &mut oorandom::Rand32,
+    strategy: ResolutionStrategy,
+    reference: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut new_sequence: Vec<u8> = Vec::with_capacity(sequence.len());
+
+    for (idx, &nt) in sequence.iter().enumerate() {
+        let possible_nts = match possible_nts(nt) {
+            Some(possible_nts) if nt != GAP_CHAR => possible_nts,
+            _ => {
+                new_sequence.push(nt);
+                continue;
+            }
+        };
+
+        let first_nt = *possible_nts
+            .iter()
+            .min()
+            .with_context(|| format!("Failed to get nucleotide for nt {:?}", nt as char))?;
+
+        let new_nt = match strategy {
+            ResolutionStrategy::Random => {
+                let index = rng.rand_range(0..possible_nts.len() as u32) as usize;
+                possible_nts[index]
+            }
+            ResolutionStrategy::First => first_nt,
+            ResolutionStrategy::Reference => {
+                let reference = reference
+                    .context("A reference sequence is required for the reference strategy.")?;
+                match reference.get(idx) {
+                    Some(ref_nt) if possible_nts.contains(ref_nt) => *ref_nt,
+                    _ => first_nt,
+                }
+            }
+        };
+        new_sequence.push(new_nt);
+    }
 
     Ok(new_sequence)
 }
@@ -30,22 +95,61 @@ fn replace_ambiguities(sequence: &[u8], rng: &mut oorandom::Rand32) -> anyhow::R
 pub fn replace_ambiguities_records(
     sequences: FastaRecords,
     seed: u64,
+    strategy: ResolutionStrategy,
+    reference: Option<&[u8]>,
 ) -> anyhow::Result<FastaRecords> {
-    let mut rng = oorandom::Rand32::new(seed);
     let mut new_sequences: FastaRecords = FastaRecords::with_capacity(sequences.capacity());
 
-    // Iterate in a deterministic order (HashMap order is randomized per-process) so the
-    // seeded RNG stream is applied to sequences in the same order on every run.
-    for seq_id in sequences.keys().sorted().cloned().collect::<Vec<_>>() {
-        let sequence = &sequences[&seq_id];
-        let new_seq = replace_ambiguities(sequence, &mut rng)?;
+    replace_ambiguities_each(sequences, seed, strategy, reference, |seq_id, new_seq| {
         new_sequences.insert(seq_id, new_seq);
-    }
+        Ok(())
+    })?;
 
     Ok(new_sequences)
 }
 
-pub fn run(input_filepath: &PathBuf, output_filepath: &PathBuf, seed: u64) -> anyhow::Result<()> {
+/// Replace the ambiguities in each sequence as in `replace_ambiguities_records`, passing each
+/// result to `on_record` as soon as it is ready instead of collecting them.
+pub fn replace_ambiguities_each(
+    sequences: FastaRecords,
+    seed: u64,
+    strategy: ResolutionStrategy,
+    reference: Option<&[u8]>,
+    mut on_record: impl FnMut(String, Vec<u8>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if let Some(reference) = reference
+        && let Some((seq_id, _)) = sequences.iter().find(|(_, seq)| seq.len() != reference.len())
+    {
+        bail!(
+            "The sequence {} is not the same length as the reference ({}). Sequences must be aligned to the reference.",
+            seq_id,
+            reference.len()
+        );
+    }
+
+    let mut rng = oorandom::Rand32::new(seed);
+
+    let mut progress = Progress::new(sequences.len());
+
+    // The records keep their input order, so the seeded RNG stream is applied to the sequences in
+    // the same order on every run.
+    for (seq_id, sequence) in sequences {
+        let new_seq = replace_ambiguities(&sequence, &mut rng, strategy, reference)?;
+        on_record(seq_id, new_seq)?;
+        progress.tick();
+    }
+
+    Ok(())
+}
+
+pub fn run(
+    input_filepath: &PathBuf,
+    output_filepath: &PathBuf,
+    seed: Option<u64>,
+    strategy: ResolutionStrategy,
+    reference_filepath: Option<&PathBuf>,
+    summary: &mut RunSummary,
+) -> anyhow::Result<()> {
     log::info!(
         "{}",
         format!(
@@ -56,7 +160,20 @@ pub fn run(input_filepath: &PathBuf, output_filepath: &PathBuf, seed: u64) -> an
         .bold()
         .bright_purple()
     );
-    log::info!("Command was run with a random seed = {}", seed);
+    let seed = seed.unwrap_or_else(|| {
+        let seed = rand::random();
+        log::info!(
+            "No seed was given, so the random seed {} was picked. Pass --seed {} to repeat this run.",
+            seed,
+            seed
+        );
+        seed
+    });
+    log::info!(
+        "Command was run with strategy {:?} and a random seed = {}",
+        strategy,
+        seed
+    );
 
     log::info!(
         "Reading sequences from {:?} and writing to {:?}.",
@@ -64,10 +181,123 @@ pub fn run(input_filepath: &PathBuf, output_filepath: &PathBuf, seed: u64) -> an
         output_filepath
     );
 
+    let reference = match (strategy, reference_filepath) {
+        (ResolutionStrategy::Reference, Some(reference_filepath)) => {
+            log::info!("Resolving ambiguities against the reference {:?}.", reference_filepath);
+            summary.input(reference_filepath);
+            Some(load_single_sequence(reference_filepath)?)
+        }
+        (ResolutionStrategy::Reference, None) => {
+            bail!("The reference strategy requires a reference file (--reference).")
+        }
+        (_, Some(_)) => {
+            log::warn!("A reference file was provided but is only used by the reference strategy.");
+            None
+        }
+        (_, None) => None,
+    };
+
     let sequences = load_fasta(input_filepath).context("Could not open input file.")?;
-    let new_sequences = replace_ambiguities_records(sequences, seed)?;
-    write_fasta_sequences(output_filepath, &new_sequences)?;
+    summary.input(input_filepath);
+    summary.count("read", sequences.len());
+    let mut writer = FastaWriter::new(output_filepath)?;
+    replace_ambiguities_each(
+        sequences,
+        seed,
+        strategy,
+        reference.as_deref(),
+        |seq_id, new_seq| writer.write_record(&seq_id, None, &new_seq),
+    )?;
+    let num_written = writer.finish()?;
+    summary.output(output_filepath);
+    summary.count("written", num_written);
 
     log::info!("Done. Exiting.");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_is_valid() {
+        assert!(validate_ambiguity_lookup().is_ok());
+    }
+
+    #[test]
+    fn test_n_resolves_to_canonical_base() -> anyhow::Result<()> {
+        let mut rng = oorandom::Rand32::new(42);
+        let resolved = replace_ambiguities(&[b'N'; 100], &mut rng, ResolutionStrategy::Random, None)?;
+
+        assert!(resolved.iter().all(|nt| CANONICAL_NTS.contains(nt)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gaps_pass_through() -> anyhow::Result<()> {
+        let mut rng = oorandom::Rand32::new(42);
+        let resolved = replace_ambiguities(b"AC--GT-", &mut rng, ResolutionStrategy::Random, None)?;
+
+        assert_eq!(b"AC--GT-".to_vec(), resolved);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ambiguity_resolves_to_member_of_set() -> anyhow::Result<()> {
+        let mut rng = oorandom::Rand32::new(42);
+        let resolved = replace_ambiguities(b"R-Y", &mut rng, ResolutionStrategy::Random, None)?;
+
+        assert!(resolved[0] == b'A' || resolved[0] == b'G');
+        assert_eq!(resolved[1], GAP_CHAR);
+        assert!(resolved[2] == b'C' || resolved[2] == b'T');
+        Ok(())
+    }
+
+    #[test]
+    fn test_first_strategy() -> anyhow::Result<()> {
+        let mut rng = oorandom::Rand32::new(42);
+        let resolved = replace_ambiguities(b"RYN-K", &mut rng, ResolutionStrategy::First, None)?;
+
+        assert_eq!(b"ACA-G".to_vec(), resolved);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_strategy() -> anyhow::Result<()> {
+        let mut rng = oorandom::Rand32::new(42);
+        // The reference base is used where it is compatible with the code; the last R is not
+        // compatible with C, so it falls back to the first base.
+        let resolved = replace_ambiguities(
+            b"RYNAR",
+            &mut rng,
+            ResolutionStrategy::Reference,
+            Some(b"GTCAC"),
+        )?;
+
+        assert_eq!(b"GTCAA".to_vec(), resolved);
+        Ok(())
+    }
+
+    #[test]
+    fn test_records_keep_input_order() -> anyhow::Result<()> {
+        let sequences: FastaRecords = velcro::map_iter!(
+            "C".to_string(): b"RA".to_vec(),
+            "A".to_string(): b"YA".to_vec(),
+            "B".to_string(): b"NA".to_vec(),
+        ).collect();
+
+        let resolved = replace_ambiguities_records(sequences, 42, ResolutionStrategy::First, None)?;
+        assert!(resolved.keys().eq(["C", "A", "B"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reference_length_mismatch() {
+        let sequences: FastaRecords = velcro::map_iter!("A".to_string(): b"RYN".to_vec()).collect();
+
+        let result =
+            replace_ambiguities_records(sequences, 42, ResolutionStrategy::Reference, Some(b"AC"));
+        assert!(result.is_err());
+    }
+}
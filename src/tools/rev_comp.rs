@@ -0,0 +1,100 @@
+use crate::utils::fasta_utils::{
+    load_fasta, reverse_complement, write_fasta_sequences, FastaRecords,
+};
+use crate::utils::summary::RunSummary;
+use anyhow::Result;
+use colored::Colorize;
+use regex::Regex;
+use std::path::PathBuf;
+
+/// Reverse complement every sequence, or only those whose ID matches `only_matching`; the rest
+/// pass through unchanged. Returns the sequences and the number that were flipped.
+pub(crate) fn rev_comp_records(
+    sequences: FastaRecords,
+    only_matching: Option<&Regex>,
+) -> (FastaRecords, usize) {
+    let mut new_sequences = FastaRecords::with_capacity(sequences.len());
+    let mut num_flipped = 0;
+
+    for (seq_name, seq) in sequences {
+        let flip = only_matching.is_none_or(|pattern| pattern.is_match(&seq_name));
+        if flip {
+            num_flipped += 1;
+            new_sequences.insert(seq_name, reverse_complement(&seq));
+        } else {
+            new_sequences.insert(seq_name, seq);
+        }
+    }
+
+    (new_sequences, num_flipped)
+}
+
+pub fn run(
+    input_file: &PathBuf,
+    output_file: &PathBuf,
+    only_matching: Option<&str>,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    log::info!(
+        "{}",
+        format!("This is 'rev-comp' version {}", env!("CARGO_PKG_VERSION"))
+            .bold()
+            .bright_green()
+    );
+
+    log::info!("Reading input file {:?}", input_file);
+    let sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+    let pattern = only_matching.map(Regex::new).transpose()?;
+
+    let (new_sequences, num_flipped) = rev_comp_records(sequences, pattern.as_ref());
+    log::info!(
+        "Reverse complemented {} of {} sequences.",
+        num_flipped,
+        new_sequences.len()
+    );
+
+    write_fasta_sequences(output_file, &new_sequences)?;
+    summary.output(output_file);
+    summary.count("written", new_sequences.len());
+    summary.count("flipped", num_flipped);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use velcro::map_iter;
+
+    #[test]
+    fn test_rev_comp_all() {
+        let sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATG-CR".to_vec(),
+            "B".to_string(): b"AAAC".to_vec(),
+        ).collect();
+
+        let (new_sequences, num_flipped) = rev_comp_records(sequences, None);
+
+        assert_eq!(num_flipped, 2);
+        assert_eq!(new_sequences["A"], b"YG-CAT".to_vec());
+        assert_eq!(new_sequences["B"], b"GTTT".to_vec());
+    }
+
+    #[test]
+    fn test_rev_comp_only_matching() -> Result<()> {
+        let sequences: FastaRecords = map_iter!(
+            "sample_rev".to_string(): b"AAAC".to_vec(),
+            "sample_fwd".to_string(): b"AAAC".to_vec(),
+        ).collect();
+
+        let pattern = Regex::new("_rev$")?;
+        let (new_sequences, num_flipped) = rev_comp_records(sequences, Some(&pattern));
+
+        assert_eq!(num_flipped, 1);
+        assert_eq!(new_sequences["sample_rev"], b"GTTT".to_vec());
+        assert_eq!(new_sequences["sample_fwd"], b"AAAC".to_vec());
+        Ok(())
+    }
+}
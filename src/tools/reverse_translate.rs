@@ -1,11 +1,33 @@
+use crate::error::PursError;
 use crate::utils::codon_tables::GAP_CHAR;
-use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
-use anyhow::{anyhow, Context, Result};
+use crate::utils::fasta_utils::{
+    load_fasta_with_descriptions, write_fasta_sequences_with_descriptions, FastaRecords,
+};
+use crate::utils::summary::RunSummary;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
 use colored::Colorize;
+use itertools::Itertools;
 use log;
 use std::path::PathBuf;
 
-pub fn reverse_translate(aa_seq: &Vec<u8>, nt_seq: &Vec<u8>) -> Result<Vec<u8>> {
+/// How a gap in the amino acid sequence is written in the reverse translated nucleotide sequence.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum GapOutput {
+    /// As a gap codon (---)
+    #[default]
+    Gap,
+    /// As NNN, keeping the sequence codon-complete without gaps
+    N,
+    /// Not at all, giving the ungapped coding sequence
+    Remove,
+}
+
+pub fn reverse_translate(
+    aa_seq: &[u8],
+    nt_seq: &[u8],
+    gap_output: GapOutput,
+) -> Result<Vec<u8>, PursError> {
     let gap_char = "-".as_bytes()[0];
     let mut new_nt_seq = Vec::with_capacity(aa_seq.len() * 3);
 
@@ -13,16 +35,20 @@ pub fn reverse_translate(aa_seq: &Vec<u8>, nt_seq: &Vec<u8>) -> Result<Vec<u8>>
 
     for amino_acid in aa_seq.iter() {
         if amino_acid == &gap_char {
-            new_nt_seq.extend_from_slice(&std::iter::repeat(gap_char).take(3).collect::<Vec<u8>>());
+            match gap_output {
+                GapOutput::Gap => new_nt_seq.extend_from_slice(&[gap_char; 3]),
+                GapOutput::N => new_nt_seq.extend_from_slice(b"NNN"),
+                GapOutput::Remove => {}
+            }
         } else {
             let to_idx = current_nt_idx + 3;
 
             if to_idx > nt_seq.len() {
-                return Err(anyhow!(
-                    "Failed to grab a codon from {} to {} on the nucleotide sequence. Index out of bounds.",
-                    current_nt_idx,
-                    to_idx
-                ));
+                return Err(PursError::CodonOutOfBounds {
+                    start: current_nt_idx,
+                    end: to_idx,
+                    len: nt_seq.len(),
+                });
             }
 
             new_nt_seq.extend_from_slice(&nt_seq[current_nt_idx..to_idx]);
@@ -36,6 +62,7 @@ pub fn reverse_translate(aa_seq: &Vec<u8>, nt_seq: &Vec<u8>) -> Result<Vec<u8>>
 pub fn process_sequences(
     aa_sequences: FastaRecords,
     nt_sequences: FastaRecords,
+    gap_output: GapOutput,
 ) -> Result<FastaRecords> {
     let mut missing_seqs = 0;
     let mut translation_errors = 0;
@@ -55,7 +82,7 @@ pub fn process_sequences(
                 let mut degapped_nt_seq = nt_sequence.clone();
                 degapped_nt_seq.retain(|&base| base != GAP_CHAR);
 
-                match reverse_translate(&aa_sequence, &degapped_nt_seq) {
+                match reverse_translate(&aa_sequence, &degapped_nt_seq, gap_output) {
                     Err(e) => {
                         log::error!(
                             "Error in reverse-translating the read {}.\n{:?}",
@@ -84,7 +111,59 @@ pub fn process_sequences(
     Ok(reverse_translated_sequences)
 }
 
-pub fn run(aa_filepath: &PathBuf, nt_filepath: &PathBuf, output_file_path: &PathBuf) -> Result<()> {
+/// Build a codon alignment from a protein alignment (the "pal2nal" operation): every residue is
+/// replaced by its codon and every gap by `---` (or `NNN`). Unlike `process_sequences`, a sequence
+/// that is missing or cannot be reverse translated is an error, and the output is checked to be a
+/// true alignment with every sequence three times the protein alignment length.
+pub fn codon_alignment(
+    aa_sequences: FastaRecords,
+    nt_sequences: FastaRecords,
+    gap_output: GapOutput,
+) -> Result<FastaRecords> {
+    if gap_output == GapOutput::Remove {
+        bail!("Removing gaps would break the codon alignment.")
+    }
+
+    let alignment_length = match aa_sequences.values().map(|seq| seq.len()).all_equal_value() {
+        Ok(length) => length,
+        Err(None) => bail!("No amino acid sequences were provided."),
+        Err(Some(_)) => bail!("The amino acid sequences are not all the same length, so they are not an alignment."),
+    };
+
+    let num_sequences = aa_sequences.len();
+    let codon_sequences = process_sequences(aa_sequences, nt_sequences, gap_output)?;
+
+    if codon_sequences.len() != num_sequences {
+        bail!(
+            "Only {} of {} sequences could be reverse translated; see the errors above.",
+            codon_sequences.len(),
+            num_sequences
+        );
+    }
+
+    if let Some((seq_name, seq)) = codon_sequences
+        .iter()
+        .find(|(_, seq)| seq.len() != alignment_length * 3)
+    {
+        bail!(
+            "The codon-aligned sequence {} has length {} but should have length {}.",
+            seq_name,
+            seq.len(),
+            alignment_length * 3
+        );
+    }
+
+    Ok(codon_sequences)
+}
+
+pub fn run(
+    aa_filepath: &PathBuf,
+    nt_filepath: &PathBuf,
+    output_file_path: &PathBuf,
+    codon_alignment_mode: bool,
+    gap_output: GapOutput,
+    summary: &mut RunSummary,
+) -> Result<()> {
     log::info!(
         "{}",
         format!(
@@ -96,18 +175,119 @@ pub fn run(aa_filepath: &PathBuf, nt_filepath: &PathBuf, output_file_path: &Path
         .red()
     );
 
-    let amino_acid_sequences: FastaRecords = load_fasta(aa_filepath)?;
-    let nuc_sequences: FastaRecords = load_fasta(nt_filepath)?;
+    let (amino_acid_sequences, aa_descriptions) = load_fasta_with_descriptions(aa_filepath)?;
+    let (nuc_sequences, mut descriptions) = load_fasta_with_descriptions(nt_filepath)?;
+    // The protein headers are the more recent, so their descriptions win over the nucleotide ones.
+    descriptions.extend(aa_descriptions);
+    summary.input(aa_filepath);
+    summary.input(nt_filepath);
+    summary.count("read", amino_acid_sequences.len());
 
-    let rev_translated_seqs = process_sequences(amino_acid_sequences, nuc_sequences)
-        .context("Error occurred while processing the sequences")?;
+    let rev_translated_seqs = if codon_alignment_mode {
+        codon_alignment(amino_acid_sequences, nuc_sequences, gap_output)
+    } else {
+        process_sequences(amino_acid_sequences, nuc_sequences, gap_output)
+    }
+    .context("Error occurred while processing the sequences")?;
 
-    write_fasta_sequences(output_file_path, &rev_translated_seqs).with_context(|| {
-        format!(
-            "Error occurred while trying to write reverse translated sequences to {:?}",
-            output_file_path
-        )
-    })?;
+    write_fasta_sequences_with_descriptions(output_file_path, &rev_translated_seqs, &descriptions)
+        .with_context(|| {
+            format!(
+                "Error occurred while trying to write reverse translated sequences to {:?}",
+                output_file_path
+            )
+        })?;
+    summary.output(output_file_path);
+    summary.count("written", rev_translated_seqs.len());
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use velcro::map_iter;
+
+    #[test]
+    fn test_reverse_translate_gaps() -> Result<()> {
+        let reverse_translated = reverse_translate(b"M-L", b"ATGTTA", GapOutput::Gap)?;
+
+        assert_eq!(b"ATG---TTA".to_vec(), reverse_translated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_translate_too_short() {
+        assert!(matches!(
+            reverse_translate(b"MKL", b"ATGAAAT", GapOutput::Gap),
+            Err(PursError::CodonOutOfBounds {
+                start: 6,
+                end: 9,
+                len: 7
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reverse_translate_gaps_as_n() -> Result<()> {
+        let reverse_translated = reverse_translate(b"M-L", b"ATGTTA", GapOutput::N)?;
+
+        assert_eq!(b"ATGNNNTTA".to_vec(), reverse_translated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_translate_gaps_removed() -> Result<()> {
+        let reverse_translated = reverse_translate(b"M--L-", b"ATGTTA", GapOutput::Remove)?;
+
+        assert_eq!(b"ATGTTA".to_vec(), reverse_translated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_codon_alignment() -> Result<()> {
+        let aa_sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"M-L".to_vec(),
+            "B".to_string(): b"MKL".to_vec(),
+        ).collect();
+        let nt_sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATG-TTA".to_vec(),
+            "B".to_string(): b"ATGAAATTA".to_vec(),
+        ).collect();
+
+        let codon_sequences =
+            codon_alignment(aa_sequences.clone(), nt_sequences.clone(), GapOutput::Gap)?;
+
+        assert_eq!(codon_sequences["A"], b"ATG---TTA".to_vec());
+        assert_eq!(codon_sequences["B"], b"ATGAAATTA".to_vec());
+        assert!(codon_alignment(aa_sequences, nt_sequences, GapOutput::Remove).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_codon_alignment_rejects_unaligned_protein() {
+        let aa_sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ML".to_vec(),
+            "B".to_string(): b"MKL".to_vec(),
+        ).collect();
+        let nt_sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATGTTA".to_vec(),
+            "B".to_string(): b"ATGAAATTA".to_vec(),
+        ).collect();
+
+        assert!(codon_alignment(aa_sequences, nt_sequences, GapOutput::Gap).is_err());
+    }
+
+    #[test]
+    fn test_codon_alignment_rejects_missing_sequence() {
+        let aa_sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"M-L".to_vec(),
+            "B".to_string(): b"MKL".to_vec(),
+        ).collect();
+        let nt_sequences: FastaRecords = map_iter!(
+            "A".to_string(): b"ATGTTA".to_vec(),
+        ).collect();
+
+        assert!(codon_alignment(aa_sequences, nt_sequences, GapOutput::Gap).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::utils::codon_tables::GAP_CHAR;
 use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
+use crate::utils::summary::RunSummary;
 use anyhow::{bail, Result};
 use colored::Colorize;
 
@@ -21,7 +22,7 @@ fn transpose_sequences(sequences: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
     for col_index in 0..max_seq_length {
         let this_col_vec: Vec<u8> = sequences
             .iter()
-            .map(|seq| seq.get(col_index).unwrap_or(&(0u8)).clone())
+            .map(|seq| *seq.get(col_index).unwrap_or(&(0u8)))
             .collect();
         transposed_sequence_columns.push(this_col_vec);
     }
@@ -49,13 +50,18 @@ pub(crate) fn strip_gap_columns(
     let final_sequences = transpose_sequences(new_columns)?;
     let output_sequences = seq_names
         .into_iter()
-        .zip(final_sequences.into_iter())
+        .zip(final_sequences)
         .collect();
 
     Ok(output_sequences)
 }
 
-pub fn run(input_file: &PathBuf, output_file: &PathBuf, gap_pct_to_remove: usize) -> Result<()> {
+pub fn run(
+    input_file: &PathBuf,
+    output_file: &PathBuf,
+    gap_pct_to_remove: usize,
+    summary: &mut RunSummary,
+) -> Result<()> {
     log::info!(
         "{}",
         format!(
@@ -68,9 +74,13 @@ pub fn run(input_file: &PathBuf, output_file: &PathBuf, gap_pct_to_remove: usize
 
     log::info!("Reading input file {:?}", input_file);
     let sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
     let stripped_sequences = strip_gap_columns(sequences, gap_pct_to_remove)?;
 
     write_fasta_sequences(output_file, &stripped_sequences)?;
+    summary.output(output_file);
+    summary.count("written", stripped_sequences.len());
 
     Ok(())
 }
@@ -78,23 +88,23 @@ pub fn run(input_file: &PathBuf, output_file: &PathBuf, gap_pct_to_remove: usize
 #[cfg(test)]
 mod tests {
     use super::*;
-    use velcro::hash_map;
+    use velcro::map_iter;
 
     #[test]
     fn basic_test() -> Result<()> {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "Test A".to_string(): vec![b'A', b'T', b'-', b'G', b'C', b'C'],
             "Test B".to_string(): vec![b'A', b'T', b'-', b'G', b'-', b'-'],
             "Test C".to_string(): vec![b'A', b'T', b'-', b'G', b'-', b'-'],
             "Test D".to_string(): vec![b'A', b'T', b'-', b'G', b'C', b'-']
-        );
+        ).collect();
 
-        let expected_seqs: FastaRecords = hash_map!(
+        let expected_seqs: FastaRecords = map_iter!(
             "Test A".to_string(): vec![b'A', b'T', b'G', b'C', b'C'],
             "Test B".to_string(): vec![b'A', b'T', b'G', b'-', b'-'],
             "Test C".to_string(): vec![b'A', b'T', b'G', b'-', b'-'],
             "Test D".to_string(): vec![b'A', b'T', b'G', b'C', b'-']
-        );
+        ).collect();
 
         let obtained_sequences = strip_gap_columns(input_seqs, 100);
         for (seq_name, seq) in obtained_sequences? {
@@ -124,7 +134,7 @@ mod tests {
 
         expected_seqs
             .into_iter()
-            .zip(obtained_sequences.into_iter())
+            .zip(obtained_sequences)
             .for_each(|(expected, actual)| {
                 assert!(expected.eq(&actual));
             });
@@ -134,10 +144,10 @@ mod tests {
 
     #[test]
     fn test_unequal_sequences() {
-        let input_seqs: FastaRecords = hash_map!(
+        let input_seqs: FastaRecords = map_iter!(
             "Test A".to_string(): vec![b'A', b'T', b'-', b'G', b'C', b'C'],
             "Test B".to_string(): vec![b'A', b'T', b'-', b'G'],
-        );
+        ).collect();
 
         assert!(strip_gap_columns(input_seqs, 100).is_err())
     }
@@ -1,8 +1,38 @@
-use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
-use crate::utils::translate::{translate, TranslationOptions};
-use anyhow::Result;
+use crate::error::PursError;
+use crate::tools::align_pair::{align_pair, AlignmentType, PairwiseScoring};
+use crate::utils::codon_tables::GAP_CHAR;
+use crate::utils::fasta_utils::{
+    duplicate_id_policy, load_fastas_with_descriptions, load_single_sequence, DescribedSequence,
+    DuplicateIdPolicy, FastaDescriptions, FastaRecords, FastaWriter, SequenceStream,
+};
+use crate::utils::output::{create_output_file, finish_csv};
+use crate::utils::progress::Progress;
+use crate::utils::summary::RunSummary;
+use crate::utils::translate::{
+    count_internal_stops, stripped_length_residual, translate, translate_six_frames,
+    translate_with_codons, load_codon_table, FrameTranslation, TranslationOptions,
+};
+use anyhow::{bail, Context, Result};
+use bio::alignment::AlignmentOperation;
 use colored::Colorize;
-use std::path::PathBuf;
+use itertools::Itertools;
+use rayon::prelude::*;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Warn if stripping the gaps from a sequence shifts its reading frame. With `strict_frame` this is
+/// an error instead, which `translate` itself raises.
+fn warn_on_frame_shift(seq_name: &str, seq: &[u8], translation_options: &TranslationOptions) {
+    if let Some(residual) = stripped_length_residual(seq, translation_options) {
+        log::warn!(
+            "Stripping gaps from {} left {} base(s) after the last complete codon; its gaps were not codon-aligned, so the reading frame may have shifted.",
+            seq_name,
+            residual
+        );
+    }
+}
 
 pub fn translate_records(
     nucleotide_sequences: FastaRecords,
@@ -11,18 +41,477 @@ pub fn translate_records(
     let mut translated_sequences: FastaRecords =
         FastaRecords::with_capacity(nucleotide_sequences.capacity());
 
-    for sequence in nucleotide_sequences {
-        let translated_seq = translate(sequence.1.as_slice(), translation_options)?;
-        translated_sequences.insert(sequence.0.to_string(), translated_seq);
+    translate_records_each(nucleotide_sequences, translation_options, |seq_name, translated_seq| {
+        translated_sequences.insert(seq_name, translated_seq);
+        Ok(())
+    })?;
+
+    Ok(translated_sequences)
+}
+
+/// Translate each sequence and pass it to `on_record` as soon as it is translated, so the caller
+/// can write it out instead of keeping every translation in memory.
+pub fn translate_records_each(
+    nucleotide_sequences: FastaRecords,
+    translation_options: &TranslationOptions,
+    mut on_record: impl FnMut(String, Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let progress = Progress::new(nucleotide_sequences.len());
+    translate_stream_each(
+        nucleotide_sequences
+            .into_iter()
+            .map(|(seq_name, seq)| Ok((seq_name, None, seq))),
+        translation_options,
+        1,
+        progress,
+        |seq_name, _, translated_seq| on_record(seq_name, translated_seq),
+    )
+}
+
+/// The number of records read ahead and translated in parallel by `translate_in_batches`.
+const BATCH_SIZE: usize = 1024;
+
+/// Translate records with `translate_one` in batches of `BATCH_SIZE`, translating the records in
+/// each batch in parallel on `threads` threads (0 for every core), and pass each result to
+/// `on_translated` in input order, along with the record's description. Only one batch is held in
+/// memory at a time.
+fn translate_in_batches<T: Send>(
+    records: impl IntoIterator<Item = Result<DescribedSequence, PursError>>,
+    threads: usize,
+    translate_one: impl Fn(&str, &[u8]) -> Result<T> + Sync,
+    mut on_translated: impl FnMut(String, Option<String>, T) -> Result<()>,
+) -> Result<()> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .context("Could not start the translation threads")?;
+
+    for batch in &records.into_iter().chunks(BATCH_SIZE) {
+        let batch = batch.collect::<Result<Vec<_>, _>>()?;
+        let translated: Vec<Result<T>> = pool.install(|| {
+            batch
+                .par_iter()
+                .map(|(seq_name, _, seq)| translate_one(seq_name, seq))
+                .collect()
+        });
+        for ((seq_name, description, _), result) in batch.into_iter().zip(translated) {
+            on_translated(seq_name, description, result?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Translate records as `translate_records_each` does, taking them from an iterator such as a
+/// `SequenceStream` so the input never needs to be held in memory, and translating on `threads`
+/// threads (0 for every core). The output order is the input order whatever the thread count.
+/// Each record's description is passed through to `on_record` untouched.
+pub fn translate_stream_each(
+    records: impl IntoIterator<Item = Result<DescribedSequence, PursError>>,
+    translation_options: &TranslationOptions,
+    threads: usize,
+    mut progress: Progress,
+    mut on_record: impl FnMut(String, Option<String>, Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    translate_in_batches(
+        records,
+        threads,
+        |seq_name, seq| {
+            warn_on_frame_shift(seq_name, seq, translation_options);
+            translate(seq, translation_options)
+                .with_context(|| format!("Failed to translate {}", seq_name))
+        },
+        |seq_name, description, translated_seq| {
+            on_record(seq_name, description, translated_seq)?;
+            progress.tick();
+            Ok(())
+        },
+    )
+}
+
+/// Translate each record as in `translate_stream_each`, also writing a TSV row to `codon_writer`
+/// for every residue with the codon it was translated from.
+pub(crate) fn translate_stream_each_with_codons<W: std::io::Write>(
+    records: impl IntoIterator<Item = Result<DescribedSequence, PursError>>,
+    translation_options: &TranslationOptions,
+    threads: usize,
+    codon_writer: &mut csv::Writer<W>,
+    mut progress: Progress,
+    mut on_record: impl FnMut(String, Option<String>, Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    codon_writer.write_record(["record", "residue_index", "amino_acid", "codon"])?;
+
+    translate_in_batches(
+        records,
+        threads,
+        |seq_name, seq| {
+            warn_on_frame_shift(seq_name, seq, translation_options);
+            translate_with_codons(seq, translation_options)
+                .with_context(|| format!("Failed to translate {}", seq_name))
+        },
+        |seq_name, description, residues| {
+            for (idx, (amino_acid, codon)) in residues.iter().enumerate() {
+                codon_writer.write_record([
+                    seq_name.as_str(),
+                    &(idx + 1).to_string(),
+                    &(*amino_acid as char).to_string(),
+                    &String::from_utf8_lossy(codon),
+                ])?;
+            }
+            let translated_seq = residues.into_iter().map(|(amino_acid, _)| amino_acid);
+            on_record(seq_name, description, translated_seq.collect())?;
+            progress.tick();
+            Ok(())
+        },
+    )?;
+
+    codon_writer.flush()?;
+    Ok(())
+}
+
+/// Translate an equal-length codon alignment column-for-column, so that the protein sequences
+/// are also an alignment. Gaps are kept, so every codon (including full and partial gap codons)
+/// becomes exactly one protein column; options that would remove columns are overridden.
+pub(crate) fn translate_msa(
+    nucleotide_sequences: FastaRecords,
+    translation_options: &TranslationOptions,
+) -> Result<FastaRecords> {
+    let Some(alignment_length) = nucleotide_sequences.values().map(Vec::len).next() else {
+        bail!("No sequences were provided.")
+    };
+    if let Some((seq_name, seq)) = nucleotide_sequences
+        .iter()
+        .find(|(_, seq)| seq.len() != alignment_length)
+    {
+        bail!(
+            "The input is not an alignment: {} has length {}, but the first sequence has length {}.",
+            seq_name,
+            seq.len(),
+            alignment_length
+        );
+    }
+
+    let translated_length = alignment_length.saturating_sub(translation_options.reading_frame);
+    if !translated_length.is_multiple_of(3) {
+        bail!(
+            "The alignment length ({}) after the reading frame offset ({}) is not a multiple of 3.",
+            alignment_length,
+            translation_options.reading_frame
+        );
+    }
+
+    if translation_options.strip_gaps || translation_options.ignore_gap_codons {
+        log::warn!("Gaps are always kept in MSA mode, ignoring --strip-gaps/--ignore-gap-codons.");
+    }
+    if translation_options.recover_frame {
+        log::warn!("Frame recovery would break the alignment, ignoring --recover-frame.");
+    }
+    let msa_options = TranslationOptions {
+        strip_gaps: false,
+        ignore_gap_codons: false,
+        recover_frame: false,
+        ..translation_options.clone()
+    };
+
+    translate_records(nucleotide_sequences, &msa_options)
+}
+
+/// Translate each sequence in all six reading frames and join the frames (forward 1-3, then
+/// reverse 1-3) into a single record, keeping the original ID, with `separator` between them.
+pub(crate) fn translate_records_concatenated(
+    nucleotide_sequences: FastaRecords,
+    translation_options: &TranslationOptions,
+    separator: u8,
+) -> Result<FastaRecords> {
+    let mut translated_sequences: FastaRecords =
+        FastaRecords::with_capacity(nucleotide_sequences.len());
+
+    let mut progress = Progress::new(nucleotide_sequences.len());
+    for (seq_name, seq) in nucleotide_sequences {
+        warn_on_frame_shift(&seq_name, &seq, translation_options);
+        let frames = translate_six_frames(&seq, translation_options)
+            .with_context(|| format!("Failed to translate {}", seq_name))?
+            .into_iter()
+            .map(|translation| translation.protein)
+            .collect::<Vec<_>>();
+        translated_sequences.insert(seq_name, frames.join(&separator));
+        progress.tick();
     }
 
     Ok(translated_sequences)
 }
 
+/// The record name for one frame of a six-frame translation: the sequence name suffixed with the
+/// strand and the 1-based frame, `_f1`..`_f3` forward and `_r1`..`_r3` on the reverse complement.
+pub(crate) fn six_frame_record_name(seq_name: &str, translation: &FrameTranslation) -> String {
+    let strand = if translation.reverse { 'r' } else { 'f' };
+    format!("{}_{}{}", seq_name, strand, translation.reading_frame + 1)
+}
+
+/// Translate each sequence in all six reading frames, as a separate record per frame named by
+/// `six_frame_record_name`.
+pub(crate) fn translate_records_six_frames(
+    nucleotide_sequences: FastaRecords,
+    translation_options: &TranslationOptions,
+) -> Result<FastaRecords> {
+    let mut translated_sequences: FastaRecords =
+        FastaRecords::with_capacity(nucleotide_sequences.len() * 6);
+
+    let mut progress = Progress::new(nucleotide_sequences.len());
+    for (seq_name, seq) in nucleotide_sequences {
+        warn_on_frame_shift(&seq_name, &seq, translation_options);
+        let frames = translate_six_frames(&seq, translation_options)
+            .with_context(|| format!("Failed to translate {}", seq_name))?;
+        for translation in frames {
+            let record_name = six_frame_record_name(&seq_name, &translation);
+            translated_sequences.insert(record_name, translation.protein);
+        }
+        progress.tick();
+    }
+
+    Ok(translated_sequences)
+}
+
+/// Infer the reading frame of a (degapped) query by aligning it to a reference whose reading
+/// frame is known: the first matching base fixes the query's codon phase relative to the
+/// reference. Returns `None` if the alignment has no matching bases.
+pub(crate) fn infer_reading_frame(
+    query: &[u8],
+    reference: &[u8],
+    reference_frame: usize,
+) -> Option<usize> {
+    let alignment = align_pair(
+        query,
+        reference,
+        &PairwiseScoring::default(),
+        AlignmentType::Semiglobal,
+        false,
+    );
+
+    // Path positions are 1-based.
+    let (query_pos, ref_pos, _) = alignment
+        .path()
+        .into_iter()
+        .find(|(_, _, op)| *op == AlignmentOperation::Match)?;
+    let ref_phase = (ref_pos - 1 + 3 - reference_frame % 3) % 3;
+
+    Some((query_pos - 1 + 3 - ref_phase) % 3)
+}
+
+/// Translate each sequence in the reading frame inferred from its alignment to `reference`,
+/// returning the translations and the frame used for each. Sequences are degapped before
+/// aligning, so they are also translated without gaps.
+pub(crate) fn translate_records_with_reference(
+    nucleotide_sequences: FastaRecords,
+    translation_options: &TranslationOptions,
+    reference: &[u8],
+    reference_frame: usize,
+) -> Result<(FastaRecords, HashMap<String, usize>)> {
+    let mut degapped_reference = reference.to_vec();
+    degapped_reference.retain(|&nt| nt != GAP_CHAR);
+
+    let mut translated_sequences: FastaRecords =
+        FastaRecords::with_capacity(nucleotide_sequences.len());
+    let mut reading_frames = HashMap::with_capacity(nucleotide_sequences.len());
+
+    let mut progress = Progress::new(nucleotide_sequences.len());
+    for (seq_name, mut seq) in nucleotide_sequences {
+        seq.retain(|&nt| nt != GAP_CHAR);
+
+        let reading_frame = match infer_reading_frame(&seq, &degapped_reference, reference_frame)
+        {
+            Some(frame) => {
+                log::info!("Inferred reading frame {} for {}", frame, seq_name);
+                frame
+            }
+            None => {
+                log::warn!(
+                    "Could not align {} to the reference, using reading frame {}",
+                    seq_name,
+                    translation_options.reading_frame
+                );
+                translation_options.reading_frame
+            }
+        };
+
+        let translated_seq = translate(
+            &seq,
+            &TranslationOptions {
+                reading_frame,
+                ..translation_options.clone()
+            },
+        )
+        .with_context(|| format!("Failed to translate {}", seq_name))?;
+        translated_sequences.insert(seq_name.clone(), translated_seq);
+        reading_frames.insert(seq_name, reading_frame);
+        progress.tick();
+    }
+
+    Ok((translated_sequences, reading_frames))
+}
+
+/// Pick the forward reading frame (0, 1 or 2) whose translation has the fewest internal stops,
+/// preferring the lowest frame on a tie, and return it along with its translation.
+pub(crate) fn pick_reading_frame(
+    seq: &[u8],
+    translation_options: &TranslationOptions,
+) -> Result<(usize, Vec<u8>)> {
+    let mut best: Option<(usize, usize, Vec<u8>)> = None;
+
+    for reading_frame in 0..3 {
+        let protein = translate(
+            seq,
+            &TranslationOptions {
+                reading_frame,
+                ..translation_options.clone()
+            },
+        )?;
+        let stops = count_internal_stops(&protein, translation_options);
+        if best.as_ref().is_none_or(|(best_stops, _, _)| stops < *best_stops) {
+            best = Some((stops, reading_frame, protein));
+        }
+    }
+
+    let (_, reading_frame, protein) = best.expect("There are always three frames.");
+    Ok((reading_frame, protein))
+}
+
+/// Translate each sequence in the forward frame with the fewest internal stops, returning the
+/// translations and the frame used for each.
+pub(crate) fn translate_records_auto_frame(
+    nucleotide_sequences: FastaRecords,
+    translation_options: &TranslationOptions,
+) -> Result<(FastaRecords, HashMap<String, usize>)> {
+    let mut translated_sequences: FastaRecords =
+        FastaRecords::with_capacity(nucleotide_sequences.len());
+    let mut reading_frames = HashMap::with_capacity(nucleotide_sequences.len());
+
+    let mut progress = Progress::new(nucleotide_sequences.len());
+    for (seq_name, seq) in nucleotide_sequences {
+        warn_on_frame_shift(&seq_name, &seq, translation_options);
+        let (reading_frame, translated_seq) = pick_reading_frame(&seq, translation_options)
+            .with_context(|| format!("Failed to translate {}", seq_name))?;
+        log::info!("Picked reading frame {} for {}", reading_frame, seq_name);
+        translated_sequences.insert(seq_name.clone(), translated_seq);
+        reading_frames.insert(seq_name, reading_frame);
+        progress.tick();
+    }
+
+    Ok((translated_sequences, reading_frames))
+}
+
+/// The provenance annotation added to each record's header with `--annotate-header`. The frame
+/// is reported 1-based, and the table is "custom" with a custom codon table.
+pub(crate) fn translation_header(
+    reading_frame: usize,
+    translation_options: &TranslationOptions,
+) -> String {
+    let table = match translation_options.custom_codon_table {
+        Some(_) => "custom".to_string(),
+        None => translation_options.genetic_code.to_string(),
+    };
+    format!("frame={} table={}", reading_frame + 1, table)
+}
+
+/// The file in `split_dir` that translations picked in `reading_frame` are written to with
+/// `--split-by-frame`: the output file's name with the 0-based frame before its extension.
+pub(crate) fn frame_output_path(
+    split_dir: &Path,
+    output_filepath: &Path,
+    reading_frame: usize,
+) -> PathBuf {
+    let stem = output_filepath
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "translated".to_string());
+    let extension = output_filepath
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "fa".to_string());
+
+    split_dir.join(format!("{}.frame{}.{}", stem, reading_frame, extension))
+}
+
+/// Check that the frame options given to `run` can be used together, before any output is
+/// created. --reading-frame auto is the same as --auto-frame, so clap can't catch every conflict.
+fn check_frame_options(
+    frame_reference: bool,
+    msa_mode: bool,
+    auto_frame: bool,
+    six_frame: bool,
+    concatenate_frames: bool,
+    split_by_frame: bool,
+    codon_output: bool,
+) -> Result<()> {
+    let modes = [
+        (frame_reference, "--frame-reference"),
+        (msa_mode, "--msa-mode"),
+        (auto_frame, "--auto-frame (or --reading-frame auto)"),
+        (six_frame, "--six-frame"),
+        (concatenate_frames, "--concatenate-frames"),
+    ];
+    let given: Vec<&str> = modes
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect();
+
+    if given.len() > 1 {
+        bail!("{} cannot be combined.", given.join(" and "));
+    }
+    if split_by_frame && !auto_frame {
+        bail!("--split-by-frame needs --auto-frame (or --reading-frame auto).");
+    }
+    if codon_output && let Some(mode) = given.first() {
+        bail!(
+            "--codon-output is only written for a single fixed frame, not with {}.",
+            mode
+        );
+    }
+    Ok(())
+}
+
+/// The header description written for a translation: the input record's own description, followed
+/// by the `--annotate-header` annotation if there is one.
+fn output_description(input: Option<&str>, annotation: Option<&str>) -> Option<String> {
+    match (input, annotation) {
+        (Some(input), Some(annotation)) => Some(format!("{} {}", input, annotation)),
+        (input, annotation) => input.or(annotation).map(str::to_string),
+    }
+}
+
+/// Load every input file for the modes that need all of the sequences before translating, along
+/// with the records' header descriptions.
+fn load_input(
+    nt_filepaths: &[PathBuf],
+    summary: &mut RunSummary,
+) -> Result<(FastaRecords, FastaDescriptions)> {
+    log::info!("Reading sequences from {:?}", nt_filepaths);
+    let (nucleotide_sequences, descriptions) = load_fastas_with_descriptions(nt_filepaths)?;
+    for nt_filepath in nt_filepaths {
+        summary.input(nt_filepath);
+    }
+    summary.count("read", nucleotide_sequences.len());
+    Ok((nucleotide_sequences, descriptions))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn run(
-    nt_filepath: &PathBuf,
+    nt_filepaths: &[PathBuf],
     output_filepath: &PathBuf,
     translation_options: &TranslationOptions,
+    annotate_header: bool,
+    frame_reference: Option<&PathBuf>,
+    reference_frame: usize,
+    msa_mode: bool,
+    auto_frame: bool,
+    six_frame: bool,
+    split_by_frame: Option<&PathBuf>,
+    frame_separator: Option<u8>,
+    codon_output: Option<&PathBuf>,
+    codon_table: Option<&PathBuf>,
+    threads: usize,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -34,21 +523,576 @@ pub fn run(
         .bold()
         .bright_purple()
     );
+    let custom_options;
+    let translation_options = match codon_table {
+        Some(codon_table) => {
+            log::info!("Reading the codon table from {:?}", codon_table);
+            let custom_codon_table = Arc::new(load_codon_table(codon_table)?);
+            summary.input(codon_table);
+            custom_options = TranslationOptions {
+                custom_codon_table: Some(custom_codon_table),
+                ..translation_options.clone()
+            };
+            &custom_options
+        }
+        None => translation_options,
+    };
     log::info!(
         "Command was run with the following options:\n{}",
         translation_options
     );
 
-    log::info!("Reading sequences from {:?}", nt_filepath);
-    let nucleotide_sequences = load_fasta(nt_filepath)?;
+    check_frame_options(
+        frame_reference.is_some(),
+        msa_mode,
+        auto_frame,
+        six_frame,
+        frame_separator.is_some(),
+        split_by_frame.is_some(),
+        codon_output.is_some(),
+    )?;
 
-    log::info!("Translating sequences.");
-    let translated_sequences = translate_records(nucleotide_sequences, translation_options)?;
+    log::info!(
+        "Translating sequences and writing them to {:?}",
+        output_filepath
+    );
+    let mut writer = FastaWriter::new(output_filepath)?;
+    let header = |reading_frame: usize| {
+        annotate_header.then(|| translation_header(reading_frame, translation_options))
+    };
 
-    log::info!("Done. Writing sequences to {:?}", output_filepath);
+    match (frame_reference, frame_separator) {
+        (_, Some(separator)) => {
+            log::info!(
+                "Concatenating all six frames, separated by {:?}.",
+                separator as char
+            );
+            let (nucleotide_sequences, descriptions) = load_input(nt_filepaths, summary)?;
+            let translated_sequences = translate_records_concatenated(
+                nucleotide_sequences,
+                translation_options,
+                separator,
+            )?;
+            for (seq_name, translated_seq) in translated_sequences {
+                let description = descriptions.get(&seq_name).map(String::as_str);
+                writer.write_record(&seq_name, description, &translated_seq)?;
+            }
+        }
+        (None, None) if msa_mode => {
+            log::info!("Translating the input as a codon alignment.");
+            let (nucleotide_sequences, descriptions) = load_input(nt_filepaths, summary)?;
+            let translated_sequences = translate_msa(nucleotide_sequences, translation_options)?;
+            let annotation = header(translation_options.reading_frame);
+            for (seq_name, translated_seq) in translated_sequences {
+                let description = output_description(
+                    descriptions.get(&seq_name).map(String::as_str),
+                    annotation.as_deref(),
+                );
+                writer.write_record(&seq_name, description.as_deref(), &translated_seq)?;
+            }
+        }
+        (None, None) if six_frame => {
+            log::info!("Translating all six frames, written as a separate record per frame.");
+            // Every frame is written as its own renamed record, so the input descriptions are
+            // not carried over.
+            let (nucleotide_sequences, _) = load_input(nt_filepaths, summary)?;
+            let translated_sequences =
+                translate_records_six_frames(nucleotide_sequences, translation_options)?;
+            for (seq_name, translated_seq) in translated_sequences {
+                writer.write_record(&seq_name, None, &translated_seq)?;
+            }
+        }
+        (None, None) if auto_frame => {
+            log::info!("Picking the reading frame with the fewest internal stops for each sequence.");
+            let (nucleotide_sequences, descriptions) = load_input(nt_filepaths, summary)?;
+            let (translated_sequences, reading_frames) =
+                translate_records_auto_frame(nucleotide_sequences, translation_options)?;
 
-    write_fasta_sequences(output_filepath, &translated_sequences)?;
+            let mut frame_writers: BTreeMap<usize, (PathBuf, FastaWriter)> = BTreeMap::new();
+            if let Some(split_dir) = split_by_frame {
+                log::info!("Splitting the translations by frame into {:?}", split_dir);
+                std::fs::create_dir_all(split_dir)
+                    .with_context(|| format!("Could not create directory {:?}", split_dir))?;
+            }
+
+            for (seq_name, translated_seq) in translated_sequences {
+                let reading_frame = reading_frames[&seq_name];
+                let description = output_description(
+                    descriptions.get(&seq_name).map(String::as_str),
+                    header(reading_frame).as_deref(),
+                );
+                writer.write_record(&seq_name, description.as_deref(), &translated_seq)?;
+
+                if let Some(split_dir) = split_by_frame {
+                    let (_, frame_writer) = match frame_writers.entry(reading_frame) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            let path = frame_output_path(split_dir, output_filepath, reading_frame);
+                            let frame_writer = FastaWriter::new(&path)?;
+                            entry.insert((path, frame_writer))
+                        }
+                    };
+                    frame_writer.write_record(
+                        &seq_name,
+                        description.as_deref(),
+                        &translated_seq,
+                    )?;
+                }
+            }
+
+            for (reading_frame, (path, frame_writer)) in frame_writers {
+                let num_written = frame_writer.finish()?;
+                log::info!(
+                    "Wrote {} sequences in reading frame {} to {:?}",
+                    num_written,
+                    reading_frame,
+                    path
+                );
+                summary.output(&path);
+                summary.count(&format!("frame{}", reading_frame), num_written);
+            }
+        }
+        (Some(reference_filepath), None) => {
+            log::info!(
+                "Inferring reading frames from the reference in {:?}",
+                reference_filepath
+            );
+            let reference = load_single_sequence(reference_filepath)?;
+            summary.input(reference_filepath);
+            let (nucleotide_sequences, descriptions) = load_input(nt_filepaths, summary)?;
+            let (translated_sequences, reading_frames) = translate_records_with_reference(
+                nucleotide_sequences,
+                translation_options,
+                &reference,
+                reference_frame,
+            )?;
+            for (seq_name, translated_seq) in translated_sequences {
+                let description = output_description(
+                    descriptions.get(&seq_name).map(String::as_str),
+                    header(reading_frames[&seq_name]).as_deref(),
+                );
+                writer.write_record(&seq_name, description.as_deref(), &translated_seq)?;
+            }
+        }
+        (None, None) => {
+            // Each record is translated on its own, so records are streamed from the input rather
+            // than loaded, and inputs of any size can be translated. Keeping the last record with
+            // a repeated ID needs the whole input first, though.
+            let load_everything = duplicate_id_policy() == DuplicateIdPolicy::Last;
+            let (records, progress): (Box<dyn Iterator<Item = _>>, _) = if load_everything {
+                let (nucleotide_sequences, mut descriptions) = load_input(nt_filepaths, summary)?;
+                let progress = Progress::new(nucleotide_sequences.len());
+                let records = nucleotide_sequences.into_iter().map(move |(id, seq)| {
+                    let description = descriptions.remove(&id);
+                    Ok((id, description, seq))
+                });
+                (Box::new(records), progress)
+            } else {
+                log::info!(
+                    "Reading sequences from {:?} as they are translated",
+                    nt_filepaths
+                );
+                for nt_filepath in nt_filepaths {
+                    summary.input(nt_filepath);
+                }
+                let records = SequenceStream::new(nt_filepaths);
+                (Box::new(records), Progress::unknown_total())
+            };
+            let mut num_read = 0;
+            let records = records.inspect(|_| num_read += 1);
+
+            let annotation = header(translation_options.reading_frame);
+            let write_record = |seq_name: String, description: Option<String>, seq: Vec<u8>| {
+                let description = output_description(description.as_deref(), annotation.as_deref());
+                writer.write_record(&seq_name, description.as_deref(), &seq)
+            };
+            match codon_output {
+                Some(codon_output) => {
+                    log::info!("Writing the codon of each residue to {:?}", codon_output);
+                    let mut codon_writer = csv::WriterBuilder::new()
+                        .delimiter(b'\t')
+                        .from_writer(create_output_file(codon_output)?);
+                    translate_stream_each_with_codons(
+                        records,
+                        translation_options,
+                        threads,
+                        &mut codon_writer,
+                        progress,
+                        write_record,
+                    )?;
+                    finish_csv(codon_writer)?;
+                    summary.output(codon_output);
+                }
+                None => translate_stream_each(
+                    records,
+                    translation_options,
+                    threads,
+                    progress,
+                    write_record,
+                )?,
+            }
+            summary.count("read", num_read);
+        }
+    }
+
+    let num_written = writer.finish()?;
+    summary.output(output_filepath);
+    summary.count("written", num_written);
 
     log::info!("Done. Exiting.");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::codon_tables::GeneticCode;
+    use crate::utils::fasta_utils::{load_fastas, write_fasta_sequences};
+    use crate::utils::test_utils::TempPath;
+    use crate::utils::translate::CustomCodonTable;
+
+    /// Records as a `SequenceStream` would yield them, without descriptions.
+    fn stream_of(
+        records: FastaRecords,
+    ) -> impl Iterator<Item = Result<DescribedSequence, PursError>> {
+        records
+            .into_iter()
+            .map(|(seq_name, seq)| Ok((seq_name, None, seq)))
+    }
+
+    #[test]
+    fn test_check_frame_options() {
+        // --reading-frame auto arrives as auto_frame, past clap's conflicts with --auto-frame.
+        assert!(check_frame_options(false, false, true, false, false, true, false).is_ok());
+        assert!(check_frame_options(false, true, true, false, false, false, false).is_err());
+        assert!(check_frame_options(false, false, true, true, false, false, false).is_err());
+        assert!(check_frame_options(false, false, true, false, false, false, true).is_err());
+        assert!(check_frame_options(false, false, false, false, false, true, false).is_err());
+        assert!(check_frame_options(false, false, false, false, false, false, true).is_ok());
+    }
+
+    #[test]
+    fn test_translation_header() {
+        assert_eq!(
+            "frame=1 table=1",
+            translation_header(0, &TranslationOptions::default())
+        );
+        let mitochondrial = TranslationOptions {
+            genetic_code: GeneticCode::VertebrateMitochondrial,
+            ..Default::default()
+        };
+        assert_eq!("frame=3 table=2", translation_header(2, &mitochondrial));
+        let custom_codon_table = CustomCodonTable::from([(*b"ATG", b'M')]);
+        let custom = TranslationOptions {
+            custom_codon_table: Some(Arc::new(custom_codon_table)),
+            ..Default::default()
+        };
+        assert_eq!("frame=1 table=custom", translation_header(0, &custom));
+    }
+
+    #[test]
+    fn test_strict_translation_names_record() {
+        let sequences: FastaRecords = velcro::map_iter!(
+            "contaminated".to_string(): b"ATGNNN".to_vec(),
+        ).collect();
+        let options = TranslationOptions {
+            strict_translation: true,
+            ..TranslationOptions::default()
+        };
+
+        let error = translate_records(sequences, &options).unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("contaminated"));
+        assert!(message.contains("NNN"));
+    }
+
+    #[test]
+    fn test_translate_stream_each_with_codons() -> Result<()> {
+        let sequences: FastaRecords = velcro::map_iter!(
+            "B".to_string(): b"TGG".to_vec(),
+            "A".to_string(): b"ATGTAA".to_vec(),
+        ).collect();
+        let mut codon_writer = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_writer(Vec::new());
+
+        let mut translated = FastaRecords::new();
+        translate_stream_each_with_codons(
+            stream_of(sequences),
+            &TranslationOptions::default(),
+            1,
+            &mut codon_writer,
+            Progress::new(2),
+            |seq_name, _, translated_seq| {
+                translated.insert(seq_name, translated_seq);
+                Ok(())
+            },
+        )?;
+
+        assert_eq!(translated["A"], b"M*".to_vec());
+        assert_eq!(
+            String::from_utf8(codon_writer.into_inner()?)?,
+            "record\tresidue_index\tamino_acid\tcodon\nB\t1\tW\tTGG\nA\t1\tM\tATG\nA\t2\t*\tTAA\n"
+        );
+        Ok(())
+    }
+
+    /// Nucleotide records of varying lengths (so some have incomplete codons), for comparing ways
+    /// of translating many records.
+    fn synthetic_records(count: usize) -> FastaRecords {
+        (0..count)
+            .map(|i| {
+                let seq = (0..90 + i % 7)
+                    .map(|j| b"ACGT"[(i * 7 + j * j) % 4])
+                    .collect();
+                (format!("seq{}", i), seq)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_threads_do_not_change_output() -> Result<()> {
+        let records = synthetic_records(3000);
+        let translate_with_threads = |threads: usize| -> Result<Vec<(String, Vec<u8>)>> {
+            let mut translated = Vec::new();
+            translate_stream_each(
+                stream_of(records.clone()),
+                &TranslationOptions::default(),
+                threads,
+                Progress::new(records.len()),
+                |seq_name, _, translated_seq| {
+                    translated.push((seq_name, translated_seq));
+                    Ok(())
+                },
+            )?;
+            Ok(translated)
+        };
+
+        let single_threaded = translate_with_threads(1)?;
+        assert_eq!(single_threaded.len(), 3000);
+        assert_eq!(single_threaded, translate_with_threads(4)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_streamed_translation_matches_batch() -> Result<()> {
+        let path = TempPath::new("stream.fasta");
+        write_fasta_sequences(&path, &synthetic_records(2000))?;
+        let options = TranslationOptions::default();
+
+        let batch = translate_records(load_fastas(std::slice::from_ref(&*path))?, &options)?;
+        let mut streamed = Vec::new();
+        translate_stream_each(
+            SequenceStream::new(std::slice::from_ref(&*path)),
+            &options,
+            1,
+            Progress::unknown_total(),
+            |seq_name, _, translated_seq| {
+                streamed.push((seq_name, translated_seq));
+                Ok(())
+            },
+        )?;
+
+        assert_eq!(streamed.len(), 2000);
+        assert_eq!(streamed, batch.into_iter().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_descriptions_are_passed_through() -> Result<()> {
+        let record = |id: &str, description: Option<&str>, seq: &[u8]| {
+            (
+                id.to_string(),
+                description.map(str::to_string),
+                seq.to_vec(),
+            )
+        };
+        let records = vec![
+            Ok(record("A", Some("sample=1 visit=2"), b"ATGTGG")),
+            Ok(record("B", None, b"AAA")),
+        ];
+        let mut translated = Vec::new();
+        translate_stream_each(
+            records,
+            &TranslationOptions::default(),
+            1,
+            Progress::new(2),
+            |seq_name, description, translated_seq| {
+                translated.push((seq_name, description, translated_seq));
+                Ok(())
+            },
+        )?;
+
+        assert_eq!(
+            translated,
+            vec![
+                record("A", Some("sample=1 visit=2"), b"MW"),
+                record("B", None, b"K"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_description() {
+        assert_eq!(output_description(None, None), None);
+        assert_eq!(
+            output_description(Some("sample=1"), None),
+            Some("sample=1".to_string())
+        );
+        assert_eq!(
+            output_description(None, Some("frame=1 table=1")),
+            Some("frame=1 table=1".to_string())
+        );
+        assert_eq!(
+            output_description(Some("sample=1"), Some("frame=1 table=1")),
+            Some("sample=1 frame=1 table=1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_translate_fastq() -> Result<()> {
+        let path = TempPath::new("translate.fastq");
+        std::fs::write(
+            &path,
+            "@A\nATGTGGTAA\n+\nIIIIIIIII\n@B\nATGAAA\n+\nIIIIII\n",
+        )?;
+
+        let sequences = load_fastas(std::slice::from_ref(&*path))?;
+        let translated = translate_records(sequences, &TranslationOptions::default())?;
+
+        assert_eq!(translated["A"], b"MW*".to_vec());
+        assert_eq!(translated["B"], b"MK".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pick_reading_frame() -> Result<()> {
+        let options = TranslationOptions::default();
+
+        // Frames 0 (TGA TGA ...) and 2 (ATG AAA TGA ...) have internal stops, but frame 1 doesn't.
+        let (reading_frame, protein) = pick_reading_frame(b"TGATGAAATGACCTAA", &options)?;
+        assert_eq!(reading_frame, 1);
+        assert_eq!(protein, b"DEMT*".to_vec());
+
+        // A stop at the end doesn't count against a frame.
+        let (reading_frame, _) = pick_reading_frame(b"ATGAAATAA", &options)?;
+        assert_eq!(reading_frame, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame_output_path() {
+        let split_dir = Path::new("frames");
+        assert_eq!(
+            frame_output_path(split_dir, Path::new("results/out.fa"), 0),
+            PathBuf::from("frames/out.frame0.fa")
+        );
+        assert_eq!(
+            frame_output_path(split_dir, Path::new("out.fasta"), 2),
+            PathBuf::from("frames/out.frame2.fasta")
+        );
+        assert_eq!(
+            frame_output_path(split_dir, Path::new("out"), 1),
+            PathBuf::from("frames/out.frame1.fa")
+        );
+    }
+
+    #[test]
+    fn test_translate_records_concatenated() -> Result<()> {
+        // The reverse complement of this sequence is ATGTTATAA.
+        let sequences: FastaRecords = velcro::map_iter!(
+            "A".to_string(): b"TTATAACAT".to_vec(),
+        ).collect();
+
+        let translated =
+            translate_records_concatenated(sequences, &TranslationOptions::default(), b'|')?;
+
+        assert_eq!(translated["A"], b"L*H|YN|IT|ML*|CY|VI".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_translate_records_six_frames() -> Result<()> {
+        // The reverse complement of this sequence is ATG---GAY: R complements to Y, gaps to gaps.
+        let sequences: FastaRecords = velcro::map_iter!(
+            "A".to_string(): b"RTC---CAT".to_vec(),
+        ).collect();
+
+        let translated = translate_records_six_frames(sequences, &TranslationOptions::default())?;
+
+        assert_eq!(
+            translated.keys().collect::<Vec<_>>(),
+            ["A_f1", "A_f2", "A_f3", "A_r1", "A_r2", "A_r3"]
+        );
+        assert_eq!(translated["A_f1"], b"X-H".to_vec());
+        assert_eq!(translated["A_r1"], b"M-D".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_translate_msa() -> Result<()> {
+        let sequences: FastaRecords = velcro::map_iter!(
+            "A".to_string(): b"ATGGCC---AAA".to_vec(),
+            "B".to_string(): b"ATG-CCAAAAAA".to_vec(),
+        ).collect();
+        let options = TranslationOptions {
+            strip_gaps: true,
+            ignore_gap_codons: true,
+            ..TranslationOptions::default()
+        };
+
+        let translated = translate_msa(sequences, &options)?;
+
+        assert_eq!(translated["A"], b"MA-K".to_vec());
+        assert_eq!(translated["B"], b"MXKK".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_translate_msa_rejects_bad_alignments() {
+        let unequal: FastaRecords = velcro::map_iter!(
+            "A".to_string(): b"ATGGCC".to_vec(),
+            "B".to_string(): b"ATG".to_vec(),
+        ).collect();
+        assert!(translate_msa(unequal, &TranslationOptions::default()).is_err());
+
+        let not_codons: FastaRecords = velcro::map_iter!(
+            "A".to_string(): b"ATGGC".to_vec(),
+            "B".to_string(): b"ATGGC".to_vec(),
+        ).collect();
+        assert!(translate_msa(not_codons, &TranslationOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_infer_reading_frame() {
+        // The reference's codons start at position 1.
+        let reference = b"GATGGCCAAAGGGTTTCCCAAA";
+
+        // The query starts at the reference's second codon (GCC), so it is in frame 0.
+        assert_eq!(infer_reading_frame(b"GCCAAAGGGTTT", reference, 1), Some(0));
+        // Starting on the last base of the first codon (ATG) pushes the next codon along by one.
+        assert_eq!(infer_reading_frame(b"GGCCAAAGGGTTT", reference, 1), Some(1));
+        // Starting one base into GCC leaves two bases before the next codon.
+        assert_eq!(infer_reading_frame(b"CCAAAGGGTTT", reference, 1), Some(2));
+    }
+
+    #[test]
+    fn test_translate_records_with_reference() -> Result<()> {
+        let reference = b"ATGGCCAAAGGGTTTCCC";
+        let sequences: FastaRecords = velcro::map_iter!(
+            "A".to_string(): b"T-GGCCAAAGGGTTT".to_vec(),
+        ).collect();
+
+        let (translated, frames) = translate_records_with_reference(
+            sequences,
+            &TranslationOptions::default(),
+            reference,
+            0,
+        )?;
+
+        assert_eq!(frames["A"], 2);
+        assert_eq!(translated["A"], b"AKGF".to_vec());
+        Ok(())
+    }
+}
@@ -1,25 +1,29 @@
-use crate::utils::codon_tables::STOP_CODONS;
+use crate::utils::codon_tables::GeneticCode;
 use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
+use crate::utils::summary::RunSummary;
+use crate::utils::translate::is_stop_codon;
 use anyhow::Result;
 use colored::Colorize;
-use std::collections::HashMap;
 use std::path::PathBuf;
 
-fn trim_sequence(sequence: &Vec<u8>, include_stop_codon: bool) -> Result<Vec<u8>> {
-    let first_stop_codon_index = sequence
-        .chunks(3)
-        .into_iter()
-        .position(|codon| STOP_CODONS.contains(<&[u8; 3]>::try_from(codon).unwrap_or(&[0; 3])));
+fn trim_sequence(
+    sequence: &[u8],
+    include_stop_codon: bool,
+    genetic_code: GeneticCode,
+) -> Result<Vec<u8>> {
+    let first_stop_codon_index = sequence.chunks(3).position(|codon| {
+        is_stop_codon(<&[u8; 3]>::try_from(codon).unwrap_or(&[0; 3]), genetic_code)
+    });
 
     match first_stop_codon_index {
-        None => Ok(sequence.clone()),
+        None => Ok(sequence.to_vec()),
         Some(index) => {
             let trim_index = if include_stop_codon {
                 (index + 1) * 3
             } else {
                 index * 3
             };
-            Ok(sequence.clone()[..trim_index].to_vec())
+            Ok(sequence[..trim_index].to_vec())
         }
     }
 }
@@ -27,18 +31,25 @@ fn trim_sequence(sequence: &Vec<u8>, include_stop_codon: bool) -> Result<Vec<u8>
 pub(crate) fn process_file(
     sequences: FastaRecords,
     include_stop_codon: bool,
+    genetic_code: GeneticCode,
 ) -> Result<FastaRecords> {
-    let mut output_sequences = HashMap::<String, Vec<u8>>::with_capacity(sequences.len());
+    let mut output_sequences = FastaRecords::with_capacity(sequences.len());
 
     for (seq_name, sequence) in sequences {
-        let trimmed_sequence = trim_sequence(&sequence, include_stop_codon)?;
+        let trimmed_sequence = trim_sequence(&sequence, include_stop_codon, genetic_code)?;
         output_sequences.insert(seq_name, trimmed_sequence);
     }
 
     Ok(output_sequences)
 }
 
-pub fn run(input_file: &PathBuf, output_file: &PathBuf, include_stop_codon: bool) -> Result<()> {
+pub fn run(
+    input_file: &PathBuf,
+    output_file: &PathBuf,
+    include_stop_codon: bool,
+    genetic_code: GeneticCode,
+    summary: &mut RunSummary,
+) -> Result<()> {
     log::info!(
         "{}",
         format!(
@@ -51,9 +62,13 @@ pub fn run(input_file: &PathBuf, output_file: &PathBuf, include_stop_codon: bool
 
     log::info!("Reading input file {:?}", input_file);
     let sequences = load_fasta(input_file)?;
-    let trimmed_sequences = process_file(sequences, include_stop_codon)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+    let trimmed_sequences = process_file(sequences, include_stop_codon, genetic_code)?;
 
     write_fasta_sequences(output_file, &trimmed_sequences)?;
+    summary.output(output_file);
+    summary.count("written", trimmed_sequences.len());
 
     Ok(())
 }
@@ -67,7 +82,7 @@ mod tests {
         let input = b"CATAATGTTTGGGCTACACATGCCTGTGTA".to_vec();
         let expected = b"CATAATGTTTGGGCTACACATGCCTGTGTA".to_vec();
 
-        let obtained = trim_sequence(&input, false).unwrap();
+        let obtained = trim_sequence(&input, false, GeneticCode::Standard).unwrap();
         assert_eq!(
             String::from_utf8(obtained).unwrap(),
             String::from_utf8(expected).unwrap()
@@ -79,7 +94,7 @@ mod tests {
         let input = b"GCTTTGCTATAAGATAGGATTATTGAAGTAGCACAAAGAATC".to_vec();
         let expected = b"GCTTTGCTATAA".to_vec();
 
-        let obtained = trim_sequence(&input, true).unwrap();
+        let obtained = trim_sequence(&input, true, GeneticCode::Standard).unwrap();
         assert_eq!(
             String::from_utf8(obtained).unwrap(),
             String::from_utf8(expected).unwrap()
@@ -91,7 +106,7 @@ mod tests {
         let input = b"TAAGCTTTGCTA".to_vec();
         let expected = b"TAA".to_vec();
 
-        let obtained = trim_sequence(&input, true).unwrap();
+        let obtained = trim_sequence(&input, true, GeneticCode::Standard).unwrap();
         assert_eq!(
             String::from_utf8(obtained).unwrap(),
             String::from_utf8(expected).unwrap()
@@ -103,7 +118,7 @@ mod tests {
         let input = b"GAAGGAACAGATAGGATTATTGA".to_vec();
         let expected = b"GAAGGAACAGATAGGATTATTGA".to_vec();
 
-        let obtained = trim_sequence(&input, true).unwrap();
+        let obtained = trim_sequence(&input, true, GeneticCode::Standard).unwrap();
         assert_eq!(
             String::from_utf8(obtained).unwrap(),
             String::from_utf8(expected).unwrap()
@@ -115,7 +130,7 @@ mod tests {
         let input = b"GCTTTGCTATAAGATAGGATTATTGAAGTAGCACAAAGAATC".to_vec();
         let expected = b"GCTTTGCTA".to_vec();
 
-        let obtained = trim_sequence(&input, false).unwrap();
+        let obtained = trim_sequence(&input, false, GeneticCode::Standard).unwrap();
         assert_eq!(
             String::from_utf8(obtained).unwrap(),
             String::from_utf8(expected).unwrap()
@@ -127,10 +142,21 @@ mod tests {
         let input = b"TAAGCTTTGCTA".to_vec();
         let expected = b"".to_vec();
 
-        let obtained = trim_sequence(&input, false).unwrap();
+        let obtained = trim_sequence(&input, false, GeneticCode::Standard).unwrap();
         assert_eq!(
             String::from_utf8(obtained).unwrap(),
             String::from_utf8(expected).unwrap()
         );
     }
+
+    #[test]
+    fn test_trim_uses_genetic_code() {
+        let input = b"ATGTGAGCTAGAGCT".to_vec();
+
+        let standard = trim_sequence(&input, true, GeneticCode::Standard).unwrap();
+        assert_eq!(standard, b"ATGTGA");
+        let mitochondrial =
+            trim_sequence(&input, true, GeneticCode::VertebrateMitochondrial).unwrap();
+        assert_eq!(mitochondrial, b"ATGTGAGCTAGA");
+    }
 }
@@ -0,0 +1,170 @@
+use crate::utils::fasta_utils::{load_fasta, write_fasta_sequences, FastaRecords};
+use crate::utils::summary::RunSummary;
+use anyhow::Result;
+use colored::Colorize;
+use std::path::PathBuf;
+
+/// The length of the run of `base` at the start of `seq`, allowing up to `max_mismatches` other
+/// bases inside the run. A run always starts and ends on `base`, so mismatches on either side of
+/// it are not counted.
+fn run_length<'a>(seq: impl Iterator<Item = &'a u8>, base: u8, max_mismatches: usize) -> usize {
+    let mut mismatches = 0;
+    let mut run_length = 0;
+
+    for (idx, &nt) in seq.enumerate() {
+        if nt == base {
+            run_length = idx + 1;
+        } else {
+            mismatches += 1;
+            if idx == 0 || mismatches > max_mismatches {
+                break;
+            }
+        }
+    }
+
+    run_length
+}
+
+/// The longest run of any of `bases` at one end of a sequence, if it is at least `min_length` long.
+fn longest_run<'a, I>(
+    seq: impl Fn() -> I,
+    bases: &[u8],
+    min_length: usize,
+    max_mismatches: usize,
+) -> usize
+where
+    I: Iterator<Item = &'a u8>,
+{
+    bases
+        .iter()
+        .map(|&base| run_length(seq(), base, max_mismatches))
+        .filter(|&length| length >= min_length)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Trim leading and trailing homopolymer runs of any of `bases` that are at least `min_length`
+/// long. Returns the trimmed sequence and the number of bases removed.
+pub(crate) fn trim_homopolymer(
+    seq: &[u8],
+    bases: &[u8],
+    min_length: usize,
+    max_mismatches: usize,
+) -> (Vec<u8>, usize) {
+    let leading = longest_run(|| seq.iter(), bases, min_length, max_mismatches);
+    let trailing = longest_run(|| seq[leading..].iter().rev(), bases, min_length, max_mismatches);
+
+    let trimmed = seq[leading..seq.len() - trailing].to_vec();
+    (trimmed, leading + trailing)
+}
+
+pub(crate) fn trim_homopolymers(
+    sequences: FastaRecords,
+    bases: &[u8],
+    min_length: usize,
+    max_mismatches: usize,
+) -> (FastaRecords, usize) {
+    let mut trimmed_sequences = FastaRecords::with_capacity(sequences.len());
+    let mut total_trimmed = 0;
+
+    for (seq_name, seq) in sequences {
+        let (trimmed_seq, num_trimmed) = trim_homopolymer(&seq, bases, min_length, max_mismatches);
+        if num_trimmed > 0 {
+            log::debug!("Trimmed {} bases from {}", num_trimmed, seq_name);
+        }
+        total_trimmed += num_trimmed;
+        trimmed_sequences.insert(seq_name, trimmed_seq);
+    }
+
+    (trimmed_sequences, total_trimmed)
+}
+
+pub fn run(
+    input_file: &PathBuf,
+    output_file: &PathBuf,
+    bases: &[u8],
+    min_length: usize,
+    max_mismatches: usize,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    log::info!(
+        "{}",
+        format!(
+            "This is 'trim-homopolymer' version {}",
+            env!("CARGO_PKG_VERSION")
+        )
+        .bold()
+        .bright_yellow()
+    );
+
+    log::info!("Reading input file {:?}", input_file);
+    let sequences = load_fasta(input_file)?;
+    summary.input(input_file);
+    summary.count("read", sequences.len());
+    let (trimmed_sequences, total_trimmed) =
+        trim_homopolymers(sequences, bases, min_length, max_mismatches);
+    log::info!(
+        "Trimmed {} bases from {} sequences.",
+        total_trimmed,
+        trimmed_sequences.len()
+    );
+
+    write_fasta_sequences(output_file, &trimmed_sequences)?;
+    summary.output(output_file);
+    summary.count("written", trimmed_sequences.len());
+    summary.count("trimmed_bases", total_trimmed);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_poly_a_tail() {
+        let (trimmed, num_trimmed) = trim_homopolymer(b"ATGCGTCAAAAAAAA", b"AT", 5, 0);
+
+        assert_eq!(trimmed, b"ATGCGTC".to_vec());
+        assert_eq!(num_trimmed, 8);
+    }
+
+    #[test]
+    fn test_trim_poly_t_head() {
+        let (trimmed, num_trimmed) = trim_homopolymer(b"TTTTTTGCGCAGC", b"AT", 5, 0);
+
+        assert_eq!(trimmed, b"GCGCAGC".to_vec());
+        assert_eq!(num_trimmed, 6);
+    }
+
+    #[test]
+    fn test_short_run_is_kept() {
+        let (trimmed, num_trimmed) = trim_homopolymer(b"GCGCAGCAAA", b"AT", 5, 0);
+
+        assert_eq!(trimmed, b"GCGCAGCAAA".to_vec());
+        assert_eq!(num_trimmed, 0);
+    }
+
+    #[test]
+    fn test_mismatch_tolerance() {
+        let seq = b"GCGCCGCAAAAGAAAA";
+
+        // Without tolerance the tail is only four bases long, so it is kept.
+        let (strict, _) = trim_homopolymer(seq, b"A", 5, 0);
+        assert_eq!(strict, seq.to_vec());
+
+        let (tolerant, num_trimmed) = trim_homopolymer(seq, b"A", 5, 1);
+        assert_eq!(tolerant, b"GCGCCGC".to_vec());
+        assert_eq!(num_trimmed, 9);
+    }
+
+    #[test]
+    fn test_run_does_not_end_on_mismatch() {
+        // The G's around the run are mismatches, but they should not be trimmed with it.
+        let (trimmed, _) = trim_homopolymer(b"GGAAAAAACGCG", b"A", 5, 2);
+        assert_eq!(trimmed, b"GGAAAAAACGCG".to_vec());
+
+        let (trimmed, _) = trim_homopolymer(b"AAAAAAGCGC", b"A", 5, 2);
+        assert_eq!(trimmed, b"GCGC".to_vec());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::utils::fasta_utils::{write_fasta_sequences, FastaRecords};
+use crate::utils::summary::RunSummary;
 use anyhow::{Context, Result};
 
 use bio::bio_types::sequence::SequenceRead;
@@ -6,7 +7,6 @@ use colored::Colorize;
 use log::warn;
 use rust_htslib::bam::ext::BamRecordExtensions;
 use rust_htslib::{bam, bam::Read, bam::Record};
-use std::collections::HashMap;
 use std::path::PathBuf;
 
 fn find_read_pos_from_ref_pos(read: &Record, ref_pos: i64) -> Option<i64> {
@@ -27,6 +27,7 @@ pub fn run(
     output_file: &PathBuf,
     trim_from: i64,
     trim_to: i64,
+    summary: &mut RunSummary,
 ) -> Result<()> {
     log::info!(
         "{}",
@@ -37,7 +38,7 @@ pub fn run(
 
     let mut reader = bam::Reader::from_path(input_file)?;
 
-    let mut output_seqs: FastaRecords = HashMap::new();
+    let mut output_seqs = FastaRecords::new();
 
     for record in reader.records() {
         let record = record?;
@@ -68,6 +69,9 @@ pub fn run(
 
     write_fasta_sequences(output_file, &output_seqs)
         .with_context(|| format!("Failed to write output file {:?}", output_file))?;
+    summary.input(input_file);
+    summary.output(output_file);
+    summary.count("written", output_seqs.len());
 
     Ok(())
 }
@@ -0,0 +1,86 @@
+use crate::utils::fasta_utils::FastaRecords;
+use crate::utils::output::{create_output_file, finish_csv};
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The hex-encoded SHA-256 digest of a sequence, as `sha256sum` would print it.
+pub fn sequence_checksum(seq: &[u8]) -> String {
+    Sha256::digest(seq)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Write a TSV of each record's ID and the checksum of its sequence, sorted by ID so the files
+/// from two runs can be diffed directly.
+pub fn write_checksums(output_file: &PathBuf, sequences: &FastaRecords) -> Result<()> {
+    let checksums: BTreeMap<String, String> = sequences
+        .iter()
+        .map(|(seq_name, seq)| (seq_name.clone(), sequence_checksum(seq)))
+        .collect();
+
+    write_checksum_table(output_file, &checksums)
+}
+
+/// Write already computed checksums as in `write_checksums`, for tools that don't keep the
+/// sequences themselves around.
+pub fn write_checksum_table(
+    output_file: &PathBuf,
+    checksums: &BTreeMap<String, String>,
+) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(create_output_file(output_file)?);
+    writer.write_record(["seq_name", "sha256"])?;
+
+    for (seq_name, checksum) in checksums {
+        writer.write_record([seq_name, checksum])?;
+    }
+
+    finish_csv(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempPath;
+
+    #[test]
+    fn test_sequence_checksum() {
+        assert_eq!(
+            sequence_checksum(b"ATG"),
+            "69126cc1893fdd258858b03dbea64ebf1ee3d395f73527c7acdc3e4d5e407091"
+        );
+        assert_eq!(
+            sequence_checksum(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_write_checksums() -> Result<()> {
+        let path = TempPath::new("checksums.tsv");
+        let sequences: FastaRecords = velcro::map_iter!(
+            "B".to_string(): b"ATG-CA".to_vec(),
+            "C".to_string(): b"ATGCA".to_vec(),
+            "A".to_string(): b"ATG-CA".to_vec(),
+        ).collect();
+
+        write_checksums(&path, &sequences)?;
+        let table = std::fs::read_to_string(&path)?;
+
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .map(|line| line.split('\t').collect())
+            .collect();
+        let ids: Vec<&str> = rows.iter().map(|row| row[0]).collect();
+        assert_eq!(ids, ["seq_name", "A", "B", "C"]);
+        // A and B have different IDs but the same sequence, and C differs only by the gap.
+        assert_eq!(rows[1][1], rows[2][1]);
+        assert_ne!(rows[1][1], rows[3][1]);
+        assert_eq!(rows[1][1], sequence_checksum(b"ATG-CA"));
+        Ok(())
+    }
+}
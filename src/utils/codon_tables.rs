@@ -1,7 +1,105 @@
+use crate::error::PursError;
 use phf::{phf_map, phf_set};
+use std::fmt;
+use std::str::FromStr;
 
 pub const GAP_CHAR: u8 = b"-"[0];
 pub const DEFAULT_STOP_CHAR: u8 = b"*"[0];
+/// The NCBI genetic code ID of `CODON_TABLE` (the standard code).
+pub const STANDARD_TABLE_ID: u8 = 1;
+
+/// The NCBI genetic codes (translation tables) that can be translated with. Each is described by
+/// how it differs from the standard code in `CODON_TABLE` and `STOP_CODONS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GeneticCode {
+    #[default]
+    Standard,
+    VertebrateMitochondrial,
+    MoldMitochondrial,
+    InvertebrateMitochondrial,
+    Bacterial,
+}
+
+impl GeneticCode {
+    pub const ALL: [GeneticCode; 5] = [
+        GeneticCode::Standard,
+        GeneticCode::VertebrateMitochondrial,
+        GeneticCode::MoldMitochondrial,
+        GeneticCode::InvertebrateMitochondrial,
+        GeneticCode::Bacterial,
+    ];
+
+    /// The NCBI translation table ID.
+    pub fn id(&self) -> u8 {
+        match self {
+            GeneticCode::Standard => STANDARD_TABLE_ID,
+            GeneticCode::VertebrateMitochondrial => 2,
+            GeneticCode::MoldMitochondrial => 4,
+            GeneticCode::InvertebrateMitochondrial => 5,
+            GeneticCode::Bacterial => 11,
+        }
+    }
+
+    /// The codons whose translation differs from the standard code, with `DEFAULT_STOP_CHAR`
+    /// for a codon that is a stop in this code.
+    pub(crate) fn codon_changes(&self) -> &'static phf::Map<&'static [u8; 3], &'static [u8; 1]> {
+        match self {
+            GeneticCode::Standard | GeneticCode::Bacterial => &NO_CODON_CHANGES,
+            GeneticCode::VertebrateMitochondrial => &VERTEBRATE_MITOCHONDRIAL_CHANGES,
+            GeneticCode::MoldMitochondrial => &MOLD_MITOCHONDRIAL_CHANGES,
+            GeneticCode::InvertebrateMitochondrial => &INVERTEBRATE_MITOCHONDRIAL_CHANGES,
+        }
+    }
+}
+
+impl TryFrom<u8> for GeneticCode {
+    type Error = PursError;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        GeneticCode::ALL
+            .into_iter()
+            .find(|code| code.id() == id)
+            .ok_or(PursError::UnknownGeneticCode { id: id.to_string() })
+    }
+}
+
+impl FromStr for GeneticCode {
+    type Err = PursError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse::<u8>()
+            .map_err(|_| PursError::UnknownGeneticCode { id: s.to_string() })
+            .and_then(GeneticCode::try_from)
+    }
+}
+
+impl fmt::Display for GeneticCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+// https://www.ncbi.nlm.nih.gov/Taxonomy/Utils/wprintgc.cgi
+static NO_CODON_CHANGES: phf::Map<&[u8; 3], &[u8; 1]> = phf_map! {};
+
+static VERTEBRATE_MITOCHONDRIAL_CHANGES: phf::Map<&[u8; 3], &[u8; 1]> = phf_map! {
+    b"AGA" => b"*",
+    b"AGG" => b"*",
+    b"ATA" => b"M",
+    b"TGA" => b"W",
+};
+
+static MOLD_MITOCHONDRIAL_CHANGES: phf::Map<&[u8; 3], &[u8; 1]> = phf_map! {
+    b"TGA" => b"W",
+};
+
+static INVERTEBRATE_MITOCHONDRIAL_CHANGES: phf::Map<&[u8; 3], &[u8; 1]> = phf_map! {
+    b"AGA" => b"S",
+    b"AGG" => b"S",
+    b"ATA" => b"M",
+    b"TGA" => b"W",
+};
 
 pub(crate) static CODON_TABLE: phf::Map<&[u8; 3], &[u8; 1]> = phf_map! {
         b"TTT" => b"F",
@@ -1,9 +1,24 @@
-use anyhow::{Context, Result};
-use bio::io::fasta;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::utils::output::{create_output_file, is_gzipped, is_stdio, OutputFile};
+use crate::error::PursError;
+use anyhow::{bail, Result};
+use bio::alphabets::dna::revcomp;
+use bio::io::{fasta, fastq};
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use indexmap::IndexMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-pub type FastaRecords = HashMap<String, Vec<u8>>;
+/// FASTA records keyed by ID, in the order they were read (or inserted), so output is written in
+/// input order.
+pub type FastaRecords = IndexMap<String, Vec<u8>>;
+pub type FastaDescriptions = HashMap<String, String>;
+/// The (Phred+33) quality strings of FASTQ records, keyed by ID like `FastaRecords`.
+pub type QualityRecords = IndexMap<String, Vec<u8>>;
 
 #[derive(Clone, Copy)]
 pub enum SequenceType {
@@ -12,28 +27,903 @@ pub enum SequenceType {
 }
 pub fn write_fasta_sequences(
     output_file: &PathBuf,
-    sequences: &HashMap<String, Vec<u8>>,
+    sequences: &FastaRecords,
 ) -> Result<()> {
-    let mut writer =
-        fasta::Writer::to_file(output_file).with_context(|| "Could not open output file")?;
+    write_fasta_sequences_with_descriptions(output_file, sequences, &FastaDescriptions::new())
+}
+
+/// Write sequences to a FASTA file, adding the matching entry from `descriptions` (if there is
+/// one) after the ID in each record's header.
+pub fn write_fasta_sequences_with_descriptions(
+    output_file: &PathBuf,
+    sequences: &FastaRecords,
+    descriptions: &FastaDescriptions,
+) -> Result<()> {
+    let mut writer = FastaWriter::new(output_file)?;
 
     for (seq_id, seq) in sequences {
-        writer.write(seq_id.as_str(), None, seq.as_slice())?;
+        let description = descriptions.get(seq_id).map(String::as_str);
+        writer.write_record(seq_id, description, seq)?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+static LINE_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Wrap the sequences written by `FastaWriter` at `width` characters per line, or write each one
+/// on a single line if it is 0. Set once from the global `--line-width` option.
+pub fn set_line_width(width: usize) {
+    LINE_WIDTH.store(width, Ordering::Relaxed);
+}
+
+/// What to do when an input file repeats a sequence ID.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum DuplicateIdPolicy {
+    /// Fail, naming the repeated ID
+    #[default]
+    Error,
+    /// Keep the first record with the ID and drop the repeats
+    First,
+    /// Keep the last record with the ID, in the place of the first
+    Last,
+    /// Keep every record, renaming the repeats to ID_1, ID_2, ...
+    Rename,
+}
+
+static DUPLICATE_ID_POLICY: Mutex<DuplicateIdPolicy> = Mutex::new(DuplicateIdPolicy::Error);
+
+/// Set what loading a file does with a repeated sequence ID. Set once from the global
+/// `--on-duplicate` option.
+pub fn set_duplicate_id_policy(policy: DuplicateIdPolicy) {
+    *DUPLICATE_ID_POLICY.lock().unwrap() = policy;
+}
+
+pub fn duplicate_id_policy() -> DuplicateIdPolicy {
+    *DUPLICATE_ID_POLICY.lock().unwrap()
+}
+
+static SKIP_INVALID: AtomicBool = AtomicBool::new(false);
+
+/// Log and skip input records that fail to parse, rather than failing. Set once from the global
+/// `--skip-invalid` flag.
+pub fn set_skip_invalid(skip: bool) {
+    SKIP_INVALID.store(skip, Ordering::Relaxed);
+}
+
+/// The global `--on-duplicate` and `--skip-invalid` settings, taken once per read and passed down
+/// so that tests can read with other settings without changing the global ones.
+#[derive(Clone, Copy, Default)]
+struct ReadOptions {
+    duplicate_ids: DuplicateIdPolicy,
+    skip_invalid: bool,
+}
+
+impl ReadOptions {
+    fn global() -> Self {
+        Self {
+            duplicate_ids: duplicate_id_policy(),
+            skip_invalid: SKIP_INVALID.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// With `skip_invalid`, log a record that failed to parse and return `None` so it is skipped;
+/// otherwise return the error.
+fn skip_if_allowed<T>(
+    result: Result<T, PursError>,
+    skip_invalid: bool,
+) -> Option<Result<T, PursError>> {
+    match result {
+        Err(e) if skip_invalid => {
+            log::warn!("Skipping an invalid record: {}", e);
+            None
+        }
+        result => Some(result),
+    }
+}
+
+/// Writes FASTA records one at a time as they are produced, so a tool doesn't need to hold all of
+/// its output in memory before writing it.
+pub struct FastaWriter {
+    writer: BufWriter<OutputFile>,
+    line_width: usize,
+    records_written: usize,
+}
+
+impl FastaWriter {
+    pub fn new(output_file: &PathBuf) -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(create_output_file(output_file)?),
+            line_width: LINE_WIDTH.load(Ordering::Relaxed),
+            records_written: 0,
+        })
+    }
+
+    pub fn write_record(
+        &mut self,
+        seq_id: &str,
+        description: Option<&str>,
+        seq: &[u8],
+    ) -> Result<()> {
+        match description {
+            Some(description) => writeln!(self.writer, ">{} {}", seq_id, description)?,
+            None => writeln!(self.writer, ">{}", seq_id)?,
+        }
+        write_wrapped(&mut self.writer, seq, self.line_width)?;
+        self.records_written += 1;
+        Ok(())
+    }
+
+    /// Flush and finish the output, reporting any error rather than losing it when the writer is
+    /// dropped. Returns the number of records written.
+    pub fn finish(self) -> Result<usize> {
+        let output = self.writer.into_inner().map_err(|e| e.into_error())?;
+        output.finish()?;
+        Ok(self.records_written)
     }
+}
 
+/// Write a sequence as lines of at most `line_width` characters, or as one line if it is 0.
+fn write_wrapped<W: Write>(writer: &mut W, seq: &[u8], line_width: usize) -> std::io::Result<()> {
+    if line_width == 0 || seq.is_empty() {
+        writer.write_all(seq)?;
+        return writer.write_all(b"\n");
+    }
+    for line in seq.chunks(line_width) {
+        writer.write_all(line)?;
+        writer.write_all(b"\n")?;
+    }
     Ok(())
 }
 
-pub fn load_fasta(file_path: &PathBuf) -> Result<FastaRecords> {
-    let mut sequences: FastaRecords = FastaRecords::new();
-    let reader = fasta::Reader::from_file(file_path).expect("Could not open file.");
+fn open_file(file_path: &PathBuf) -> Result<File, PursError> {
+    File::open(file_path).map_err(|source| match source.kind() {
+        ErrorKind::NotFound => PursError::FileNotFound {
+            path: file_path.clone(),
+        },
+        _ => PursError::Io {
+            path: file_path.clone(),
+            source,
+        },
+    })
+}
 
-    for result in reader.records() {
-        let record = result.expect("This record is invalid and failed to parse.");
-        let mut seq = record.seq().to_vec();
-        seq.make_ascii_uppercase();
-        sequences.insert(record.id().to_string(), seq);
+/// Open an input for reading: the file at `file_path`, decompressed if it ends in .gz, or stdin if
+/// the path is "-".
+fn open_input(file_path: &PathBuf) -> Result<Box<dyn BufRead>, PursError> {
+    if is_stdio(file_path) {
+        // Stdin's buffer is shared by every lock, so peeking at it (as `is_fastq` does) doesn't
+        // lose any input.
+        Ok(Box::new(std::io::stdin().lock()))
+    } else if is_gzipped(file_path) {
+        let file = open_file(file_path)?;
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(open_file(file_path)?)))
     }
+}
 
+/// Open a FASTA file (or stdin, for "-") for reading record by record.
+pub fn open_fasta(file_path: &PathBuf) -> Result<fasta::Reader<Box<dyn BufRead>>, PursError> {
+    Ok(fasta::Reader::from_bufread(open_input(file_path)?))
+}
+
+/// Whether a file is FASTQ rather than FASTA: it has a .fastq or .fq extension (before any .gz), or
+/// its first character is '@'.
+pub fn is_fastq(file_path: &PathBuf) -> Result<bool, PursError> {
+    let uncompressed_path = if is_gzipped(file_path) {
+        file_path.with_extension("")
+    } else {
+        file_path.clone()
+    };
+    let extension = uncompressed_path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    if matches!(extension.as_deref(), Some("fastq" | "fq")) {
+        return Ok(true);
+    }
+
+    let mut reader = open_input(file_path)?;
+    let buffer = reader.fill_buf().map_err(|source| PursError::Io {
+        path: file_path.clone(),
+        source,
+    })?;
+    Ok(buffer.first() == Some(&b'@'))
+}
+
+/// Load a FASTQ file into memory, uppercasing the sequences as `load_fasta` does, along with the
+/// quality string of each record.
+pub fn load_fastq(file_path: &PathBuf) -> Result<(FastaRecords, QualityRecords), PursError> {
+    read_fastq(open_input(file_path)?, file_path)
+}
+
+/// Read FASTQ records from any reader, such as stdin. `file_path` is only used in errors.
+pub fn read_fastq<B: BufRead>(
+    reader: B,
+    file_path: &Path,
+) -> Result<(FastaRecords, QualityRecords), PursError> {
+    let options = ReadOptions::global();
+    let records = fastq_records(reader, file_path, options);
+    let loaded = collect_records(records, file_path, options)?;
+    Ok((loaded.sequences, loaded.qualities))
+}
+
+/// A record as parsed from a FASTA or FASTQ file, with its sequence uppercased. `qual` is empty
+/// for FASTA.
+struct ParsedRecord {
+    id: String,
+    description: Option<String>,
+    seq: Vec<u8>,
+    qual: Vec<u8>,
+}
+
+/// Records loaded into memory, keyed by ID.
+#[derive(Default)]
+struct LoadedRecords {
+    sequences: FastaRecords,
+    descriptions: FastaDescriptions,
+    qualities: QualityRecords,
+}
+
+/// Load parsed records into memory, handling repeated IDs as `options` says to.
+fn collect_records(
+    records: impl Iterator<Item = Result<ParsedRecord, PursError>>,
+    file_path: &Path,
+    options: ReadOptions,
+) -> Result<LoadedRecords, PursError> {
+    let mut loaded = LoadedRecords::default();
+
+    for record in records {
+        let record = record?;
+        let is_taken = |id: &str| loaded.sequences.contains_key(id);
+        let Some(seq_id) = record_id(is_taken, &record.id, file_path, options.duplicate_ids)?
+        else {
+            continue;
+        };
+        match record.description {
+            Some(description) => loaded.descriptions.insert(seq_id.clone(), description),
+            None => loaded.descriptions.remove(&seq_id),
+        };
+        if !record.qual.is_empty() {
+            loaded.qualities.insert(seq_id.clone(), record.qual);
+        }
+        loaded.sequences.insert(seq_id, record.seq);
+    }
+
+    Ok(loaded)
+}
+
+/// Parse FASTQ records one at a time. With `--skip-invalid`, records that fail validation (such
+/// as a quality string of the wrong length) are skipped, but the file can't be read past a record
+/// with broken structure.
+fn fastq_records<B: BufRead>(
+    reader: B,
+    file_path: &Path,
+    options: ReadOptions,
+) -> impl Iterator<Item = Result<ParsedRecord, PursError>> + use<B> {
+    let file_path = file_path.to_path_buf();
+    let reader = fastq::Reader::from_bufread(reader);
+    reader
+        .records()
+        .enumerate()
+        .filter_map(move |(idx, result)| {
+            let parse_error = |message: String| PursError::FastqParse {
+                path: file_path.clone(),
+                record: idx + 1,
+                message,
+            };
+            let record = match result {
+                Ok(record) => record,
+                Err(e) => return Some(Err(parse_error(e.to_string()))),
+            };
+            if let Err(e) = record.check() {
+                return skip_if_allowed(Err(parse_error(e.to_string())), options.skip_invalid);
+            }
+
+            let mut seq = record.seq().to_vec();
+            seq.make_ascii_uppercase();
+            Some(Ok(ParsedRecord {
+                id: record.id().to_string(),
+                description: record.desc().map(str::to_string),
+                seq,
+                qual: record.qual().to_vec(),
+            }))
+        })
+}
+
+/// Load a FASTA or FASTQ file (see `is_fastq`) into memory, discarding any qualities.
+pub fn load_sequences(file_path: &PathBuf) -> Result<FastaRecords, PursError> {
+    let (sequences, _) = load_sequences_with_descriptions(file_path)?;
     Ok(sequences)
 }
+
+/// Load a FASTA or FASTQ file as `load_sequences` does, along with the description that follows
+/// the ID in each record's header (for the records that have one).
+pub fn load_sequences_with_descriptions(
+    file_path: &PathBuf,
+) -> Result<(FastaRecords, FastaDescriptions), PursError> {
+    let loaded = load_sequences_with(file_path, ReadOptions::global())?;
+    Ok((loaded.sequences, loaded.descriptions))
+}
+
+fn load_sequences_with(
+    file_path: &PathBuf,
+    options: ReadOptions,
+) -> Result<LoadedRecords, PursError> {
+    let fastq = is_fastq(file_path)?;
+    let reader = open_input(file_path)?;
+    if fastq {
+        log::debug!("Reading {:?} as FASTQ", file_path);
+        let records = fastq_records(reader, file_path, options);
+        collect_records(records, file_path, options)
+    } else {
+        let records = fasta_records(reader, file_path, options);
+        collect_records(records, file_path, options)
+    }
+}
+
+/// Load a FASTA file into memory, uppercasing the sequences. Record IDs are kept as UTF-8 strings,
+/// and only the sequences are handled as bytes, so non-ASCII IDs are never split or case-changed.
+pub fn load_fasta(file_path: &PathBuf) -> Result<FastaRecords, PursError> {
+    read_fasta(open_input(file_path)?, file_path)
+}
+
+/// Load a FASTA file as `load_fasta` does, along with the description that follows the ID in each
+/// record's header (for the records that have one).
+pub fn load_fasta_with_descriptions(
+    file_path: &PathBuf,
+) -> Result<(FastaRecords, FastaDescriptions), PursError> {
+    let options = ReadOptions::global();
+    let records = fasta_records(open_input(file_path)?, file_path, options);
+    let loaded = collect_records(records, file_path, options)?;
+    Ok((loaded.sequences, loaded.descriptions))
+}
+
+/// Read FASTA records from any reader, such as stdin, as `load_fasta` does. `file_path` is only
+/// used in errors.
+pub fn read_fasta<B: BufRead>(reader: B, file_path: &Path) -> Result<FastaRecords, PursError> {
+    read_fasta_with(reader, file_path, ReadOptions::global())
+}
+
+fn read_fasta_with<B: BufRead>(
+    reader: B,
+    file_path: &Path,
+    options: ReadOptions,
+) -> Result<FastaRecords, PursError> {
+    let records = fasta_records(reader, file_path, options);
+    let loaded = collect_records(records, file_path, options)?;
+    Ok(loaded.sequences)
+}
+
+/// Parse FASTA records one at a time. Each record is parsed on its own (see `FastaChunks`), so
+/// with `--skip-invalid` a record that fails to parse is skipped and reading carries on from the
+/// next header. Failing to read the input at all, as with a truncated gzip file, is an I/O error
+/// that can't be skipped.
+fn fasta_records<B: BufRead>(
+    reader: B,
+    file_path: &Path,
+    options: ReadOptions,
+) -> impl Iterator<Item = Result<ParsedRecord, PursError>> + use<B> {
+    let file_path = file_path.to_path_buf();
+    let chunks = FastaChunks {
+        reader,
+        next_header: Vec::new(),
+    };
+    chunks.enumerate().filter_map(move |(idx, chunk)| {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(source) => {
+                let path = file_path.clone();
+                return Some(Err(PursError::Io { path, source }));
+            }
+        };
+        // A lone '>' at the end of the input holds no record, and is ignored as bio ignores it.
+        let record = fasta::Reader::new(chunk.as_slice()).records().next()?;
+        match record {
+            Ok(record) => {
+                let mut seq = record.seq().to_vec();
+                seq.make_ascii_uppercase();
+                Some(Ok(ParsedRecord {
+                    id: record.id().to_string(),
+                    description: record.desc().map(str::to_string),
+                    seq,
+                    qual: Vec::new(),
+                }))
+            }
+            Err(error) => {
+                let parse_error = PursError::Parse {
+                    path: file_path.clone(),
+                    record: idx + 1,
+                    error,
+                };
+                skip_if_allowed(Err(parse_error), options.skip_invalid)
+            }
+        }
+    })
+}
+
+/// Splits FASTA input into the raw lines of each record, from a header line up to the next one,
+/// so that one record failing to parse doesn't stop the rest from being read. Anything before the
+/// first header is returned as a record of its own, which then fails to parse.
+struct FastaChunks<B> {
+    reader: B,
+    next_header: Vec<u8>,
+}
+
+impl<B: BufRead> Iterator for FastaChunks<B> {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = std::mem::take(&mut self.next_header);
+        loop {
+            let mut line = Vec::new();
+            match self.reader.read_until(b'\n', &mut line) {
+                Err(e) => return Some(Err(e)),
+                Ok(0) => return (!chunk.is_empty()).then_some(Ok(chunk)),
+                Ok(_) if line.starts_with(b">") && !chunk.is_empty() => {
+                    self.next_header = line;
+                    return Some(Ok(chunk));
+                }
+                Ok(_) => chunk.extend(line),
+            }
+        }
+    }
+}
+
+/// The ID to store a record under, or `None` to drop it, as `policy` says to when `is_taken` shows
+/// a record with the same ID was already read.
+fn record_id(
+    is_taken: impl Fn(&str) -> bool,
+    seq_id: &str,
+    file_path: &Path,
+    policy: DuplicateIdPolicy,
+) -> Result<Option<String>, PursError> {
+    if !is_taken(seq_id) {
+        return Ok(Some(seq_id.to_string()));
+    }
+
+    match policy {
+        DuplicateIdPolicy::Error => Err(PursError::DuplicateId {
+            path: file_path.to_path_buf(),
+            id: seq_id.to_string(),
+        }),
+        DuplicateIdPolicy::First => {
+            log::warn!(
+                "The sequence ID {} appears more than once in {:?}, keeping the first record.",
+                seq_id,
+                file_path
+            );
+            Ok(None)
+        }
+        DuplicateIdPolicy::Last => {
+            log::warn!(
+                "The sequence ID {} appears more than once in {:?}, keeping the last record.",
+                seq_id,
+                file_path
+            );
+            Ok(Some(seq_id.to_string()))
+        }
+        DuplicateIdPolicy::Rename => {
+            let renamed = (1..)
+                .map(|suffix| format!("{}_{}", seq_id, suffix))
+                .find(|renamed| !is_taken(renamed))
+                .unwrap();
+            log::warn!(
+                "The sequence ID {} appears more than once in {:?}, renaming the repeat to {}.",
+                seq_id,
+                file_path,
+                renamed
+            );
+            Ok(Some(renamed))
+        }
+    }
+}
+
+type RecordIter = Box<dyn Iterator<Item = Result<ParsedRecord, PursError>>>;
+
+/// A record's ID, the description from its header (if it has one) and its sequence.
+pub type DescribedSequence = (String, Option<String>, Vec<u8>);
+
+/// Reads the records of one or more FASTA or FASTQ files (see `is_fastq`) one at a time, as if
+/// the files had been concatenated, for tools that handle each record on its own and so don't
+/// need the whole input in memory. Only the IDs already read are kept, to catch repeats, and as in
+/// `load_fastas` an ID repeated in a later file is handled as a repeat within one file would be
+/// in `load_fasta`. A record can't be replaced once it has been passed on, so a repeat is an
+/// error with `--on-duplicate last`, and callers should load the whole input in that case.
+pub struct SequenceStream<'a> {
+    file_paths: std::slice::Iter<'a, PathBuf>,
+    current: Option<(&'a PathBuf, RecordIter)>,
+    seen_ids: HashSet<String>,
+    options: ReadOptions,
+}
+
+impl<'a> SequenceStream<'a> {
+    pub fn new(file_paths: &'a [PathBuf]) -> Self {
+        Self {
+            file_paths: file_paths.iter(),
+            current: None,
+            seen_ids: HashSet::new(),
+            options: ReadOptions::global(),
+        }
+    }
+
+    fn open(file_path: &PathBuf, options: ReadOptions) -> Result<RecordIter, PursError> {
+        let is_fastq = is_fastq(file_path)?;
+        let reader = open_input(file_path)?;
+        if is_fastq {
+            log::debug!("Reading {:?} as FASTQ", file_path);
+            Ok(Box::new(fastq_records(reader, file_path, options)))
+        } else {
+            Ok(Box::new(fasta_records(reader, file_path, options)))
+        }
+    }
+}
+
+impl Iterator for SequenceStream<'_> {
+    type Item = Result<DescribedSequence, PursError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((file_path, records)) = &mut self.current {
+                match records.next() {
+                    Some(Ok(record)) => {
+                        let policy = match self.options.duplicate_ids {
+                            DuplicateIdPolicy::Last => DuplicateIdPolicy::Error,
+                            policy => policy,
+                        };
+                        let is_taken = |id: &str| self.seen_ids.contains(id);
+                        match record_id(is_taken, &record.id, file_path, policy) {
+                            Ok(Some(seq_id)) => {
+                                self.seen_ids.insert(seq_id.clone());
+                                return Some(Ok((seq_id, record.description, record.seq)));
+                            }
+                            Ok(None) => continue,
+                            Err(e) => return Some(Err(e)),
+                        }
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => self.current = None,
+                }
+            }
+
+            let file_path = self.file_paths.next()?;
+            match Self::open(file_path, self.options) {
+                Ok(records) => self.current = Some((file_path, records)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Load several FASTA (or FASTQ) files into one set of records, as if they had been concatenated.
+/// An ID that is already in an earlier file is handled as `--on-duplicate` says to, just like a
+/// repeat within one file.
+pub fn load_fastas(file_paths: &[PathBuf]) -> Result<FastaRecords> {
+    let (sequences, _) = load_fastas_with_descriptions(file_paths)?;
+    Ok(sequences)
+}
+
+/// Load several files as `load_fastas` does, along with the description in each record's header
+/// (for the records that have one).
+pub fn load_fastas_with_descriptions(
+    file_paths: &[PathBuf],
+) -> Result<(FastaRecords, FastaDescriptions)> {
+    Ok(load_fastas_with(file_paths, ReadOptions::global())?)
+}
+
+fn load_fastas_with(
+    file_paths: &[PathBuf],
+    options: ReadOptions,
+) -> Result<(FastaRecords, FastaDescriptions), PursError> {
+    let mut sequences = FastaRecords::new();
+    let mut descriptions = FastaDescriptions::new();
+
+    for file_path in file_paths {
+        let mut loaded = load_sequences_with(file_path, options)?;
+        for (file_id, seq) in loaded.sequences {
+            let is_taken = |id: &str| sequences.contains_key(id);
+            let Some(seq_id) = record_id(is_taken, &file_id, file_path, options.duplicate_ids)?
+            else {
+                continue;
+            };
+            match loaded.descriptions.remove(&file_id) {
+                Some(description) => descriptions.insert(seq_id.clone(), description),
+                None => descriptions.remove(&seq_id),
+            };
+            sequences.insert(seq_id, seq);
+        }
+    }
+
+    Ok((sequences, descriptions))
+}
+
+/// Load a FASTA file that should contain exactly one sequence, such as a reference.
+pub fn load_single_sequence(file_path: &PathBuf) -> Result<Vec<u8>> {
+    let sequences = load_fasta(file_path)?;
+    if sequences.len() != 1 {
+        bail!(
+            "Expected exactly one sequence in {:?}, found {}.",
+            file_path,
+            sequences.len()
+        );
+    }
+    Ok(sequences.into_values().next().unwrap())
+}
+
+/// Reverse complement a nucleotide sequence. IUPAC ambiguity codes are complemented (R<->Y, K<->M,
+/// B<->V, D<->H; S, W and N are their own complements) and case is kept. Gaps and any other
+/// character are left as they are.
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    revcomp(seq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempPath;
+
+    #[test]
+    fn test_load_fastas() -> Result<()> {
+        let first = TempPath::new("load-fastas-1.fasta");
+        let second = TempPath::new("load-fastas-2.fasta");
+        std::fs::write(&first, ">A\natg\n>B\nAAA\n")?;
+        std::fs::write(&second, ">C\nCCC\n>B\nGGG\n")?;
+        let paths = [first.to_path_buf(), second.to_path_buf()];
+        let load = |duplicate_ids| {
+            let options = ReadOptions {
+                duplicate_ids,
+                ..ReadOptions::default()
+            };
+            load_fastas_with(&paths, options).map(|(sequences, _)| sequences)
+        };
+
+        let repeated = load(DuplicateIdPolicy::Error);
+        let first_kept = load(DuplicateIdPolicy::First);
+        let last_kept = load(DuplicateIdPolicy::Last);
+        let renamed = load(DuplicateIdPolicy::Rename);
+
+        // B is in both files, which is a repeat like any other.
+        assert!(matches!(
+            repeated,
+            Err(PursError::DuplicateId { path, id }) if path == *second && id == "B"
+        ));
+        let first_kept = first_kept?;
+        assert_eq!(first_kept.keys().collect::<Vec<_>>(), ["A", "B", "C"]);
+        assert_eq!(first_kept["A"], b"ATG".to_vec());
+        assert_eq!(first_kept["B"], b"AAA".to_vec());
+        assert_eq!(last_kept?["B"], b"GGG".to_vec());
+        assert_eq!(renamed?.keys().collect::<Vec<_>>(), ["A", "B", "C", "B_1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_fasta_non_utf8_header() -> Result<()> {
+        let path = TempPath::new("latin1.fasta");
+        std::fs::write(&path, b">M\xfcller_2019\nATG\n")?;
+
+        let error = load_fasta(&path).unwrap_err();
+        let message = error.to_string();
+        assert!(matches!(error, PursError::Parse { record: 1, .. }));
+        assert!(message.ends_with(": stream did not contain valid UTF-8"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_truncated_fasta() -> Result<()> {
+        let fasta = TempPath::new("truncated.fasta");
+        let gzipped = TempPath::new("truncated.fasta.gz");
+        std::fs::write(&fasta, ">A\nATG\n>B\nAT\n>")?;
+        let sequences: FastaRecords = (0..200)
+            .map(|i| (format!("seq{}", i), b"ATGTGGAAA".repeat(10)))
+            .collect();
+        write_fasta_sequences(&gzipped, &sequences)?;
+        let compressed = std::fs::read(&gzipped)?;
+        std::fs::write(&gzipped, &compressed[..compressed.len() / 2])?;
+
+        let sequences = load_fasta(&fasta)?;
+        let truncated = load_fasta(&gzipped);
+
+        assert_eq!(sequences.keys().collect::<Vec<_>>(), ["A", "B"]);
+        assert_eq!(sequences["B"], b"AT".to_vec());
+        assert!(matches!(truncated, Err(PursError::Io { path, .. }) if path == *gzipped));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_fasta_skip_invalid() -> Result<()> {
+        let input: &[u8] = b">A\nATG\n>M\xfcller\nCCC\nGGG\n>B\nTGG\n";
+        let path = Path::new("invalid.fasta");
+        let skip_invalid = ReadOptions {
+            skip_invalid: true,
+            ..ReadOptions::default()
+        };
+
+        let result = read_fasta_with(input, path, ReadOptions::default());
+        let skipped = read_fasta_with(input, path, skip_invalid)?;
+
+        assert!(matches!(result, Err(PursError::Parse { record: 2, .. })));
+        assert_eq!(skipped.keys().collect::<Vec<_>>(), ["A", "B"]);
+        assert_eq!(skipped["B"], b"TGG".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_records_keep_input_order() -> Result<()> {
+        let input = TempPath::new("order-in.fasta");
+        let output = TempPath::new("order-out.fasta");
+        let contents = ">zeta\nAAA\n>alpha\nCCC\n>mu\nGGG\n>beta\nTTT\n";
+        std::fs::write(&input, contents)?;
+
+        let sequences = load_fasta(&input)?;
+        write_fasta_sequences(&output, &sequences)?;
+        let written = std::fs::read_to_string(&output)?;
+
+        assert_eq!(
+            sequences.keys().collect::<Vec<_>>(),
+            ["zeta", "alpha", "mu", "beta"]
+        );
+        assert_eq!(written, contents);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_fasta_duplicate_ids() -> Result<()> {
+        let input: &[u8] = b">A\nAAA\n>A_1\nCCC\n>A\nGGG\n>A\nTTT\n";
+        let path = Path::new("duplicates.fasta");
+        let read = |duplicate_ids| {
+            let options = ReadOptions {
+                duplicate_ids,
+                ..ReadOptions::default()
+            };
+            read_fasta_with(input, path, options)
+        };
+
+        assert!(matches!(
+            read(DuplicateIdPolicy::Error),
+            Err(PursError::DuplicateId { id, .. }) if id == "A"
+        ));
+        let first = read(DuplicateIdPolicy::First)?;
+        assert_eq!(first.keys().collect::<Vec<_>>(), ["A", "A_1"]);
+        assert_eq!(first["A"], b"AAA".to_vec());
+        let last = read(DuplicateIdPolicy::Last)?;
+        assert_eq!(last.keys().collect::<Vec<_>>(), ["A", "A_1"]);
+        assert_eq!(last["A"], b"TTT".to_vec());
+        let renamed = read(DuplicateIdPolicy::Rename)?;
+        assert_eq!(
+            renamed.keys().collect::<Vec<_>>(),
+            ["A", "A_1", "A_2", "A_3"]
+        );
+        assert_eq!(renamed["A_2"], b"GGG".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence_stream() -> Result<()> {
+        let fasta = TempPath::new("stream-1.fasta");
+        let fastq = TempPath::new("stream-2.fastq");
+        std::fs::write(&fasta, ">A\natg\n>B v=2\nAAA\n")?;
+        std::fs::write(&fastq, "@C run 7\nCCC\n+\nIII\n@A\nGGG\n+\nIII\n")?;
+
+        let paths = [fasta.to_path_buf(), fastq.to_path_buf()];
+        let mut stream = SequenceStream::new(&paths);
+        let first_three = stream.by_ref().take(3).collect::<Result<Vec<_>, _>>()?;
+        let repeat = stream.next();
+
+        assert_eq!(
+            first_three,
+            [
+                ("A".to_string(), None, b"ATG".to_vec()),
+                ("B".to_string(), Some("v=2".to_string()), b"AAA".to_vec()),
+                ("C".to_string(), Some("run 7".to_string()), b"CCC".to_vec()),
+            ]
+        );
+        assert!(matches!(repeat, Some(Err(PursError::DuplicateId { id, .. })) if id == "A"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_fastas_with_descriptions() -> Result<()> {
+        let first = TempPath::new("desc-1.fasta");
+        let second = TempPath::new("desc-2.fasta");
+        std::fs::write(&first, ">A sample=1 visit=2\nATG\n>B\nAAA\n>C old\nCCC\n")?;
+        std::fs::write(&second, ">C\nGGG\n")?;
+
+        let options = ReadOptions {
+            duplicate_ids: DuplicateIdPolicy::Last,
+            ..ReadOptions::default()
+        };
+        let paths = [first.to_path_buf(), second.to_path_buf()];
+        let (sequences, descriptions) = load_fastas_with(&paths, options)?;
+
+        assert_eq!(sequences.keys().collect::<Vec<_>>(), ["A", "B", "C"]);
+        assert_eq!(descriptions["A"], "sample=1 visit=2");
+        // B never had a description, and C's was replaced by a record without one.
+        assert_eq!(descriptions.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_fastq() -> Result<()> {
+        let path = TempPath::new("load-fastq.fastq");
+        std::fs::write(&path, "@A first\natgtaa\n+\nIIIIII\n@B\nTGG\n+B\n#+@\n")?;
+
+        let (sequences, qualities) = load_fastq(&path)?;
+        assert_eq!(sequences.keys().collect::<Vec<_>>(), ["A", "B"]);
+        assert_eq!(sequences["A"], b"ATGTAA".to_vec());
+        assert_eq!(qualities["B"], b"#+@".to_vec());
+
+        std::fs::write(&path, "@A\nATG\n+\nIII\n@B\nTGG\n+\nII\n")?;
+        assert!(matches!(
+            load_fastq(&path),
+            Err(PursError::FastqParse { record: 2, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_sequences_detects_fastq() -> Result<()> {
+        let path = TempPath::new("detect-fastq.txt");
+        std::fs::write(&path, "@A\nATG\n+\nIII\n")?;
+        let from_fastq = load_sequences(&path)?;
+        std::fs::write(&path, ">A\nATG\n")?;
+        let from_fasta = load_sequences(&path)?;
+
+        assert_eq!(from_fastq, from_fasta);
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzipped_fasta_round_trip() -> Result<()> {
+        let path = TempPath::new("gzip.fasta.gz");
+        let sequences: FastaRecords = velcro::map_iter!(
+            "B".to_string(): b"TTT".to_vec(),
+            "A".to_string(): b"ATG".to_vec(),
+        ).collect();
+
+        write_fasta_sequences(&path, &sequences)?;
+        let compressed = std::fs::read(&path)?;
+        let loaded = load_sequences(&path)?;
+
+        assert_eq!(compressed[..2], [0x1f, 0x8b]);
+        assert_eq!(loaded, sequences);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_fasta_from_reader() -> Result<()> {
+        let input: &[u8] = b">B\nttt\n>A\nATG\n";
+        let sequences = read_fasta(input, Path::new("-"))?;
+
+        assert_eq!(sequences.keys().collect::<Vec<_>>(), ["B", "A"]);
+        assert_eq!(sequences["B"], b"TTT".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_wrapped() -> Result<()> {
+        let mut output = Vec::new();
+        write_wrapped(&mut output, b"MKVLAAGIV", 4)?;
+        write_wrapped(&mut output, b"MKVL", 4)?;
+        write_wrapped(&mut output, b"MKVLAAGIV", 0)?;
+
+        assert_eq!(output, b"MKVL\nAAGI\nV\nMKVL\nMKVLAAGIV\n".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_complement() {
+        let seq = b"ACGT-RYKMBVDHSWN-acgtry";
+
+        assert_eq!(reverse_complement(seq), b"ryacgt-NWSDHBVKMRY-ACGT".to_vec());
+        assert_eq!(reverse_complement(&reverse_complement(seq)), seq.to_vec());
+    }
+
+    #[test]
+    fn test_load_fasta_missing_file() {
+        let path = PathBuf::from("this/file/does/not/exist.fasta");
+
+        assert!(matches!(load_fasta(&path), Err(PursError::FileNotFound { .. })));
+    }
+}
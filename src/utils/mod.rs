@@ -1,3 +1,9 @@
+pub mod checksum;
 pub mod codon_tables;
 pub mod fasta_utils;
+pub mod output;
+pub mod progress;
+pub mod summary;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub mod translate;
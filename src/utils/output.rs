@@ -0,0 +1,143 @@
+use anyhow::{bail, Context, Result};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Stdout, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static NO_CLOBBER: AtomicBool = AtomicBool::new(false);
+
+/// Make every subsequent `create_output_file` fail if its path already exists, instead of
+/// truncating it. Set once from the global `--no-clobber` flag.
+pub fn set_no_clobber(no_clobber: bool) {
+    NO_CLOBBER.store(no_clobber, Ordering::Relaxed);
+}
+
+/// Whether a path is "-", which stands for stdin (as an input) or stdout (as an output) rather
+/// than a file.
+pub fn is_stdio<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref() == Path::new("-")
+}
+
+/// Whether a path ends in .gz, so it should be read or written gzip-compressed.
+pub fn is_gzipped<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// An output opened by `create_output_file`. Call `finish` once everything is written: a gzip
+/// stream needs its trailer written, and any error doing so would be lost if it were only dropped.
+#[derive(Debug)]
+pub enum OutputFile {
+    Stdout(Stdout),
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+impl OutputFile {
+    /// Flush the output and, if it is gzip-compressed, write the end of the stream.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            OutputFile::Stdout(mut stdout) => stdout.flush()?,
+            OutputFile::Plain(mut file) => file.flush()?,
+            OutputFile::Gzip(mut encoder) => encoder.try_finish()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Stdout(stdout) => stdout.write(buf),
+            OutputFile::Plain(file) => file.write(buf),
+            OutputFile::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Stdout(stdout) => stdout.flush(),
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Flush a CSV or TSV writer and finish its output file, as in `OutputFile::finish`.
+pub fn finish_csv(writer: csv::Writer<OutputFile>) -> Result<()> {
+    writer.into_inner().map_err(|e| e.into_error())?.finish()
+}
+
+/// Create an output file, truncating any existing file unless `--no-clobber` was given. The
+/// existence check and creation are a single operation, so a file can't appear in between. A path
+/// of "-" writes to stdout instead, and a path ending in .gz is gzip-compressed.
+pub fn create_output_file<P: AsRef<Path>>(path: P) -> Result<OutputFile> {
+    create_output_file_with(path.as_ref(), NO_CLOBBER.load(Ordering::Relaxed))
+}
+
+fn create_output_file_with(path: &Path, no_clobber: bool) -> Result<OutputFile> {
+    if is_stdio(path) {
+        return Ok(OutputFile::Stdout(std::io::stdout()));
+    }
+    let file = if no_clobber {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                bail!("The output file {:?} already exists (--no-clobber).", path)
+            }
+            result => result.with_context(|| format!("Could not create output file {:?}", path))?,
+        }
+    } else {
+        File::create(path).with_context(|| format!("Could not create output file {:?}", path))?
+    };
+    if is_gzipped(path) {
+        let encoder = GzEncoder::new(file, Compression::default());
+        Ok(OutputFile::Gzip(encoder))
+    } else {
+        Ok(OutputFile::Plain(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TempPath;
+    use std::io::Read;
+
+    #[test]
+    fn test_no_clobber() -> Result<()> {
+        let path = TempPath::new("no-clobber");
+        create_output_file_with(&path, false)?;
+
+        let refused = create_output_file_with(&path, true);
+        let overwritten = create_output_file_with(&path, false);
+
+        assert!(refused.is_err());
+        assert!(overwritten.is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_finish_gzip() -> Result<()> {
+        let path = TempPath::new("finish.tsv.gz");
+        let mut output = create_output_file(&path)?;
+        output.write_all(b"seq_name\tlength\nA\t3\n")?;
+        output.finish()?;
+
+        let mut decompressed = String::new();
+        let mut decoder = flate2::read::GzDecoder::new(File::open(&path)?);
+        decoder.read_to_string(&mut decompressed)?;
+
+        assert_eq!(decompressed, "seq_name\tlength\nA\t3\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_stdio() {
+        assert!(is_stdio("-"));
+        assert!(!is_stdio("-.fasta"));
+        assert!(!is_stdio("out/-/seqs.fasta"));
+    }
+}
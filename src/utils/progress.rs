@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static PROGRESS_INTERVAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Log a progress line every `interval` records in the long-running tools, or never if it is 0.
+/// Set once from the global `--progress-interval` option.
+pub fn set_progress_interval(interval: usize) {
+    PROGRESS_INTERVAL.store(interval, Ordering::Relaxed);
+}
+
+/// Counts the records a tool has processed and logs "Processed N/total records." at the interval
+/// set by `--progress-interval` (and once more after the last record, if the total is known). Plain log lines are used
+/// so the progress shows up in redirected logs, not just on a terminal.
+pub struct Progress {
+    processed: usize,
+    total: Option<usize>,
+    interval: usize,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            processed: 0,
+            total: Some(total),
+            interval: PROGRESS_INTERVAL.load(Ordering::Relaxed),
+        }
+    }
+
+    /// For records that are read as they are processed, so the total isn't known in advance.
+    pub fn unknown_total() -> Self {
+        Self {
+            total: None,
+            ..Self::new(0)
+        }
+    }
+
+    /// Record that one more record has been processed, logging the progress if it is due.
+    pub fn tick(&mut self) {
+        self.processed += 1;
+        if self.is_due() {
+            match self.total {
+                Some(total) => log::info!("Processed {}/{} records.", self.processed, total),
+                None => log::info!("Processed {} records.", self.processed),
+            }
+        }
+    }
+
+    fn is_due(&self) -> bool {
+        self.interval > 0
+            && (self.processed.is_multiple_of(self.interval) || Some(self.processed) == self.total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_is_due() {
+        let mut progress = Progress {
+            processed: 0,
+            total: Some(5),
+            interval: 2,
+        };
+
+        let due: Vec<bool> = (0..5)
+            .map(|_| {
+                progress.tick();
+                progress.is_due()
+            })
+            .collect();
+        assert_eq!(due, vec![false, true, false, true, true]);
+
+        let mut disabled = Progress {
+            processed: 0,
+            total: Some(5),
+            interval: 0,
+        };
+        disabled.tick();
+        assert!(!disabled.is_due());
+    }
+}
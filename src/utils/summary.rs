@@ -0,0 +1,90 @@
+use crate::utils::output::create_output_file;
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// The version of the `--summary-json` schema. Bump this whenever a field is renamed, removed or
+/// changes meaning; adding a new count does not need a bump.
+pub const SUMMARY_SCHEMA_VERSION: u32 = 1;
+
+/// A machine-readable summary of a single tool run, written with `--summary-json`. Every tool
+/// records the files it read and wrote and the number of records it processed, so the summaries
+/// from each step of a pipeline share one schema.
+pub struct RunSummary {
+    tool: String,
+    inputs: Vec<PathBuf>,
+    outputs: Vec<PathBuf>,
+    counts: BTreeMap<String, usize>,
+    started: Instant,
+}
+
+impl RunSummary {
+    pub fn new(tool: &str) -> Self {
+        Self {
+            tool: tool.to_string(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            counts: BTreeMap::new(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn input(&mut self, path: &Path) {
+        self.inputs.push(path.to_path_buf());
+    }
+
+    pub fn output(&mut self, path: &Path) {
+        self.outputs.push(path.to_path_buf());
+    }
+
+    /// Set a named count, e.g. "read", "written" or "rejected".
+    pub fn count(&mut self, name: &str, value: usize) {
+        self.counts.insert(name.to_string(), value);
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "schema_version": SUMMARY_SCHEMA_VERSION,
+            "tool": self.tool,
+            "version": env!("CARGO_PKG_VERSION"),
+            "inputs": self.inputs,
+            "outputs": self.outputs,
+            "counts": self.counts,
+            "duration_seconds": self.started.elapsed().as_secs_f64(),
+        })
+    }
+
+    pub fn write(&self, output_file: &PathBuf) -> Result<()> {
+        let summary = serde_json::to_string_pretty(&self.to_json())?;
+        let mut output = create_output_file(output_file)?;
+        output
+            .write_all(summary.as_bytes())
+            .with_context(|| format!("Failed to write the run summary to {:?}", output_file))?;
+        output.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_schema() {
+        let mut summary = RunSummary::new("collapse");
+        summary.input(Path::new("in.fasta"));
+        summary.output(Path::new("out.fasta"));
+        summary.count("read", 10);
+        summary.count("written", 4);
+
+        let value = summary.to_json();
+        assert_eq!(value["schema_version"], SUMMARY_SCHEMA_VERSION);
+        assert_eq!(value["tool"], "collapse");
+        assert_eq!(value["inputs"], json!(["in.fasta"]));
+        assert_eq!(value["outputs"], json!(["out.fasta"]));
+        assert_eq!(value["counts"], json!({"read": 10, "written": 4}));
+        assert!(value["duration_seconds"].as_f64().unwrap() >= 0.0);
+    }
+}
@@ -0,0 +1,44 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A path in the system temp directory, unique to this test process.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("purs-{}-{}", std::process::id(), name))
+}
+
+/// A temporary file that is removed when dropped, so it doesn't outlive a failed assertion.
+pub(crate) struct TempPath(PathBuf);
+
+impl TempPath {
+    /// `name` is kept as the end of the file name, so it can carry an extension like `.fastq`.
+    pub(crate) fn new(name: &str) -> Self {
+        Self(temp_path(name))
+    }
+}
+
+impl From<PathBuf> for TempPath {
+    fn from(path: PathBuf) -> Self {
+        Self(path)
+    }
+}
+
+impl Deref for TempPath {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // The test may never have created the file, so a failure here isn't worth reporting.
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
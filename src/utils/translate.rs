@@ -1,14 +1,34 @@
 use crate::utils::codon_tables::{
     AMBIGUOUS_CODON_AND_AA_TABLE, AMBIGUOUS_CODON_TABLE, AMBIGUOUS_NT_LOOKUP, CODON_TABLE,
-    GAP_CHAR, STOP_CODONS,
+    DEFAULT_STOP_CHAR, GAP_CHAR, GeneticCode, STOP_CODONS,
 };
-use anyhow::Result;
+use crate::utils::fasta_utils::reverse_complement;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
 use itertools::Itertools;
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
 
-#[derive(Clone, Copy)]
+/// A user-supplied codon table, mapping each codon to its amino acid (or `DEFAULT_STOP_CHAR` for a
+/// stop). See `load_codon_table`.
+pub type CustomCodonTable = HashMap<[u8; 3], u8>;
+
+/// Which stop codons to translate as the amino acids they are recoded to in some proteins.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum StopRecoding {
+    /// TGA as selenocysteine
+    Sec,
+    /// TAG as pyrrolysine
+    Pyl,
+    /// TGA as selenocysteine and TAG as pyrrolysine
+    Both,
+}
+
+#[derive(Clone)]
 pub struct TranslationOptions {
     pub unknown_aa: u8,
     pub stop_aa: u8,
@@ -19,6 +39,41 @@ pub struct TranslationOptions {
     pub strip_gaps: bool,
     pub ignore_gap_codons: bool,
     pub drop_incomplete_codons: bool,
+    /// Emit a gap for a trailing incomplete codon. Takes precedence over `drop_incomplete_codons`
+    /// and `incomplete_aa`.
+    pub incomplete_as_gap: bool,
+    /// The first codon (1-based, inclusive, counted from the reading frame) to translate.
+    pub codon_start: Option<usize>,
+    /// The last codon (1-based, inclusive, counted from the reading frame) to translate.
+    pub codon_end: Option<usize>,
+    /// Heuristically skip 1 or 2 bases where the reading frame appears to be broken by an
+    /// insertion. See `find_frame_recovery`.
+    pub recover_frame: bool,
+    /// Return an error, rather than `unknown_aa`, for a codon that can't be translated.
+    pub strict_translation: bool,
+    /// Return an error if `strip_gaps` removes gaps and leaves a length that isn't a multiple of
+    /// 3, since the gaps can't have been codon-aligned. See `stripped_length_residual`.
+    pub strict_frame: bool,
+    /// The NCBI genetic code to translate with.
+    pub genetic_code: GeneticCode,
+    /// A custom codon table to translate with instead of `genetic_code`. Codons containing
+    /// ambiguity codes are resolved by expanding them through this table.
+    pub custom_codon_table: Option<Arc<CustomCodonTable>>,
+    /// Translate the reverse complement of the sequence. The reading frame and codon range are
+    /// counted from the start of the reverse complement.
+    pub reverse_complement: bool,
+    /// Resolve a codon with ambiguity codes that isn't in the built-in ambiguous tables by
+    /// enumerating every codon it could stand for: if they all code for the same amino acid (or
+    /// are all stops), that is the translation. Needs `allow_ambiguities`.
+    pub exhaustive_ambiguities: bool,
+    /// Translate TGA as `sec_aa` and/or TAG as `pyl_aa` wherever the genetic code makes them
+    /// stops. Every such codon is recoded, not only those in a recoding context (see
+    /// `recoded_stop`).
+    pub recode_stops: Option<StopRecoding>,
+    /// The character for selenocysteine, with `recode_stops`.
+    pub sec_aa: u8,
+    /// The character for pyrrolysine, with `recode_stops`.
+    pub pyl_aa: u8,
 }
 
 impl Default for TranslationOptions {
@@ -33,6 +88,19 @@ impl Default for TranslationOptions {
             strip_gaps: false,
             ignore_gap_codons: false,
             drop_incomplete_codons: true,
+            incomplete_as_gap: false,
+            codon_start: None,
+            codon_end: None,
+            recover_frame: false,
+            strict_translation: false,
+            strict_frame: false,
+            genetic_code: GeneticCode::Standard,
+            custom_codon_table: None,
+            reverse_complement: false,
+            exhaustive_ambiguities: false,
+            recode_stops: None,
+            sec_aa: b'U',
+            pyl_aa: b'O',
         }
     }
 }
@@ -53,9 +121,33 @@ impl fmt::Display for TranslationOptions {
         write!(f, "ignore_gap_codons: {:?}\n\t", self.ignore_gap_codons)?;
         write!(
             f,
-            "drop_incomplete_codons: {:?}\n",
+            "drop_incomplete_codons: {:?}\n\t",
             self.drop_incomplete_codons
         )?;
+        write!(f, "incomplete_as_gap: {:?}\n\t", self.incomplete_as_gap)?;
+        write!(f, "codon_start: {:?}\n\t", self.codon_start)?;
+        write!(f, "codon_end: {:?}\n\t", self.codon_end)?;
+        write!(f, "recover_frame: {:?}\n\t", self.recover_frame)?;
+        write!(f, "strict_translation: {:?}\n\t", self.strict_translation)?;
+        write!(f, "strict_frame: {:?}\n\t", self.strict_frame)?;
+        write!(f, "genetic_code: {}\n\t", self.genetic_code)?;
+        write!(
+            f,
+            "custom_codon_table: {}\n\t",
+            self.custom_codon_table
+                .as_ref()
+                .map(|table| format!("{} codons", table.len()))
+                .unwrap_or_else(|| "none".to_string())
+        )?;
+        write!(f, "reverse_complement: {:?}\n\t", self.reverse_complement)?;
+        write!(
+            f,
+            "exhaustive_ambiguities: {:?}\n\t",
+            self.exhaustive_ambiguities
+        )?;
+        write!(f, "recode_stops: {:?}\n\t", self.recode_stops)?;
+        write!(f, "sec_aa_char: {:?}\n\t", self.sec_aa as char)?;
+        writeln!(f, "pyl_aa_char: {:?}", self.pyl_aa as char)?;
         write!(f, "}}")
     }
 }
@@ -72,8 +164,147 @@ pub fn find_ambiguity_code(nts: &Vec<&u8>) -> Option<&'static [u8; 1]> {
     None
 }
 
+/// Slice a sequence (already offset to its reading frame) down to the codons from `codon_start` to
+/// `codon_end` (1-based, inclusive). Bounds outside the sequence are clamped with a warning.
+fn restrict_to_codon_range(
+    seq: Vec<u8>,
+    codon_start: Option<usize>,
+    codon_end: Option<usize>,
+) -> Vec<u8> {
+    let num_codons = seq.len().div_ceil(3);
+
+    let mut start = codon_start.unwrap_or(1);
+    if start < 1 {
+        log::warn!("The codon start {} is out of range, clamping to 1.", start);
+        start = 1;
+    }
+    let mut end = codon_end.unwrap_or(num_codons);
+    if end > num_codons {
+        log::warn!(
+            "The codon end {} is beyond the last codon of the sequence, clamping to {}.",
+            end,
+            num_codons
+        );
+        end = num_codons;
+    }
+
+    if start > end {
+        log::warn!(
+            "The codon range {}..={} is empty for a sequence with {} codons.",
+            start,
+            end,
+            num_codons
+        );
+        return Vec::new();
+    }
+
+    let from_idx = (start - 1) * 3;
+    let to_idx = (end * 3).min(seq.len());
+    seq[from_idx..to_idx].to_vec()
+}
+
+/// The strand of a sequence that is translated: the sequence itself, or with `reverse_complement`,
+/// its reverse complement. Ambiguity codes are complemented (R to Y, etc.) and gaps stay gaps.
+fn oriented_sequence<'a>(dna_seq: &'a [u8], options: &TranslationOptions) -> Cow<'a, [u8]> {
+    if options.reverse_complement {
+        Cow::Owned(reverse_complement(dna_seq))
+    } else {
+        Cow::Borrowed(dna_seq)
+    }
+}
+
+/// With `strip_gaps`, the number of bases left over after the last complete codon once the gaps
+/// have been removed, if the sequence had gaps and the stripped length isn't a multiple of 3. Gaps
+/// that weren't codon-aligned shift the reading frame of everything after them.
+pub fn stripped_length_residual(dna_seq: &[u8], options: &TranslationOptions) -> Option<usize> {
+    let dna_seq = oriented_sequence(dna_seq, options);
+    let seq = dna_seq.get(options.reading_frame..).unwrap_or_default();
+    let num_gaps = seq.iter().filter(|&&nt| nt == GAP_CHAR).count();
+    let residual = (seq.len() - num_gaps) % 3;
+
+    (options.strip_gaps && num_gaps > 0 && residual != 0).then_some(residual)
+}
+
 pub fn translate(dna_seq: &[u8], options: &TranslationOptions) -> Result<Vec<u8>> {
-    let mut new_seq = dna_seq[options.reading_frame..].to_vec();
+    let (amino_acids, _) = translate_with_report(dna_seq, options)?;
+    Ok(amino_acids)
+}
+
+/// The codons in a translation that a caller may want to gate on. Each is the 1-based position of
+/// the residue in the protein, as in the `--codon-output` TSV.
+#[derive(Debug, Default, PartialEq)]
+pub struct TranslationReport {
+    /// Codons that couldn't be translated and were written as `unknown_aa`.
+    pub unknown_codons: Vec<usize>,
+    /// Partial gap codons, and bases skipped by `recover_frame`, written as `frameshift_aa`.
+    pub frameshift_codons: Vec<usize>,
+    pub stop_codons: Vec<usize>,
+}
+
+/// What a residue of a translation was made from, for `TranslationReport`.
+#[derive(Clone, Copy)]
+enum ResidueSource {
+    Codon(CodonResolution),
+    Incomplete,
+    FrameRecovery,
+}
+
+/// Translate a sequence as in `translate`, also reporting where it had unknown, frameshift and
+/// stop codons instead of only logging them.
+pub fn translate_with_report(
+    dna_seq: &[u8],
+    options: &TranslationOptions,
+) -> Result<(Vec<u8>, TranslationReport)> {
+    let mut amino_acids = Vec::with_capacity(dna_seq.len() / 3);
+    let mut report = TranslationReport::default();
+    translate_each_codon(dna_seq, options, |amino_acid, _, source| {
+        amino_acids.push(amino_acid);
+        let position = amino_acids.len();
+        match source {
+            ResidueSource::Codon(CodonResolution::Unknown) => report.unknown_codons.push(position),
+            ResidueSource::Codon(CodonResolution::PartialGap) | ResidueSource::FrameRecovery => {
+                report.frameshift_codons.push(position)
+            }
+            ResidueSource::Codon(CodonResolution::Stop) => report.stop_codons.push(position),
+            ResidueSource::Codon(_) | ResidueSource::Incomplete => {}
+        }
+    })?;
+    Ok((amino_acids, report))
+}
+
+/// Translate a sequence as in `translate`, also returning the bases each residue was translated
+/// from: the codon, a trailing incomplete codon, or the bases skipped to recover the frame.
+pub fn translate_with_codons(
+    dna_seq: &[u8],
+    options: &TranslationOptions,
+) -> Result<Vec<(u8, Vec<u8>)>> {
+    let mut residues = Vec::with_capacity(dna_seq.len() / 3);
+    translate_each_codon(dna_seq, options, |amino_acid, codon, _| {
+        residues.push((amino_acid, codon.to_vec()))
+    })?;
+    Ok(residues)
+}
+
+/// Translate a sequence, passing each residue to `on_residue` along with the bases it was
+/// translated from and what they were.
+fn translate_each_codon(
+    dna_seq: &[u8],
+    options: &TranslationOptions,
+    mut on_residue: impl FnMut(u8, &[u8], ResidueSource),
+) -> Result<()> {
+    if options.strict_frame
+        && let Some(residual) = stripped_length_residual(dna_seq, options)
+    {
+        bail!(
+            "Stripping gaps left {} base(s) after the last complete codon, so the gaps were not codon-aligned.",
+            residual
+        );
+    }
+
+    let mut new_seq = oriented_sequence(dna_seq, options)
+        .get(options.reading_frame..)
+        .unwrap_or_default()
+        .to_vec();
     if options.strip_gaps {
         new_seq = new_seq
             .iter()
@@ -82,67 +313,512 @@ pub fn translate(dna_seq: &[u8], options: &TranslationOptions) -> Result<Vec<u8>
             .collect();
     }
 
-    let mut amino_acids = Vec::with_capacity(new_seq.len() / 3);
-    for codon in new_seq.chunks(3) {
+    if options.codon_start.is_some() || options.codon_end.is_some() {
+        new_seq = restrict_to_codon_range(new_seq, options.codon_start, options.codon_end);
+    }
+
+    let mut pos = 0;
+    while pos < new_seq.len() {
+        let codon = &new_seq[pos..(pos + 3).min(new_seq.len())];
         // If the codon is not a multiple of 3, we will always want to replace it with an incomplete amino acid, so we don't need to
         // check anything else.
 
         if codon.len() != 3 {
-            if !options.drop_incomplete_codons {
+            if options.incomplete_as_gap {
+                on_residue(GAP_CHAR, codon, ResidueSource::Incomplete);
+            } else if !options.drop_incomplete_codons {
                 log::debug!(
                     "The codon {:?} had a length of {} so we're adding a {:?}",
                     String::from_utf8(codon.to_vec())?,
                     codon.len(),
                     options.incomplete_aa as char
                 );
-                amino_acids.push(options.incomplete_aa);
+                on_residue(options.incomplete_aa, codon, ResidueSource::Incomplete);
             }
+            break;
+        }
+        let (amino_acid, resolution) = translate_codon(codon, options)
+            .with_context(|| format!("Failed to translate the codon at position {}", pos + 1))?;
+
+        if options.recover_frame
+            && is_frame_break(amino_acid, options)
+            && let Some(skip) = find_frame_recovery(&new_seq[pos..], options)
+        {
+            log::debug!(
+                "Skipping {} base(s) at position {} to recover the reading frame",
+                skip,
+                pos
+            );
+            on_residue(
+                options.frameshift_aa,
+                &new_seq[pos..pos + skip],
+                ResidueSource::FrameRecovery,
+            );
+            pos += skip;
             continue;
         }
-        let nt_triplet: [u8; 3] = codon
-            .try_into()
-            .expect("The codon should always be a triplet vector since we've checked for it.");
 
-        if !options.strip_gaps {
-            let num_gaps = nt_triplet.iter().filter(|char| **char == GAP_CHAR).count();
-            if (num_gaps == 1) | (num_gaps == 2) {
-                amino_acids.push(options.frameshift_aa as u8);
+        if !(options.ignore_gap_codons & (amino_acid == GAP_CHAR)) {
+            on_residue(amino_acid, codon, ResidueSource::Codon(resolution));
+        }
+        pos += 3;
+    }
+
+    Ok(())
+}
+
+/// What a single complete codon translates to, before the options pick the character to emit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CodonResolution {
+    /// A sense codon of the genetic code.
+    AminoAcid(u8),
+    /// A codon containing IUPAC ambiguity codes whose possible codons all translate to the same
+    /// amino acid, or to one of the ambiguous amino acids B (D/N) and Z (E/Q).
+    Ambiguous(u8),
+    Stop,
+    /// A codon made up entirely of gaps.
+    Gap,
+    /// A codon with one or two gaps, which breaks the reading frame.
+    PartialGap,
+    /// A codon that can't be translated.
+    Unknown,
+}
+
+/// The ambiguous amino acid codes used in `AMBIGUOUS_CODON_AND_AA_TABLE`, and the amino acids
+/// each stands for.
+const AMBIGUOUS_AA_CODES: [(u8, &[u8]); 2] = [(b'B', b"DN"), (b'Z', b"EQ")];
+
+/// Every codon that a codon containing IUPAC ambiguity codes could stand for.
+fn expand_ambiguous_codon(codon: &[u8; 3]) -> Vec<[u8; 3]> {
+    codon
+        .iter()
+        .map(|nt| match AMBIGUOUS_NT_LOOKUP.get(&[*nt]) {
+            Some(bases) => bases.iter().map(|base| base[0]).sorted().collect(),
+            None => vec![*nt],
+        })
+        .multi_cartesian_product()
+        .map(|codon| [codon[0], codon[1], codon[2]])
+        .collect()
+}
+
+/// Build the map from every codon in the built-in tables to its resolution in `genetic_code`,
+/// checking that the tables are consistent: each of the 64 standard codons is either a sense codon
+/// or a stop, no codon appears in more than one table, and every ambiguous codon only stands for
+/// codons of the amino acid it is mapped to.
+///
+/// The ambiguous tables are written for the standard code, so for any other code an ambiguous
+/// codon that no longer holds (e.g. ATH, once ATA codes for M) is left out rather than an error.
+pub(crate) fn build_codon_map(
+    genetic_code: GeneticCode,
+) -> Result<HashMap<[u8; 3], CodonResolution>> {
+    let mut codon_map = HashMap::new();
+    let insert = |codon_map: &mut HashMap<[u8; 3], CodonResolution>,
+                  codon: [u8; 3],
+                  resolution: CodonResolution|
+     -> Result<()> {
+        if let Some(existing) = codon_map.insert(codon, resolution) {
+            bail!(
+                "The codon {:?} is in more than one codon table ({:?} and {:?}).",
+                String::from_utf8_lossy(&codon),
+                existing,
+                resolution
+            );
+        }
+        Ok(())
+    };
+
+    let changes = genetic_code.codon_changes();
+    let resolve_standard = |codon: &[u8; 3], standard: CodonResolution| match changes.get(codon) {
+        Some([DEFAULT_STOP_CHAR]) => CodonResolution::Stop,
+        Some(amino_acid) => CodonResolution::AminoAcid(amino_acid[0]),
+        None => standard,
+    };
+
+    for (codon, amino_acid) in CODON_TABLE.entries() {
+        let resolution = if **codon == [GAP_CHAR; 3] {
+            CodonResolution::Gap
+        } else {
+            resolve_standard(codon, CodonResolution::AminoAcid(amino_acid[0]))
+        };
+        insert(&mut codon_map, **codon, resolution)?;
+    }
+    for codon in STOP_CODONS.iter() {
+        insert(
+            &mut codon_map,
+            **codon,
+            resolve_standard(codon, CodonResolution::Stop),
+        )?;
+    }
+
+    let standard_codons = [b"ACGT"; 3]
+        .iter()
+        .map(|bases| bases.iter().copied())
+        .multi_cartesian_product();
+    for codon in standard_codons {
+        if !codon_map.contains_key(&[codon[0], codon[1], codon[2]]) {
+            bail!(
+                "The codon {:?} is missing from the codon table.",
+                String::from_utf8_lossy(&codon)
+            );
+        }
+    }
+
+    let ambiguous_entries = AMBIGUOUS_CODON_TABLE
+        .entries()
+        .chain(AMBIGUOUS_CODON_AND_AA_TABLE.entries());
+    let mut ambiguous_codons = Vec::new();
+    'ambiguous: for (codon, amino_acid) in ambiguous_entries {
+        let amino_acid = amino_acid[0];
+        let allowed: &[u8] = AMBIGUOUS_AA_CODES
+            .iter()
+            .find(|(code, _)| *code == amino_acid)
+            .map_or(std::slice::from_ref(&amino_acid), |(_, members)| members);
+        let mut coded = HashSet::new();
+        for expanded in expand_ambiguous_codon(codon) {
+            match codon_map.get(&expanded) {
+                Some(CodonResolution::AminoAcid(aa)) if allowed.contains(aa) => {
+                    coded.insert(*aa);
+                }
+                _ if genetic_code != GeneticCode::Standard => continue 'ambiguous,
+                other => bail!(
+                    "The ambiguous codon {:?} is mapped to {:?}, but {:?} translates to {:?}.",
+                    String::from_utf8_lossy(*codon),
+                    amino_acid as char,
+                    String::from_utf8_lossy(&expanded),
+                    other
+                ),
+            }
+        }
+        if coded.len() != allowed.len() {
+            if genetic_code != GeneticCode::Standard {
                 continue;
             }
+            bail!(
+                "The ambiguous codon {:?} does not cover every amino acid that {:?} stands for.",
+                String::from_utf8_lossy(*codon),
+                amino_acid as char
+            );
         }
-        let amino_acid;
+        ambiguous_codons.push((**codon, CodonResolution::Ambiguous(amino_acid)));
+    }
+    for (codon, resolution) in ambiguous_codons {
+        insert(&mut codon_map, codon, resolution)?;
+    }
 
-        if CODON_TABLE.contains_key(&nt_triplet) {
-            amino_acid = &CODON_TABLE[&nt_triplet][0];
-        } else if options.allow_ambiguities && AMBIGUOUS_CODON_TABLE.contains_key(&nt_triplet) {
-            amino_acid = &AMBIGUOUS_CODON_TABLE[&nt_triplet][0];
-        } else if options.allow_ambiguities
-            && AMBIGUOUS_CODON_AND_AA_TABLE.contains_key(&nt_triplet)
-        {
-            amino_acid = &AMBIGUOUS_CODON_AND_AA_TABLE[&nt_triplet][0];
-        } else if STOP_CODONS.contains(&nt_triplet) {
-            amino_acid = &options.stop_aa;
-        } else {
+    Ok(codon_map)
+}
+
+static CODON_MAPS: LazyLock<HashMap<GeneticCode, HashMap<[u8; 3], CodonResolution>>> =
+    LazyLock::new(|| {
+        GeneticCode::ALL
+            .into_iter()
+            .map(|genetic_code| {
+                let codon_map =
+                    build_codon_map(genetic_code).expect("The built-in codon tables are invalid.");
+                (genetic_code, codon_map)
+            })
+            .collect()
+    });
+
+/// Check the built-in codon tables for every genetic code, so a broken table is reported at
+/// startup rather than by the first translation.
+pub fn validate_codon_tables() -> Result<()> {
+    for genetic_code in GeneticCode::ALL {
+        build_codon_map(genetic_code).with_context(|| {
+            format!(
+                "The built-in codon tables for genetic code {} are invalid.",
+                genetic_code
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Load a custom codon table from a file with a row per codon: the codon and the amino acid it
+/// codes for (`*` for a stop), separated by whitespace. Blank lines and lines starting with `#`
+/// are skipped. Every codon must be 3 of A, C, G and T, and every amino acid a single ASCII
+/// character; errors name the offending line.
+pub fn load_codon_table(path: &PathBuf) -> Result<CustomCodonTable> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read the codon table {:?}", path))?;
+
+    let mut codon_table = CustomCodonTable::new();
+    for (idx, line) in contents.lines().enumerate() {
+        let line_number = idx + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((codon, amino_acid)) = line.split_whitespace().collect_tuple() else {
+            bail!(
+                "Line {} of {:?} should have two columns (codon and amino acid): {:?}",
+                line_number,
+                path,
+                line
+            );
+        };
+
+        let codon = codon.to_ascii_uppercase();
+        let codon: [u8; 3] = match <[u8; 3]>::try_from(codon.as_bytes()) {
+            Ok(codon) if codon.iter().all(|nt| b"ACGT".contains(nt)) => codon,
+            _ => bail!(
+                "Line {} of {:?}: {:?} is not a codon of 3 A/C/G/T bases.",
+                line_number,
+                path,
+                codon
+            ),
+        };
+        let amino_acid = match amino_acid.as_bytes() {
+            [amino_acid] if amino_acid.is_ascii_graphic() => *amino_acid,
+            _ => bail!(
+                "Line {} of {:?}: {:?} is not a single amino acid character.",
+                line_number,
+                path,
+                amino_acid
+            ),
+        };
+
+        if codon_table.insert(codon, amino_acid).is_some() {
+            bail!(
+                "Line {} of {:?}: the codon {:?} is listed more than once.",
+                line_number,
+                path,
+                String::from_utf8_lossy(&codon)
+            );
+        }
+    }
+
+    if codon_table.is_empty() {
+        bail!("The codon table {:?} has no codons.", path);
+    }
+    if codon_table.len() < 64 {
+        log::warn!(
+            "The codon table {:?} only has {} of the 64 codons; the rest will be translated as unknown.",
+            path,
+            codon_table.len()
+        );
+    }
+
+    Ok(codon_table)
+}
+
+/// Resolve a codon containing ambiguity codes by enumerating every codon it could stand for and
+/// resolving each with `resolve`. It is ambiguous for an amino acid only if every one codes for that
+/// amino acid, and a stop only if every one is a stop.
+fn resolve_by_expansion(
+    codon: &[u8; 3],
+    resolve: impl Fn(&[u8; 3]) -> CodonResolution,
+) -> CodonResolution {
+    let expanded = expand_ambiguous_codon(codon);
+    if expanded.len() < 2 {
+        return CodonResolution::Unknown;
+    }
+
+    let resolutions: HashSet<CodonResolution> = expanded.iter().map(resolve).collect();
+    match resolutions.into_iter().exactly_one() {
+        Ok(CodonResolution::AminoAcid(amino_acid)) => CodonResolution::Ambiguous(amino_acid),
+        Ok(CodonResolution::Stop) => CodonResolution::Stop,
+        _ => CodonResolution::Unknown,
+    }
+}
+
+/// Resolve a codon with a custom codon table. A codon containing ambiguity codes is resolved by
+/// `resolve_by_expansion`, so only if every codon it could stand for is in the table.
+fn resolve_custom_codon(codon: &[u8; 3], codon_table: &CustomCodonTable) -> CodonResolution {
+    if *codon == [GAP_CHAR; 3] {
+        return CodonResolution::Gap;
+    }
+
+    let resolve = |codon: &[u8; 3]| match codon_table.get(codon) {
+        Some(&DEFAULT_STOP_CHAR) => CodonResolution::Stop,
+        Some(amino_acid) => CodonResolution::AminoAcid(*amino_acid),
+        None => CodonResolution::Unknown,
+    };
+    match resolve(codon) {
+        CodonResolution::Unknown => resolve_by_expansion(codon, resolve),
+        resolution => resolution,
+    }
+}
+
+/// Whether a codon is a stop in `genetic_code`. Stops differ between codes, e.g. AGA and AGG are
+/// stops in the vertebrate mitochondrial code, where TGA codes for W.
+pub fn is_stop_codon(codon: &[u8; 3], genetic_code: GeneticCode) -> bool {
+    CODON_MAPS[&genetic_code].get(codon) == Some(&CodonResolution::Stop)
+}
+
+/// Resolve a single complete codon, taking into account whether ambiguous codons are allowed and
+/// whether gaps are being stripped.
+pub fn resolve_codon(codon: &[u8; 3], options: &TranslationOptions) -> CodonResolution {
+    if !options.strip_gaps {
+        let num_gaps = codon.iter().filter(|nt| **nt == GAP_CHAR).count();
+        if (num_gaps == 1) | (num_gaps == 2) {
+            return CodonResolution::PartialGap;
+        }
+    }
+
+    let resolution = match &options.custom_codon_table {
+        Some(codon_table) => resolve_custom_codon(codon, codon_table),
+        None => {
+            let codon_map = &CODON_MAPS[&options.genetic_code];
+            let resolve = |codon: &[u8; 3]| {
+                codon_map
+                    .get(codon)
+                    .copied()
+                    .unwrap_or(CodonResolution::Unknown)
+            };
+            match resolve(codon) {
+                CodonResolution::Unknown if options.exhaustive_ambiguities => {
+                    resolve_by_expansion(codon, resolve)
+                }
+                resolution => resolution,
+            }
+        }
+    };
+    match resolution {
+        CodonResolution::Ambiguous(_) if !options.allow_ambiguities => CodonResolution::Unknown,
+        CodonResolution::Stop => match recoded_stop(codon, options) {
+            Some(amino_acid) => CodonResolution::AminoAcid(amino_acid),
+            None => CodonResolution::Stop,
+        },
+        resolution => resolution,
+    }
+}
+
+/// The amino acid a stop codon is recoded to with `recode_stops`, if any. Selenocysteine and
+/// pyrrolysine are only inserted at a UGA or UAG with the right downstream signal (a SECIS or
+/// PYLIS element), which this doesn't look for: every TGA or TAG is recoded, so a real stop is
+/// read through too. Only use it for sequences known to be recoded.
+fn recoded_stop(codon: &[u8; 3], options: &TranslationOptions) -> Option<u8> {
+    match (options.recode_stops?, codon) {
+        (StopRecoding::Sec | StopRecoding::Both, b"TGA") => Some(options.sec_aa),
+        (StopRecoding::Pyl | StopRecoding::Both, b"TAG") => Some(options.pyl_aa),
+        _ => None,
+    }
+}
+
+/// Translate a single complete codon, returning the character to emit and how the codon was
+/// resolved. Fails if the codon can't be translated and `strict_translation` is set.
+fn translate_codon(codon: &[u8], options: &TranslationOptions) -> Result<(u8, CodonResolution)> {
+    let nt_triplet: [u8; 3] = codon
+        .try_into()
+        .expect("The codon should always be a triplet vector since we've checked for it.");
+
+    let resolution = resolve_codon(&nt_triplet, options);
+    let amino_acid = match resolution {
+        CodonResolution::AminoAcid(amino_acid) | CodonResolution::Ambiguous(amino_acid) => {
+            amino_acid
+        }
+        CodonResolution::Stop => options.stop_aa,
+        CodonResolution::Gap => GAP_CHAR,
+        CodonResolution::PartialGap => options.frameshift_aa,
+        CodonResolution::Unknown if options.strict_translation => {
+            bail!(
+                "The codon {:?} cannot be translated unambiguously.",
+                String::from_utf8_lossy(&nt_triplet)
+            );
+        }
+        CodonResolution::Unknown => {
             log::debug!(
                 "Could not find a suitable character for the codon {:?}",
                 String::from_utf8(nt_triplet.to_vec())
             );
-            amino_acid = &options.unknown_aa;
+            options.unknown_aa
         }
+    };
 
-        if options.ignore_gap_codons & (amino_acid.eq(&GAP_CHAR)) {
-            continue;
-        } else {
-            amino_acids.push(amino_acid.clone());
+    Ok((amino_acid, resolution))
+}
+
+/// The number of codons inspected on either side of a frame shift by `find_frame_recovery`.
+const FRAME_RECOVERY_WINDOW: usize = 5;
+
+fn is_frame_break(amino_acid: u8, options: &TranslationOptions) -> bool {
+    amino_acid == options.stop_aa
+        || amino_acid == options.unknown_aa
+        || amino_acid == options.frameshift_aa
+}
+
+/// Given a sequence starting at a stop, unknown or frameshift codon, decide whether the frame has
+/// been broken by an insertion and, if so, how many bases (1 or 2) to skip to restore it.
+///
+/// This is a greedy heuristic: the frame is only considered broken if there are at least two
+/// such codons in the next `FRAME_RECOVERY_WINDOW` codons, so a lone premature stop or ambiguous
+/// codon is left alone, and the smallest skip whose window translates without any is taken.
+fn find_frame_recovery(seq: &[u8], options: &TranslationOptions) -> Option<usize> {
+    let count_breaks = |seq: &[u8]| {
+        seq.chunks_exact(3)
+            .take(FRAME_RECOVERY_WINDOW)
+            // An untranslatable codon under strict translation is as broken as an unknown one.
+            .filter(|codon| {
+                translate_codon(codon, options).map_or(true, |(aa, _)| is_frame_break(aa, options))
+            })
+            .count()
+    };
+
+    if count_breaks(seq) < 2 {
+        return None;
+    }
+
+    (1..=2).find(|&skip| seq.len() >= skip + 3 && count_breaks(&seq[skip..]) == 0)
+}
+
+/// The translation of a sequence in a single reading frame, on either the forward strand or the
+/// reverse complement.
+pub struct FrameTranslation {
+    pub reading_frame: usize,
+    pub reverse: bool,
+    pub protein: Vec<u8>,
+}
+
+/// Translate a sequence in all six reading frames: the three forward frames followed by the three
+/// frames of its reverse complement. The `reading_frame` and `reverse_complement` in `options` are
+/// ignored.
+pub fn translate_six_frames(
+    dna_seq: &[u8],
+    options: &TranslationOptions,
+) -> Result<Vec<FrameTranslation>> {
+    let mut translations = Vec::with_capacity(6);
+
+    for reverse in [false, true] {
+        for reading_frame in 0..3 {
+            let protein = translate(
+                dna_seq,
+                &TranslationOptions {
+                    reading_frame,
+                    reverse_complement: reverse,
+                    ..options.clone()
+                },
+            )?;
+            translations.push(FrameTranslation {
+                reading_frame,
+                reverse,
+                protein,
+            });
         }
     }
 
-    Ok(amino_acids)
+    Ok(translations)
+}
+
+pub fn count_stops(protein: &[u8], options: &TranslationOptions) -> usize {
+    protein.iter().filter(|&&aa| aa == options.stop_aa).count()
+}
+
+/// Count the stops in a protein, ignoring a stop at the very end, which is expected in a complete
+/// coding sequence.
+pub fn count_internal_stops(protein: &[u8], options: &TranslationOptions) -> usize {
+    let internal = match protein.last() {
+        Some(&aa) if aa == options.stop_aa => &protein[..protein.len() - 1],
+        _ => protein,
+    };
+    count_stops(internal, options)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::test_utils::TempPath;
 
 
     #[test]
@@ -205,8 +881,8 @@ mod tests {
 
     #[test]
     fn test_ambiguity() -> Result<()> {
-        let test_cases = vec!["ATGTTACTNTAA", "NNNATGGGG", "ATGRAY---GTA"];
-        let expected_outputs = vec!["MLL*", "?MG", "MB-V"];
+        let test_cases = ["ATGTTACTNTAA", "NNNATGGGG", "ATGRAY---GTA"];
+        let expected_outputs = ["MLL*", "?MG", "MB-V"];
 
         for (idx, test_case) in test_cases.iter().enumerate() {
             let expected_translation = expected_outputs[idx].as_bytes();
@@ -238,5 +914,436 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_short_sequence_in_later_frame() -> Result<()> {
+        let translation = translate(b"A", &TranslationOptions {
+            reading_frame: 2,
+            ..TranslationOptions::default()
+        })?;
+
+        assert!(translation.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_translate_six_frames() -> Result<()> {
+        // The reverse complement of this sequence is ATGTTATAA.
+        let translations = translate_six_frames(b"TTATAACAT", &TranslationOptions::default())?;
+
+        assert_eq!(translations.len(), 6);
+        assert!(!translations[0].reverse);
+        assert_eq!(translations[0].protein, b"L*H".to_vec());
+        assert!(translations[3].reverse);
+        assert_eq!(translations[3].reading_frame, 0);
+        assert_eq!(translations[3].protein, b"ML*".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_complement() -> Result<()> {
+        // The reverse complement is ATG---GAYTAA: R complements to Y, and gaps stay gaps.
+        let options = TranslationOptions {
+            reverse_complement: true,
+            ..Default::default()
+        };
+        assert_eq!(translate(b"TTARTC---CAT", &options)?, b"M-D*");
+
+        // The reading frame is counted from the start of the reverse complement.
+        let translation = translate(b"TTARTC---CATG", &TranslationOptions {
+            reading_frame: 1,
+            ..options
+        })?;
+        assert_eq!(translation, b"M-D*");
+        Ok(())
+    }
+
+    #[test]
+    fn test_codon_range() -> Result<()> {
+        let translation = translate(b"ATGTTACTGTGGTAA", &TranslationOptions {
+            codon_start: Some(2),
+            codon_end: Some(4),
+            ..TranslationOptions::default()
+        })?;
+
+        assert_eq!("LLW".to_owned(), String::from_utf8(translation)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_codon_range_respects_reading_frame() -> Result<()> {
+        let translation = translate(b"GATGTTACTG", &TranslationOptions {
+            reading_frame: 1,
+            codon_start: Some(2),
+            ..TranslationOptions::default()
+        })?;
+
+        assert_eq!("LL".to_owned(), String::from_utf8(translation)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_codon_range_clamps() -> Result<()> {
+        let translation = translate(b"ATGTTACTG", &TranslationOptions {
+            codon_start: Some(0),
+            codon_end: Some(10),
+            ..TranslationOptions::default()
+        })?;
+        assert_eq!("MLL".to_owned(), String::from_utf8(translation)?);
+
+        let empty = translate(b"ATGTTACTG", &TranslationOptions {
+            codon_start: Some(5),
+            ..TranslationOptions::default()
+        })?;
+        assert!(empty.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_incomplete_as_gap() -> Result<()> {
+        let dropped = translate(b"ATGTTACT", &TranslationOptions::default())?;
+        assert_eq!("ML".to_owned(), String::from_utf8(dropped)?);
+
+        // incomplete_as_gap wins over both drop_incomplete_codons and incomplete_aa.
+        for drop_incomplete_codons in [true, false] {
+            let gapped = translate(b"ATGTTACT", &TranslationOptions {
+                incomplete_as_gap: true,
+                drop_incomplete_codons,
+                ..TranslationOptions::default()
+            })?;
+            assert_eq!("ML-".to_owned(), String::from_utf8(gapped)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_frame_after_insertion() -> Result<()> {
+        // ATG AAT AAG GCC TGG (MNKAW) with a T inserted after the start codon.
+        let dna_seq = b"ATGTAATAAGGCCTGG";
+
+        let naive = translate(dna_seq, &TranslationOptions::default())?;
+        assert_eq!("M**GL".to_owned(), String::from_utf8(naive)?);
+
+        let recovered = translate(dna_seq, &TranslationOptions {
+            recover_frame: true,
+            ..TranslationOptions::default()
+        })?;
+        assert_eq!("MXNKAW".to_owned(), String::from_utf8(recovered)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_frame_keeps_lone_stop() -> Result<()> {
+        let translation = translate(b"ATGTAAGCCTGGAAAGGGTAA", &TranslationOptions {
+            recover_frame: true,
+            ..TranslationOptions::default()
+        })?;
+
+        assert_eq!("M*AWKG*".to_owned(), String::from_utf8(translation)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_translate_with_codons() -> Result<()> {
+        let residues = translate_with_codons(b"ATG---TTACT", &TranslationOptions {
+            ignore_gap_codons: true,
+            drop_incomplete_codons: false,
+            ..TranslationOptions::default()
+        })?;
+
+        assert_eq!(
+            residues,
+            vec![
+                (b'M', b"ATG".to_vec()),
+                (b'L', b"TTA".to_vec()),
+                (b'?', b"CT".to_vec()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_translate_with_report() -> Result<()> {
+        let (translation, report) =
+            translate_with_report(b"ATGNNNTAAAT-GCCTGAAC", &TranslationOptions {
+                drop_incomplete_codons: false,
+                ..Default::default()
+            })?;
+
+        assert_eq!(translation, b"MX*XA*?");
+        assert_eq!(report, TranslationReport {
+            unknown_codons: vec![2],
+            frameshift_codons: vec![4],
+            stop_codons: vec![3, 6],
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_stripped_length_residual() {
+        let options = TranslationOptions {
+            strip_gaps: true,
+            ..TranslationOptions::default()
+        };
+
+        // A stray gap: 8 bases are left, 2 more than the last complete codon.
+        assert_eq!(stripped_length_residual(b"ATGT-TATA", &options), Some(2));
+        // Codon-aligned gaps, no gaps at all, or gaps that aren't stripped are all fine.
+        assert_eq!(stripped_length_residual(b"ATG---TAA", &options), None);
+        assert_eq!(stripped_length_residual(b"ATGTTATA", &options), None);
+        assert_eq!(
+            stripped_length_residual(b"ATGT-TATA", &TranslationOptions::default()),
+            None
+        );
+
+        let strict_options = TranslationOptions {
+            strict_frame: true,
+            ..options
+        };
+        assert!(translate(b"ATGT-TATA", &strict_options).is_err());
+        assert!(translate(b"ATG---TAA", &strict_options).is_ok());
+    }
+
+    #[test]
+    fn test_strict_translation() -> Result<()> {
+        let lenient = translate(b"ATGNNNTAA", &TranslationOptions::default())?;
+        assert_eq!("MX*".to_owned(), String::from_utf8(lenient)?);
+
+        let strict_options = TranslationOptions {
+            strict_translation: true,
+            ..TranslationOptions::default()
+        };
+        let error = translate(b"ATGNNNTAA", &strict_options).unwrap_err();
+        assert!(format!("{:#}", error).contains("NNN"));
+
+        // Ambiguous codons that still translate to a single amino acid are fine.
+        let strict = translate(b"ATGCTN", &strict_options)?;
+        assert_eq!("ML".to_owned(), String::from_utf8(strict)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_codon_tables_are_valid() -> Result<()> {
+        let codon_map = build_codon_map(GeneticCode::Standard)?;
+
+        // 61 sense codons, 3 stops, the gap codon and the ambiguous codons.
+        assert_eq!(
+            codon_map.len(),
+            65 + AMBIGUOUS_CODON_TABLE.len() + AMBIGUOUS_CODON_AND_AA_TABLE.len()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_codon() {
+        let options = TranslationOptions::default();
+
+        assert_eq!(
+            resolve_codon(b"ATG", &options),
+            CodonResolution::AminoAcid(b'M')
+        );
+        assert_eq!(resolve_codon(b"TAG", &options), CodonResolution::Stop);
+        assert_eq!(resolve_codon(b"---", &options), CodonResolution::Gap);
+        assert_eq!(resolve_codon(b"AT-", &options), CodonResolution::PartialGap);
+        assert_eq!(
+            resolve_codon(b"RAY", &options),
+            CodonResolution::Ambiguous(b'B')
+        );
+        assert_eq!(resolve_codon(b"NNN", &options), CodonResolution::Unknown);
+
+        let no_ambiguities = TranslationOptions {
+            allow_ambiguities: false,
+            ..options
+        };
+        assert_eq!(
+            resolve_codon(b"CTN", &no_ambiguities),
+            CodonResolution::Unknown
+        );
+    }
+
+    #[test]
+    fn test_mitochondrial_genetic_code() -> Result<()> {
+        // The start of human MT-CO1, where TGA codes for W.
+        let dna_seq = b"ATGTTCGCCGACCGTTGACTATTCTCTACAAACCACAAAGACATTGGTACCCTGTAC";
+
+        let standard = translate(dna_seq, &TranslationOptions::default())?;
+        assert_eq!(standard, b"MFADR*LFSTNHKDIGTLY");
+
+        for genetic_code in [
+            GeneticCode::VertebrateMitochondrial,
+            GeneticCode::MoldMitochondrial,
+            GeneticCode::InvertebrateMitochondrial,
+        ] {
+            let mitochondrial = translate(
+                dna_seq,
+                &TranslationOptions {
+                    genetic_code,
+                    ..Default::default()
+                },
+            )?;
+            assert_eq!(mitochondrial, b"MFADRWLFSTNHKDIGTLY");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_stops_follow_genetic_code() -> Result<()> {
+        let dna_seq = b"ATGAGATGAAGG";
+        let options = TranslationOptions {
+            genetic_code: GeneticCode::VertebrateMitochondrial,
+            ..Default::default()
+        };
+
+        assert_eq!(translate(dna_seq, &options)?, b"M*W*");
+        assert_eq!(translate(dna_seq, &TranslationOptions::default())?, b"MR*R");
+        assert!(is_stop_codon(b"AGA", GeneticCode::VertebrateMitochondrial));
+        assert!(!is_stop_codon(b"TGA", GeneticCode::VertebrateMitochondrial));
+        assert!(is_stop_codon(b"TGA", GeneticCode::Bacterial));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_codon_table() -> Result<()> {
+        let path = TempPath::new("codon-table.tsv");
+
+        std::fs::write(&path, "# codon\tamino_acid\nATG\tM\n\ngcc\tA\nGCT A\nTAA\t*\n")?;
+        let codon_table = load_codon_table(&path)?;
+        assert_eq!(codon_table.len(), 4);
+        assert_eq!(codon_table[b"GCC"], b'A');
+
+        let errors = [
+            ("ATG\tM\nATGA\tM\n", "Line 2"),
+            ("ATG\tM\nATN\tM\n", "Line 2"),
+            ("ATG\tMet\n", "Line 1"),
+            ("ATG\n", "Line 1"),
+            ("ATG\tM\n#\nATG\tM\n", "Line 3"),
+        ];
+        for (contents, line) in errors {
+            std::fs::write(&path, contents)?;
+            let error = load_codon_table(&path).unwrap_err().to_string();
+            assert!(error.starts_with(line), "{:?} should start with {:?}", error, line);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_codon_table() -> Result<()> {
+        let codon_table = CustomCodonTable::from([
+            (*b"ATG", b'M'),
+            (*b"TGA", b'W'),
+            (*b"GCA", b'A'),
+            (*b"GCG", b'A'),
+            (*b"TAA", b'*'),
+        ]);
+        let options = TranslationOptions {
+            custom_codon_table: Some(Arc::new(codon_table)),
+            ..Default::default()
+        };
+
+        // GCR only stands for codons in the table, but GCN also stands for GCC and GCT.
+        assert_eq!(translate(b"ATGTGA---GCRGCNTAA", &options)?, b"MW-AX*");
+        assert_eq!(
+            resolve_codon(b"GCR", &options),
+            CodonResolution::Ambiguous(b'A')
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_codon_table_unknown_codon() -> Result<()> {
+        let codon_table = CustomCodonTable::from([(*b"ATG", b'M'), (*b"TAA", b'*')]);
+        let options = TranslationOptions {
+            unknown_aa: b'#',
+            custom_codon_table: Some(Arc::new(codon_table)),
+            ..Default::default()
+        };
+
+        // TTT (F in every genetic code) isn't in the table, so it is unknown rather than F.
+        assert_eq!(translate(b"ATGTTTTAA", &options)?, b"M#*");
+        assert_eq!(resolve_codon(b"TTT", &options), CodonResolution::Unknown);
+
+        let strict = TranslationOptions {
+            strict_translation: true,
+            ..options
+        };
+        assert!(translate(b"ATGTTTTAA", &strict).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_exhaustive_ambiguities() -> Result<()> {
+        let exhaustive = TranslationOptions {
+            exhaustive_ambiguities: true,
+            ..Default::default()
+        };
+
+        // ATY (ATC/ATT) and CTK (CTG/CTT) aren't in the ambiguous tables, TAR is TAA/TAG, and
+        // YTN could be L or F.
+        assert_eq!(
+            translate(b"ATYCTKTARYTN", &TranslationOptions::default())?,
+            b"XXXX"
+        );
+        assert_eq!(translate(b"ATYCTKTARYTN", &exhaustive)?, b"IL*X");
+        assert_eq!(
+            resolve_codon(b"ATY", &exhaustive),
+            CodonResolution::Ambiguous(b'I')
+        );
+
+        // The expansion follows the genetic code: ATA codes for M in table 2.
+        let mitochondrial = TranslationOptions {
+            genetic_code: GeneticCode::VertebrateMitochondrial,
+            ..exhaustive.clone()
+        };
+        assert_eq!(translate(b"ATR", &mitochondrial)?, b"M");
+
+        let no_ambiguities = TranslationOptions {
+            allow_ambiguities: false,
+            ..exhaustive
+        };
+        assert_eq!(translate(b"ATY", &no_ambiguities)?, b"X");
+        Ok(())
+    }
+
+    #[test]
+    fn test_recode_stops() -> Result<()> {
+        let sec = TranslationOptions {
+            recode_stops: Some(StopRecoding::Sec),
+            ..Default::default()
+        };
+
+        let default = TranslationOptions::default();
+        assert_eq!(translate(b"ATGTGATAGTAA", &default)?, b"M***");
+        assert_eq!(translate(b"ATGTGATAGTAA", &sec)?, b"MU**");
+        let both = TranslationOptions {
+            recode_stops: Some(StopRecoding::Both),
+            pyl_aa: b'o',
+            ..sec.clone()
+        };
+        assert_eq!(translate(b"ATGTGATAGTAA", &both)?, b"MUo*");
+
+        // Only codons that are stops in the genetic code are recoded: TGA is W in table 2.
+        let mitochondrial = TranslationOptions {
+            genetic_code: GeneticCode::VertebrateMitochondrial,
+            ..sec
+        };
+        assert_eq!(translate(b"TGA", &mitochondrial)?, b"W");
+        Ok(())
+    }
+
+    #[test]
+    fn test_genetic_code_ids() {
+        for genetic_code in GeneticCode::ALL {
+            assert_eq!(
+                GeneticCode::try_from(genetic_code.id()).ok(),
+                Some(genetic_code)
+            );
+        }
+        assert_eq!(
+            "2".parse::<GeneticCode>().ok(),
+            Some(GeneticCode::VertebrateMitochondrial)
+        );
+        assert!(GeneticCode::try_from(3).is_err());
+        assert!("mito".parse::<GeneticCode>().is_err());
+    }
+
     // TODO: Add more tests lol
 }